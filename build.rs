@@ -0,0 +1,18 @@
+use std::path::Path;
+
+// `audit a11y` injects the axe-core in vendor/axe-core (fetched with
+// script/update-axe) so it works offline and under a strict CSP. Without
+// it the daemon falls back to loading axe-core from jsDelivr.
+fn main() {
+    let vendored = Path::new("vendor/axe-core/axe.min.js");
+    println!("cargo:rerun-if-changed={}", vendored.display());
+    let source = std::fs::read_to_string(vendored).unwrap_or_else(|_| {
+        println!(
+            "cargo:warning={} is missing, so audit a11y will load axe-core from jsDelivr. Run script/update-axe to bundle it.",
+            vendored.display()
+        );
+        String::new()
+    });
+    let out = Path::new(&std::env::var("OUT_DIR").unwrap()).join("axe.min.js");
+    std::fs::write(out, source).unwrap();
+}
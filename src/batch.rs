@@ -0,0 +1,128 @@
+/// Variable values for `split_expanding`, by name.
+pub type Lookup<'a> = &'a dyn Fn(&str) -> Option<String>;
+
+/// Splits a command line into words the way a shell would, minus
+/// expansions: whitespace separates words, single quotes keep everything
+/// literally, double quotes and backslashes escape. A `#` at the start of a
+/// word comments out the rest of the line.
+pub fn split(line: &str) -> Result<Vec<String>, String> {
+    words(line, None)
+}
+
+/// Like `split`, but also expands `$NAME` and `${NAME}` with `lookup`,
+/// except in single quotes. `\$` is a literal `$`.
+pub fn split_expanding(line: &str, lookup: Lookup) -> Result<Vec<String>, String> {
+    words(line, Some(lookup))
+}
+
+fn words(line: &str, lookup: Option<Lookup>) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {
+                words.extend(word.take());
+            }
+            '#' if word.is_none() => break,
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated ' quote".to_string()),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\' | '$' | '`')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err("Unterminated \" quote".to_string()),
+                        },
+                        Some('$') if lookup.is_some() => {
+                            word.push_str(&expand(&mut chars, lookup.unwrap())?)
+                        }
+                        Some(c) => word.push(c),
+                        None => return Err("Unterminated \" quote".to_string()),
+                    }
+                }
+            }
+            '$' if lookup.is_some() => {
+                let value = expand(&mut chars, lookup.unwrap())?;
+                word.get_or_insert_with(String::new).push_str(&value);
+            }
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                if let Some(c) = chars.next() {
+                    word.push(c);
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Ok(words)
+}
+
+/// The value of the variable named right after a `$`. A `$` that isn't
+/// followed by a name stays as it is.
+fn expand(
+    chars: &mut std::iter::Peekable<std::str::Chars>,
+    lookup: Lookup,
+) -> Result<String, String> {
+    let mut name = String::new();
+    if chars.next_if_eq(&'{').is_some() {
+        loop {
+            match chars.next() {
+                Some('}') => break,
+                Some(c) => name.push(c),
+                None => return Err("Unterminated ${".to_string()),
+            }
+        }
+    } else {
+        while let Some(c) = chars.next_if(|&c| c.is_ascii_alphanumeric() || c == '_') {
+            name.push(c);
+        }
+        if name.is_empty() {
+            return Ok("$".to_string());
+        }
+    }
+    lookup(&name).ok_or_else(|| format!("Variable ${} is not set", name))
+}
+
+/// Reads the JSON form of a batch: an array whose items are command lines
+/// or arrays of arguments, like `["open https://example.com", ["text", "h1"]]`.
+pub fn parse_json(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let items: Vec<serde_json::Value> =
+        serde_json::from_str(text).map_err(|e| format!("Invalid JSON batch: {}", e))?;
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| match item {
+            serde_json::Value::String(line) => split(line),
+            serde_json::Value::Array(args) => args
+                .iter()
+                .map(|arg| arg.as_str().map(String::from))
+                .collect::<Option<Vec<String>>>()
+                .ok_or_else(|| {
+                    format!(
+                        "Item {} of the batch has arguments that aren't strings",
+                        i + 1
+                    )
+                }),
+            _ => Err(format!(
+                "Item {} of the batch must be a command line or an array of arguments",
+                i + 1
+            )),
+        })
+        .collect()
+}
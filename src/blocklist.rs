@@ -0,0 +1,149 @@
+use std::collections::HashSet;
+
+/// Analytics, session recording and tracking pixels.
+const TRACKERS: &[&str] = &[
+    "google-analytics.com",
+    "googletagmanager.com",
+    "analytics.google.com",
+    "connect.facebook.net",
+    "pixel.facebook.com",
+    "hotjar.com",
+    "hotjar.io",
+    "segment.io",
+    "segment.com",
+    "mixpanel.com",
+    "amplitude.com",
+    "fullstory.com",
+    "heapanalytics.com",
+    "clarity.ms",
+    "mouseflow.com",
+    "crazyegg.com",
+    "quantserve.com",
+    "scorecardresearch.com",
+    "chartbeat.com",
+    "chartbeat.net",
+    "nr-data.net",
+    "js-agent.newrelic.com",
+    "stats.wp.com",
+    "bat.bing.com",
+    "analytics.tiktok.com",
+    "snap.licdn.com",
+    "px.ads.linkedin.com",
+    "analytics.twitter.com",
+    "static.ads-twitter.com",
+    "ct.pinterest.com",
+    "sc-static.net",
+    "hs-analytics.net",
+    "mc.yandex.ru",
+    "omtrdc.net",
+    "demdex.net",
+    "2o7.net",
+    "everesttech.net",
+    "bounceexchange.com",
+    "kissmetrics.io",
+    "plausible.io",
+];
+
+/// Ad networks and exchanges.
+const ADS: &[&str] = &[
+    "doubleclick.net",
+    "googlesyndication.com",
+    "googleadservices.com",
+    "adservice.google.com",
+    "adnxs.com",
+    "adsrvr.org",
+    "criteo.com",
+    "criteo.net",
+    "taboola.com",
+    "outbrain.com",
+    "amazon-adsystem.com",
+    "pubmatic.com",
+    "rubiconproject.com",
+    "openx.net",
+    "casalemedia.com",
+    "indexww.com",
+    "moatads.com",
+    "adform.net",
+    "smartadserver.com",
+    "yieldmo.com",
+    "media.net",
+    "sharethrough.com",
+    "teads.tv",
+    "33across.com",
+    "revcontent.com",
+    "mgid.com",
+    "adroll.com",
+    "advertising.com",
+    "bidswitch.net",
+    "contextweb.com",
+    "lijit.com",
+    "sovrn.com",
+    "3lift.com",
+    "triplelift.com",
+    "gumgum.com",
+    "spotxchange.com",
+    "serving-sys.com",
+    "flashtalking.com",
+    "zedo.com",
+];
+
+pub const PRESETS: &[&str] = &["trackers", "ads"];
+
+/// The domains of a bundled preset.
+pub fn preset(name: &str) -> Option<&'static [&'static str]> {
+    match name {
+        "trackers" => Some(TRACKERS),
+        "ads" => Some(ADS),
+        _ => None,
+    }
+}
+
+/// Reads the domains from a filter list: one domain per line, hosts-file
+/// lines (`0.0.0.0 example.com`) or adblock domain rules (`||example.com^`).
+/// Comments and any other adblock syntax are skipped.
+pub fn parse(text: &str) -> Vec<String> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', '!', '[']) {
+                return None;
+            }
+            let domain = if let Some(rule) = line.strip_prefix("||") {
+                rule.strip_suffix('^')?
+            } else {
+                let mut parts = line.split_whitespace();
+                let first = parts.next()?;
+                match parts.next() {
+                    Some(host) if matches!(first, "0.0.0.0" | "127.0.0.1" | "::") => host,
+                    Some(_) => return None,
+                    None => first,
+                }
+            };
+            let valid = domain.contains('.')
+                && domain
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+            valid.then(|| domain.to_ascii_lowercase())
+        })
+        .collect()
+}
+
+/// Whether the URL's host is one of `domains` or a subdomain of one.
+pub fn matches(domains: &HashSet<String>, url: &str) -> bool {
+    let Some(rest) = url.split_once("://").map(|(_, rest)| rest) else {
+        return false;
+    };
+    let authority = rest.split(['/', '?', '#']).next().unwrap_or("");
+    let host = authority.rsplit('@').next().unwrap_or("");
+    let host = host.split(':').next().unwrap_or("").to_ascii_lowercase();
+    let mut candidate = host.as_str();
+    loop {
+        if domains.contains(candidate) {
+            return true;
+        }
+        match candidate.split_once('.') {
+            Some((_, parent)) => candidate = parent,
+            None => return false,
+        }
+    }
+}
@@ -0,0 +1,199 @@
+use aes::cipher::{block_padding::Pkcs7, BlockDecryptMut, KeyIvInit};
+use anyhow::{bail, Result};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::path::Path;
+use std::process::Command;
+
+/// Seconds between 1601-01-01, where Chrome's timestamps start, and the
+/// Unix epoch.
+const WINDOWS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// "v10" in hex, the prefix of encrypted values
+const V10: &str = "763130";
+
+#[derive(Deserialize)]
+struct Row {
+    host_key: String,
+    name: String,
+    value: String,
+    /// Hex, since sqlite3 can't print blobs as JSON
+    encrypted: String,
+    path: String,
+    expires_utc: i64,
+    is_secure: i64,
+    is_httponly: i64,
+    samesite: i64,
+}
+
+/// Reads the cookies of a local Chrome profile (e.g. "Default" or
+/// "Profile 1") in `user_data_dir`, decrypting them with the key Chrome keeps
+/// in the OS keychain. Returns cookies for Playwright's `addCookies` and the
+/// number that couldn't be decrypted. With `domain`, only cookies that would
+/// be sent to that domain are read.
+pub fn read(
+    user_data_dir: &Path,
+    profile: &str,
+    domain: Option<&str>,
+) -> Result<(Vec<Value>, usize)> {
+    let profile_dir = user_data_dir.join(profile);
+    let db = ["Network/Cookies", "Cookies"]
+        .iter()
+        .map(|p| profile_dir.join(p))
+        .find(|p| p.is_file());
+    let Some(db) = db else {
+        bail!("No Chrome cookie store in {}", profile_dir.display());
+    };
+    if !on_path("sqlite3") {
+        bail!("Reading Chrome's cookies needs the sqlite3 command, install it first");
+    }
+
+    // Chrome keeps the database locked while it runs, so read a copy.
+    let tmp = tempfile::tempdir()?;
+    let copy = tmp.path().join("Cookies");
+    std::fs::copy(&db, &copy)?;
+    let version: i64 = sqlite_json(&copy, "SELECT value FROM meta WHERE key = 'version'")?
+        .first()
+        .and_then(|r| r["value"].as_str()?.parse().ok())
+        .unwrap_or(0);
+    let rows: Vec<Row> = serde_json::from_value(Value::Array(sqlite_json(
+        &copy,
+        "SELECT host_key, name, value, hex(encrypted_value) AS encrypted, path, \
+         expires_utc, is_secure, is_httponly, samesite FROM cookies",
+    )?))?;
+
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)?
+        .as_secs() as i64;
+    let mut keys = std::collections::HashMap::new();
+    let mut cookies = Vec::new();
+    let mut skipped = 0;
+    for row in rows {
+        // Host-only cookies have no leading dot and skip subdomains.
+        let sent_to = |d: &str| match row.host_key.strip_prefix('.') {
+            Some(host) => d == host || d.ends_with(&row.host_key),
+            None => d == row.host_key,
+        };
+        if domain.is_some_and(|d| !sent_to(d)) {
+            continue;
+        }
+        let expires = match row.expires_utc {
+            0 => -1,
+            us => us / 1_000_000 - WINDOWS_EPOCH_OFFSET,
+        };
+        if expires != -1 && expires < now {
+            continue;
+        }
+        let value = if row.encrypted.is_empty() {
+            Some(row.value)
+        } else {
+            let prefix = row.encrypted.get(..6).unwrap_or("").to_string();
+            if !keys.contains_key(&prefix) {
+                keys.insert(prefix.clone(), encryption_key(&prefix)?);
+            }
+            decrypt(&keys[&prefix], &row.encrypted, version)
+        };
+        let Some(value) = value else {
+            skipped += 1;
+            continue;
+        };
+        let mut cookie = json!({
+            "name": row.name,
+            "value": value,
+            "domain": row.host_key,
+            "path": row.path,
+            "expires": expires,
+            "httpOnly": row.is_httponly != 0,
+            "secure": row.is_secure != 0,
+        });
+        // Browsers reject SameSite=None without Secure, so leave it unset.
+        let same_site = match row.samesite {
+            0 if row.is_secure != 0 => Some("None"),
+            1 => Some("Lax"),
+            2 => Some("Strict"),
+            _ => None,
+        };
+        if let Some(same_site) = same_site {
+            cookie["sameSite"] = json!(same_site);
+        }
+        cookies.push(cookie);
+    }
+    Ok((cookies, skipped))
+}
+
+fn on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+}
+
+fn sqlite_json(db: &Path, query: &str) -> Result<Vec<Value>> {
+    let output = Command::new("sqlite3")
+        .arg("-json")
+        .arg(db)
+        .arg(query)
+        .output()
+        .map_err(|e| anyhow::anyhow!("Cannot run sqlite3: {}", e))?;
+    if !output.status.success() {
+        bail!(
+            "sqlite3 failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    let text = String::from_utf8_lossy(&output.stdout);
+    if text.trim().is_empty() {
+        return Ok(Vec::new());
+    }
+    Ok(serde_json::from_str(&text)?)
+}
+
+/// The AES key for values with the given (hex) prefix, derived from the
+/// password Chrome stores in the macOS Keychain or the Linux secret service.
+/// On Linux, "v10" values always use a fixed password.
+fn encryption_key(prefix: &str) -> Result<[u8; 16]> {
+    let (password, iterations) = if cfg!(target_os = "macos") {
+        let output = Command::new("security")
+            .args(["find-generic-password", "-w", "-s", "Chrome Safe Storage"])
+            .output()?;
+        if !output.status.success() {
+            bail!("Cannot read Chrome's key from the Keychain");
+        }
+        (output.stdout, 1003)
+    } else if prefix == V10 {
+        (b"peanuts".to_vec(), 1)
+    } else {
+        let output = Command::new("secret-tool")
+            .args(["lookup", "application", "chrome"])
+            .output();
+        match output {
+            Ok(o) if o.status.success() && !o.stdout.is_empty() => (o.stdout, 1),
+            _ => bail!(
+                "Cannot read Chrome's key from the secret service (is secret-tool installed?)"
+            ),
+        }
+    };
+    let password = String::from_utf8_lossy(&password).trim().to_string();
+    let mut key = [0u8; 16];
+    pbkdf2::pbkdf2_hmac::<sha1::Sha1>(password.as_bytes(), b"saltysalt", iterations, &mut key);
+    Ok(key)
+}
+
+/// Decrypts a "v10"/"v11" cookie value: AES-128-CBC with an IV of spaces.
+/// From database version 24 on, the plaintext starts with a SHA-256 of the
+/// cookie's domain.
+fn decrypt(key: &[u8; 16], hex: &str, version: i64) -> Option<String> {
+    let bytes: Vec<u8> = (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect::<Option<_>>()?;
+    let mut data = bytes
+        .strip_prefix(b"v10")
+        .or_else(|| bytes.strip_prefix(b"v11"))?
+        .to_vec();
+    let mut plain = cbc::Decryptor::<aes::Aes128>::new(key.into(), &[b' '; 16].into())
+        .decrypt_padded_mut::<Pkcs7>(&mut data)
+        .ok()?;
+    if version >= 24 {
+        plain = plain.get(32..)?;
+    }
+    String::from_utf8(plain.to_vec()).ok()
+}
@@ -1,71 +1,279 @@
+use crate::logs;
 use crate::protocol::{Command, Request, Response};
+use crate::socket;
 use anyhow::{bail, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
 use std::io::BufRead;
 use std::os::unix::process::CommandExt;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::process::{Command as StdCommand, Stdio};
+use std::sync::Arc;
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixStream;
+use tokio::net::unix::{OwnedReadHalf, OwnedWriteHalf};
+use tokio::net::{UnixListener, UnixStream};
+use tokio::sync::oneshot;
 
 const STARTUP_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(30);
 
 pub async fn send_if_running(socket_path: &Path, command: Command) -> Result<Option<Response>> {
     let stream = match UnixStream::connect(socket_path).await {
         Ok(s) => s,
-        Err(_) => return Ok(None),
+        Err(e) => {
+            if is_stale(&e) {
+                socket::remove(socket_path);
+            }
+            return Ok(None);
+        }
     };
-    send_on_stream(stream, command).await.map(Some)
+    Connection::new(stream).send(command).await.map(Some)
 }
 
 pub async fn send(socket_path: &Path, command: Command) -> Result<Response> {
-    let stream = UnixStream::connect(socket_path)
-        .await
-        .map_err(|_| anyhow::anyhow!("No session running. Use 'plwr start' first."))?;
-    send_on_stream(stream, command).await
+    Connection::open(socket_path).await?.send(command).await
+}
+
+/// A connection to the daemon that can carry any number of commands, one
+/// after the other. The daemon serves other connections in between.
+pub struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+}
+
+impl Connection {
+    /// Connects to the session. If its daemon died and left the socket
+    /// behind, a new one is started in its place with the options the
+    /// session was started with.
+    pub async fn open(socket_path: &Path) -> Result<Self> {
+        match UnixStream::connect(socket_path).await {
+            Ok(stream) => return Ok(Self::new(stream)),
+            Err(e) if is_stale(&e) => {}
+            Err(_) => bail!("No session running. Use 'plwr start' first."),
+        }
+        let _lock = socket::lock(socket_path)?;
+        // Another client may have started it while we waited for the lock
+        match UnixStream::connect(socket_path).await {
+            Ok(stream) => return Ok(Self::new(stream)),
+            Err(e) if is_stale(&e) => {}
+            Err(_) => bail!("No session running. Use 'plwr start' first."),
+        }
+        let options = std::fs::read_to_string(socket::options_path(socket_path))
+            .ok()
+            .and_then(|json| serde_json::from_str::<StartOptions>(&json).ok());
+        let Some(options) = options else {
+            socket::remove(socket_path);
+            bail!("No session running. Use 'plwr start' first.");
+        };
+        eprintln!("The session's daemon had died, starting a new one");
+        start_daemon(socket_path, &options)?;
+        Ok(Self::new(UnixStream::connect(socket_path).await?))
+    }
+
+    fn new(stream: UnixStream) -> Self {
+        let (reader, writer) = stream.into_split();
+        Self {
+            reader: BufReader::new(reader),
+            writer,
+        }
+    }
+
+    pub async fn send(&mut self, command: Command) -> Result<Response> {
+        write_request(&mut self.writer, command).await?;
+
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).await? == 0 {
+            bail!("The session closed the connection");
+        }
+        let resp: Response = serde_json::from_str(&line)?;
+        Ok(resp)
+    }
+}
+
+async fn write_request(writer: &mut OwnedWriteHalf, command: Command) -> Result<()> {
+    let mut buf = serde_json::to_vec(&Request { command })?;
+    buf.push(b'\n');
+    writer.write_all(&buf).await?;
+    Ok(())
 }
 
-pub async fn ensure_started(
+/// Serves the commands of `plwr shell`'s children, arriving on `listener`,
+/// over one connection to the session. Children are served side by side:
+/// their commands are sent in turn and the session answers them in order,
+/// so a slow one doesn't keep the others from being sent. If the session
+/// goes away, as with `stop` and `start`, the next command reconnects.
+///
+/// Each `plwr` a child runs still connects to the relay. That takes some
+/// 15µs next to the 5ms it takes to start `plwr`, while one inherited fd
+/// shared by every child would need locking around each command.
+pub async fn relay(
+    listener: UnixListener,
     socket_path: &Path,
-    headed: bool,
-    video: Option<&str>,
-    ignore_cert_errors: bool,
-    cdp: Option<&str>,
+    conn: Option<Connection>,
 ) -> Result<()> {
-    if socket_path.exists() {
-        if UnixStream::connect(socket_path).await.is_ok() {
-            return Ok(());
+    let upstream = Arc::new(tokio::sync::Mutex::new(conn.map(Upstream::new)));
+    loop {
+        let (stream, _) = listener.accept().await?;
+        if !socket::is_own_user(&stream) {
+            continue;
         }
-        std::fs::remove_file(socket_path).ok();
+        tokio::spawn(relay_child(
+            stream,
+            socket_path.to_path_buf(),
+            upstream.clone(),
+        ));
     }
-    start_daemon(socket_path, headed, video, ignore_cert_errors, cdp)
 }
 
-async fn send_on_stream(stream: UnixStream, command: Command) -> Result<Response> {
-    let (reader, mut writer) = stream.into_split();
+/// The relay's connection to the session. A reader task hands each response
+/// to the next one waiting for it.
+struct Upstream {
+    writer: OwnedWriteHalf,
+    /// Who waits for the coming responses, in order; None once the session
+    /// has closed the connection
+    waiting: Arc<std::sync::Mutex<Option<VecDeque<oneshot::Sender<Response>>>>>,
+}
 
-    let req = Request { command };
-    let mut buf = serde_json::to_vec(&req)?;
-    buf.push(b'\n');
-    writer.write_all(&buf).await?;
+impl Upstream {
+    fn new(conn: Connection) -> Self {
+        let waiting = Arc::new(std::sync::Mutex::new(Some(VecDeque::new())));
+        tokio::spawn(read_responses(conn.reader, waiting.clone()));
+        Self {
+            writer: conn.writer,
+            waiting,
+        }
+    }
 
-    let mut reader = BufReader::new(reader);
+    fn is_open(&self) -> bool {
+        self.waiting.lock().unwrap().is_some()
+    }
+}
+
+async fn read_responses(
+    mut reader: BufReader<OwnedReadHalf>,
+    waiting: Arc<std::sync::Mutex<Option<VecDeque<oneshot::Sender<Response>>>>>,
+) {
     let mut line = String::new();
-    reader.read_line(&mut line).await?;
+    while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+        let resp = serde_json::from_str(&line).unwrap_or_else(|e| Response::err(e.to_string()));
+        line.clear();
+        let next = waiting
+            .lock()
+            .unwrap()
+            .as_mut()
+            .and_then(VecDeque::pop_front);
+        if let Some(next) = next {
+            let _ = next.send(resp);
+        }
+    }
+    // Dropping the senders tells whoever is still waiting
+    waiting.lock().unwrap().take();
+}
 
-    let resp: Response = serde_json::from_str(&line)?;
-    Ok(resp)
+async fn relay_child(
+    stream: UnixStream,
+    socket_path: PathBuf,
+    upstream: Arc<tokio::sync::Mutex<Option<Upstream>>>,
+) {
+    let (reader, mut writer) = stream.into_split();
+    let mut reader = BufReader::new(reader);
+    let mut line = String::new();
+    while reader.read_line(&mut line).await.unwrap_or(0) > 0 {
+        let resp = match serde_json::from_str::<Request>(&line) {
+            Ok(req) => forward(&upstream, &socket_path, req.command).await,
+            Err(e) => Response::err(e.to_string()),
+        };
+        line.clear();
+        let Ok(mut buf) = serde_json::to_vec(&resp) else {
+            break;
+        };
+        buf.push(b'\n');
+        if writer.write_all(&buf).await.is_err() {
+            break;
+        }
+    }
 }
 
-fn start_daemon(
+/// Sends a child's command and waits for its response, holding the
+/// connection only while sending.
+async fn forward(
+    upstream: &tokio::sync::Mutex<Option<Upstream>>,
     socket_path: &Path,
-    headed: bool,
-    video: Option<&str>,
-    ignore_cert_errors: bool,
-    cdp: Option<&str>,
-) -> Result<()> {
-    if socket_path.exists() {
-        std::fs::remove_file(socket_path).ok();
+    command: Command,
+) -> Response {
+    let is_stop = matches!(command, Command::Stop);
+    let response = {
+        let mut upstream = upstream.lock().await;
+        if !upstream.as_ref().is_some_and(Upstream::is_open) {
+            match Connection::open(socket_path).await {
+                Ok(conn) => *upstream = Some(Upstream::new(conn)),
+                Err(e) => return Response::err(e.to_string()),
+            }
+        }
+        let conn = upstream.as_mut().unwrap();
+        let (tx, rx) = oneshot::channel();
+        if let Some(waiting) = conn.waiting.lock().unwrap().as_mut() {
+            waiting.push_back(tx);
+        }
+        if let Err(e) = write_request(&mut conn.writer, command).await {
+            *upstream = None;
+            return Response::err(e.to_string());
+        }
+        if is_stop {
+            *upstream = None;
+        }
+        rx
+    };
+    response
+        .await
+        .unwrap_or_else(|_| Response::err("The session closed the connection".to_string()))
+}
+
+/// How `start` launches the browser. The daemon gets these as environment
+/// variables.
+#[derive(Default, Serialize, Deserialize)]
+pub struct StartOptions {
+    pub headed: bool,
+    /// Where to save the video on stop
+    pub video: Option<String>,
+    pub ignore_cert_errors: bool,
+    /// Channel, user data dir or WebSocket URL to connect to over CDP
+    pub cdp: Option<String>,
+    pub touch: bool,
+    pub fake_media: Option<FakeMedia>,
+    /// Absolute path of a persistent user data directory
+    pub profile: Option<String>,
+    /// File to keep the session's state in across restarts
+    pub persist: Option<String>,
+    /// Minutes without commands after which the daemon stops itself
+    pub idle_timeout: Option<f64>,
+}
+
+/// Chromium's fake camera and microphone for `start --fake-media`, playing
+/// these files (absolute paths) instead of a test pattern and a beep.
+#[derive(Serialize, Deserialize)]
+pub struct FakeMedia {
+    pub video: Option<String>,
+    pub audio: Option<String>,
+}
+
+/// Whether connecting failed because nobody listens on the socket, which
+/// means the daemon died without removing it.
+fn is_stale(e: &std::io::Error) -> bool {
+    e.kind() == std::io::ErrorKind::ConnectionRefused
+}
+
+pub async fn ensure_started(socket_path: &Path, options: &StartOptions) -> Result<()> {
+    let _lock = socket::lock(socket_path)?;
+    if socket_path.exists() && UnixStream::connect(socket_path).await.is_ok() {
+        return Ok(());
     }
+    start_daemon(socket_path, options)
+}
+
+/// Starts the session's daemon and remembers its options. Call with the
+/// session locked.
+fn start_daemon(socket_path: &Path, options: &StartOptions) -> Result<()> {
+    socket::remove(socket_path);
 
     let exe = std::env::current_exe()?;
 
@@ -74,10 +282,15 @@ fn start_daemon(
         .and_then(|s| s.to_str())
         .unwrap_or("default");
 
+    // The daemon's stderr, like a panic, ends up in its log too.
+    let stderr = match logs::open_new(&logs::path(session)) {
+        Ok(file) => Stdio::from(file),
+        Err(_) => Stdio::null(),
+    };
     let mut cmd = StdCommand::new(&exe);
     cmd.args(["--session", session, "daemon"])
         .stdout(Stdio::piped())
-        .stderr(Stdio::null())
+        .stderr(stderr)
         .stdin(Stdio::null());
 
     // Safety: setsid() is async-signal-safe and has no preconditions
@@ -88,18 +301,39 @@ fn start_daemon(
         });
     }
 
-    if headed {
+    if options.headed {
         cmd.env("PLAYWRIGHT_HEADED", "1");
     }
-    if let Some(path) = video {
+    if let Some(path) = &options.video {
         cmd.env("PLWR_VIDEO", path);
     }
-    if ignore_cert_errors {
+    if options.ignore_cert_errors {
         cmd.env("PLWR_IGNORE_CERT_ERRORS", "1");
     }
-    if let Some(channel) = cdp {
+    if let Some(channel) = &options.cdp {
         cmd.env("PLWR_CDP", channel);
     }
+    if options.touch {
+        cmd.env("PLWR_TOUCH", "1");
+    }
+    if let Some(dir) = &options.profile {
+        cmd.env("PLWR_PROFILE", dir);
+    }
+    if let Some(path) = &options.persist {
+        cmd.env("PLWR_PERSIST_FILE", path);
+    }
+    if let Some(minutes) = options.idle_timeout {
+        cmd.env("PLWR_IDLE_TIMEOUT", minutes.to_string());
+    }
+    if let Some(media) = &options.fake_media {
+        cmd.env("PLWR_FAKE_MEDIA", "1");
+        if let Some(path) = &media.video {
+            cmd.env("PLWR_FAKE_VIDEO", path);
+        }
+        if let Some(path) = &media.audio {
+            cmd.env("PLWR_FAKE_AUDIO", path);
+        }
+    }
 
     let mut child = cmd
         .spawn()
@@ -120,6 +354,7 @@ fn start_daemon(
 
         if line == "### ready" {
             drop(child);
+            save_options(socket_path, options)?;
             return Ok(());
         }
 
@@ -132,3 +367,19 @@ fn start_daemon(
     let _ = child.wait();
     bail!("Daemon exited unexpectedly");
 }
+
+/// The file is only readable by us: the options can name a profile or a
+/// session file with cookies in it.
+fn save_options(socket_path: &Path, options: &StartOptions) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .mode(0o600)
+        .open(socket::options_path(socket_path))?;
+    file.write_all(serde_json::to_string(options)?.as_bytes())?;
+    Ok(())
+}
@@ -1,18 +1,30 @@
-use crate::protocol::{Command, Request, Response};
+use crate::blocklist;
+use crate::glob;
+use crate::har;
+use crate::logs;
+use crate::protocol::{Command, LoginStep, Request, Response, ScreenshotCompare};
 use crate::pw_ext;
+use crate::socket;
+use crate::suggestions;
+use crate::tree;
 use anyhow::Result;
+use base64::prelude::{Engine, BASE64_STANDARD};
 use playwright_rs::{
-    protocol::click::{KeyboardModifier, MouseButton},
-    protocol::ContinueOptions,
+    protocol::click::{KeyboardModifier, MouseButton, Position},
+    protocol::{
+        ContinueOptions, Download, FetchOptions, FulfillOptions, Route, ScreenshotClip,
+        ScreenshotOptions, ScreenshotType,
+    },
     server::channel_owner::ChannelOwner,
-    BrowserContextOptions, CheckOptions, ClickOptions, FillOptions, HoverOptions, LaunchOptions,
-    Locator, Page, Playwright, RecordVideo, SelectOption, SelectOptions,
+    Browser, BrowserContextOptions, CheckOptions, ClickOptions, FillOptions, HoverOptions,
+    LaunchOptions, Locator, Page, Playwright, RecordVideo, SelectOption, SelectOptions,
 };
 use std::collections::HashMap;
 use std::path::Path;
 use std::sync::{Arc, Mutex};
 use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
-use tokio::net::UnixListener;
+use tokio::net::unix::OwnedWriteHalf;
+use tokio::net::{UnixListener, UnixStream};
 
 const READY_SIGNAL: &str = "### ready";
 const ERROR_PREFIX: &str = "### error ";
@@ -191,23 +203,798 @@ if (!window.__plwr_console) {
 }
 "#;
 
+// Finds the innermost elements whose text (or aria-label, placeholder, alt,
+// title) contains `needle`, and proposes selectors for each, ending with the
+// CSS one from `selectorFor`. Uniqueness is checked afterwards with real
+// locators, since role= and text= aren't CSS.
+const FIND_CANDIDATES_JS: &str = r#"(needle, limit, selectorFor) => {
+    const norm = s => (s || '').replace(/\s+/g, ' ').trim();
+    const lower = needle.toLowerCase();
+    const skip = new Set(['SCRIPT', 'STYLE', 'NOSCRIPT', 'TEMPLATE', 'HEAD', 'TITLE']);
+    const attrText = el => ['aria-label', 'placeholder', 'alt', 'title']
+        .map(a => el.getAttribute(a)).find(v => v && v.toLowerCase().includes(lower));
+    const textMatches = el => norm(el.textContent).toLowerCase().includes(lower);
+
+    const matches = [];
+    for (const el of document.body.querySelectorAll('*')) {
+        if (skip.has(el.tagName) || el.closest('[data-plwr-highlight]')) continue;
+        const byAttr = attrText(el);
+        const byText = textMatches(el)
+            && !Array.from(el.children).some(c => !skip.has(c.tagName) && textMatches(c));
+        if (byAttr || byText) matches.push({ el, label: byAttr || norm(el.textContent) });
+        if (matches.length >= limit) break;
+    }
+
+    const quote = s => JSON.stringify(s);
+    const implicitRole = el => {
+        const tag = el.tagName.toLowerCase();
+        const type = (el.getAttribute('type') || 'text').toLowerCase();
+        if (el.getAttribute('role')) return el.getAttribute('role');
+        if (tag === 'a' && el.hasAttribute('href')) return 'link';
+        if (tag === 'button') return 'button';
+        if (/^h[1-6]$/.test(tag)) return 'heading';
+        if (tag === 'select') return 'combobox';
+        if (tag === 'textarea') return 'textbox';
+        if (tag === 'img' && el.getAttribute('alt')) return 'img';
+        if (tag === 'li') return 'listitem';
+        if (tag === 'input') {
+            if (['button', 'submit', 'reset'].includes(type)) return 'button';
+            if (type === 'checkbox' || type === 'radio') return type;
+            if (['text', 'email', 'search', 'tel', 'url', 'password'].includes(type)) return 'textbox';
+        }
+        return null;
+    };
+
+    return JSON.stringify(matches.map(({ el, label }) => {
+        const selectors = [];
+        if (el.id) selectors.push('#' + CSS.escape(el.id));
+        for (const attr of ['data-testid', 'data-test', 'data-cy']) {
+            const v = el.getAttribute(attr);
+            if (v) selectors.push('[' + attr + '=' + CSS.escape(v) + ']');
+        }
+        const role = implicitRole(el);
+        if (role) selectors.push('role=' + role + '[name=' + quote(label) + ']');
+        if (label.length <= 80) selectors.push('text=' + quote(label));
+        selectors.push(selectorFor(el));
+        return { tag: el.tagName.toLowerCase(), text: label, selectors };
+    }));
+}"#;
+
+// Shortest stable CSS selector for an element: a unique id, then a unique
+// test id, then a parent path that stops as soon as it's unique.
+const UNIQUE_SELECTOR_JS: &str = r#"el => {
+    const unique = sel => {
+        try { return document.querySelectorAll(sel).length === 1; } catch { return false; }
+    };
+    if (el.id && unique('#' + CSS.escape(el.id))) return '#' + CSS.escape(el.id);
+    for (const attr of ['data-testid', 'data-test', 'data-cy']) {
+        const v = el.getAttribute(attr);
+        const sel = v && '[' + attr + '=' + CSS.escape(v) + ']';
+        if (sel && unique(sel)) return sel;
+    }
+    const parts = [];
+    for (let node = el; node && node !== document.documentElement; node = node.parentElement) {
+        if (node !== el && node.id && unique('#' + CSS.escape(node.id))) {
+            parts.unshift('#' + CSS.escape(node.id));
+            break;
+        }
+        let part = node.tagName.toLowerCase();
+        const siblings = node.parentElement ? Array.from(node.parentElement.children) : [];
+        const cls = Array.from(node.classList).find(c => siblings.filter(s => s.classList.contains(c)).length === 1);
+        if (cls) part += '.' + CSS.escape(cls);
+        else if (siblings.filter(s => s.tagName === node.tagName).length > 1)
+            part += ':nth-child(' + (siblings.indexOf(node) + 1) + ')';
+        parts.unshift(part);
+        if (unique(parts.join(' > '))) break;
+    }
+    return parts.join(' > ');
+}"#;
+
+// Outlines the element under the mouse; the next click is swallowed and
+// its element's selector stored for the daemon to poll. Escape cancels.
+const PICK_INSTALL_JS: &str = r#"selectorFor => {
+    if (window.__plwr_pick) return;
+    const box = document.createElement('div');
+    box.setAttribute('data-plwr-highlight', '');
+    Object.assign(box.style, {
+        position: 'fixed',
+        outline: '2px solid #e0f',
+        background: 'rgba(238, 0, 255, 0.15)',
+        zIndex: '2147483647',
+        pointerEvents: 'none',
+        display: 'none',
+    });
+    document.documentElement.appendChild(box);
+    const state = window.__plwr_pick = { picked: null, cancelled: false };
+    const swallow = e => { e.preventDefault(); e.stopImmediatePropagation(); };
+    const move = e => {
+        const r = e.target.getBoundingClientRect();
+        Object.assign(box.style, {
+            display: 'block',
+            left: r.left + 'px',
+            top: r.top + 'px',
+            width: r.width + 'px',
+            height: r.height + 'px',
+        });
+    };
+    const click = e => {
+        swallow(e);
+        state.picked = selectorFor(e.target);
+        state.cleanup();
+    };
+    const key = e => {
+        if (e.key !== 'Escape') return;
+        swallow(e);
+        state.cancelled = true;
+        state.cleanup();
+    };
+    const opts = { capture: true };
+    state.cleanup = () => {
+        box.remove();
+        document.removeEventListener('mousemove', move, opts);
+        document.removeEventListener('mousedown', swallow, opts);
+        document.removeEventListener('mouseup', swallow, opts);
+        document.removeEventListener('click', click, opts);
+        document.removeEventListener('keydown', key, opts);
+    };
+    document.addEventListener('mousemove', move, opts);
+    document.addEventListener('mousedown', swallow, opts);
+    document.addEventListener('mouseup', swallow, opts);
+    document.addEventListener('click', click, opts);
+    document.addEventListener('keydown', key, opts);
+}"#;
+
+const PICK_POLL_JS: &str = r#"() => {
+    const state = window.__plwr_pick;
+    if (!state) return 'missing';
+    if (state.cancelled) { delete window.__plwr_pick; return 'cancelled'; }
+    if (state.picked === null) return 'waiting';
+    delete window.__plwr_pick;
+    return 'picked:' + state.picked;
+}"#;
+
+const PICK_CANCEL_JS: &str = r#"() => {
+    if (window.__plwr_pick) { window.__plwr_pick.cleanup(); delete window.__plwr_pick; }
+}"#;
+
+// Records trusted clicks, typing, Enter presses and <select> changes as login
+// steps. They go to sessionStorage so a same-origin navigation right after a
+// click doesn't lose them before the daemon polls. Escape stops recording.
+const LOGIN_RECORD_INSTALL_JS: &str = r#"selectorFor => {
+    if (window.__plwr_login) return;
+    const KEY = '__plwr_login';
+    const load = () => JSON.parse(sessionStorage.getItem(KEY) || '{"steps":[],"stopped":false}');
+    const update = f => {
+        const rec = load();
+        f(rec);
+        sessionStorage.setItem(KEY, JSON.stringify(rec));
+    };
+    const push = step => update(rec => rec.steps.push(step));
+    const NOT_TEXT = new Set(['button', 'checkbox', 'color', 'file', 'hidden', 'image',
+        'radio', 'range', 'reset', 'submit']);
+    const isText = el => el.tagName === 'TEXTAREA'
+        || (el.tagName === 'INPUT' && !NOT_TEXT.has(el.type));
+    const input = e => {
+        if (e.isTrusted && isText(e.target))
+            push({ action: 'fill', selector: selectorFor(e.target), value: e.target.value });
+    };
+    const change = e => {
+        if (e.isTrusted && e.target.tagName === 'SELECT')
+            push({ action: 'select', selector: selectorFor(e.target), value: e.target.value });
+    };
+    const click = e => {
+        if (!e.isTrusted || !(e.target instanceof Element)) return;
+        const el = e.target.closest('a, button, input, label, select, textarea, summary, '
+            + '[role=button], [role=link], [role=checkbox], [role=tab], [onclick]') || e.target;
+        // Focusing a field is implied by filling it.
+        if (isText(el) || el.tagName === 'SELECT') return;
+        push({ action: 'click', selector: selectorFor(el) });
+    };
+    const key = e => {
+        if (!e.isTrusted) return;
+        if (e.key === 'Escape') {
+            e.preventDefault();
+            e.stopImmediatePropagation();
+            update(rec => { rec.stopped = true; });
+        } else if (e.key === 'Enter' && isText(e.target) && e.target.tagName === 'INPUT') {
+            push({ action: 'press', selector: selectorFor(e.target), key: 'Enter' });
+        }
+    };
+    const opts = { capture: true };
+    window.__plwr_login = {
+        cleanup: () => {
+            document.removeEventListener('input', input, opts);
+            document.removeEventListener('change', change, opts);
+            document.removeEventListener('click', click, opts);
+            document.removeEventListener('keydown', key, opts);
+        },
+    };
+    document.addEventListener('input', input, opts);
+    document.addEventListener('change', change, opts);
+    document.addEventListener('click', click, opts);
+    document.addEventListener('keydown', key, opts);
+}"#;
+
+// Takes the steps recorded so far, and says whether the recorder is still
+// installed in this document.
+const LOGIN_RECORD_POLL_JS: &str = r#"() => {
+    const rec = JSON.parse(sessionStorage.getItem('__plwr_login') || '{"steps":[],"stopped":false}');
+    sessionStorage.removeItem('__plwr_login');
+    return JSON.stringify({ ...rec, installed: !!window.__plwr_login });
+}"#;
+
+const LOGIN_RECORD_STOP_JS: &str = r#"() => {
+    if (window.__plwr_login) { window.__plwr_login.cleanup(); delete window.__plwr_login; }
+    sessionStorage.removeItem('__plwr_login');
+}"#;
+
+// Covers each element with an absolutely positioned box for `screenshot --mask`.
+const MASK_INSTALL_JS: &str = r#"els => {
+    for (const el of els) {
+        const r = el.getBoundingClientRect();
+        const box = document.createElement('div');
+        box.setAttribute('data-plwr-mask', '');
+        box.style.cssText = `position: absolute; left: ${r.left + scrollX}px; top: ${r.top + scrollY}px;
+            width: ${r.width}px; height: ${r.height}px; background: #FF00FF;
+            z-index: 2147483647; pointer-events: none;`;
+        document.documentElement.appendChild(box);
+    }
+    return String(els.length);
+}"#;
+
+// Outlines each element with a red box captioned `text` for
+// `screenshot --annotate`. Numbered when the selector matches several.
+const ANNOTATE_INSTALL_JS: &str = r#"(els, text) => {
+    els.forEach((el, i) => {
+        const r = el.getBoundingClientRect();
+        const box = document.createElement('div');
+        box.setAttribute('data-plwr-annotation', '');
+        box.style.cssText = `position: absolute; left: ${r.left + scrollX}px; top: ${r.top + scrollY}px;
+            width: ${r.width}px; height: ${r.height}px; outline: 2px solid #e11d48;
+            z-index: 2147483647; pointer-events: none;`;
+        const label = document.createElement('span');
+        label.textContent = els.length > 1 ? `${text} (${i + 1})` : text;
+        label.style.cssText = `position: absolute; left: -2px; bottom: 100%; padding: 1px 4px;
+            background: #e11d48; color: #fff; font: 12px/1.4 monospace; white-space: nowrap;`;
+        box.appendChild(label);
+        document.documentElement.appendChild(box);
+    });
+    return String(els.length);
+}"#;
+
+const OVERLAY_REMOVE_JS: &str = r#"() => {
+    document.querySelectorAll('[data-plwr-mask], [data-plwr-annotation]').forEach(box => box.remove());
+}"#;
+
+// Compares two base64-encoded images pixel by pixel. Returns the number of
+// pixels whose largest channel difference exceeds `threshold` (0-1) and, if
+// any, a PNG with those pixels in red over a faded copy of the screenshot.
+const IMAGE_DIFF_JS: &str = r#"async (actual, baseline, threshold) => {
+    const load = async b64 => {
+        const bytes = Uint8Array.from(atob(b64), c => c.charCodeAt(0));
+        const bitmap = await createImageBitmap(new Blob([bytes]));
+        const canvas = document.createElement('canvas');
+        canvas.width = bitmap.width;
+        canvas.height = bitmap.height;
+        const ctx = canvas.getContext('2d');
+        ctx.drawImage(bitmap, 0, 0);
+        return ctx.getImageData(0, 0, bitmap.width, bitmap.height);
+    };
+    const a = await load(actual), b = await load(baseline);
+    if (a.width !== b.width || a.height !== b.height)
+        return JSON.stringify({ size: [a.width, a.height], baseline_size: [b.width, b.height] });
+
+    const canvas = document.createElement('canvas');
+    canvas.width = a.width;
+    canvas.height = a.height;
+    const ctx = canvas.getContext('2d');
+    const out = ctx.createImageData(a.width, a.height);
+    const limit = threshold * 255;
+    let diff = 0;
+    for (let i = 0; i < a.data.length; i += 4) {
+        let max = 0;
+        for (let c = 0; c < 4; c++) max = Math.max(max, Math.abs(a.data[i + c] - b.data[i + c]));
+        if (max > limit) {
+            diff++;
+            out.data.set([255, 0, 0, 255], i);
+        } else {
+            const lum = a.data[i] * 0.299 + a.data[i + 1] * 0.587 + a.data[i + 2] * 0.114;
+            const faded = 255 - (255 - lum) * 0.1;
+            out.data.set([faded, faded, faded, 255], i);
+        }
+    }
+    let png = '';
+    if (diff) {
+        ctx.putImageData(out, 0, 0);
+        const url = canvas.toDataURL('image/png');
+        png = url.slice(url.indexOf(',') + 1);
+    }
+    return JSON.stringify({ size: [a.width, a.height], diff_pixels: diff, diff_png: png });
+}"#;
+
+// Serializes the DOM under an element for `tree` and `snapshot`. Options:
+// depth and maxText limits, an `only` attribute allowlist, and `layout` to
+// record each element's document-relative [x, y, width, height].
+const TREE_JS: &str = r#"(el, { depth, maxText, only, layout }) => {
+    const keep = name => only ? only.includes(name) : !name.startsWith('data-plwr');
+    function walk(el, level) {
+        const node = { tag: el.tagName ? el.tagName.toLowerCase() : '#text' };
+        if (el.id && keep('id')) node.id = el.id;
+        if (el.className && typeof el.className === 'string' && el.className.trim() && keep('class'))
+            node.class = el.className.trim().split(/\s+/);
+        if (el.attributes) {
+            const attrs = {};
+            for (const a of el.attributes) {
+                if (a.name !== 'id' && a.name !== 'class' && keep(a.name))
+                    attrs[a.name] = a.value;
+            }
+            if (Object.keys(attrs).length > 0) node.attrs = attrs;
+        }
+        let text = Array.from(el.childNodes)
+            .filter(n => n.nodeType === 3)
+            .map(n => n.textContent.trim())
+            .filter(t => t)
+            .join(' ');
+        if (maxText !== null && text.length > maxText) text = text.slice(0, maxText) + '…';
+        if (text) node.text = text;
+        if (layout) {
+            const r = el.getBoundingClientRect();
+            node.rect = [r.x + scrollX, r.y + scrollY, r.width, r.height].map(Math.round);
+        }
+        if (depth !== null && level >= depth) return node;
+        const children = Array.from(el.children).map(c => walk(c, level + 1));
+        if (children.length > 0) node.children = children;
+        return node;
+    }
+    return walk(el, 0);
+}"#;
+
+/// axe-core from vendor/axe-core, or empty when the build had none (see
+/// build.rs).
+const AXE_SOURCE: &str = include_str!(concat!(env!("OUT_DIR"), "/axe.min.js"));
+
+/// Where axe-core is loaded from when there's no bundled copy.
+const AXE_URL: &str = "https://cdn.jsdelivr.net/npm/axe-core@4.10.2/axe.min.js";
+
+// Loads axe-core from `url` if it's given and the page doesn't already have
+// it, runs it (limited to `tags` when given) and returns the violations.
+const AXE_AUDIT_JS: &str = r#"async (url, tags) => {
+    if (!window.axe && url) {
+        await new Promise((resolve, reject) => {
+            const script = document.createElement('script');
+            script.src = url;
+            script.onload = resolve;
+            script.onerror = () => reject(new Error('Could not load axe-core from ' + url));
+            document.head.appendChild(script);
+        });
+    }
+    const options = tags.length ? { runOnly: { type: 'tag', values: tags } } : {};
+    const results = await window.axe.run(document, options);
+    return JSON.stringify(results.violations.map(v => ({
+        id: v.id,
+        impact: v.impact,
+        help: v.help,
+        help_url: v.helpUrl,
+        nodes: v.nodes.map(n => ({
+            selector: n.target.map(t => [].concat(t).join(' >> ')).join(' >> '),
+            html: n.html,
+            summary: n.failureSummary,
+        })),
+    })));
+}"#;
+
+// Converts the rendered DOM under an element to Markdown. Hidden elements
+// are skipped; block elements become paragraphs, everything else is inline.
+const MARKDOWN_JS: &str = r#"root => {
+    const SKIP = new Set(['SCRIPT', 'STYLE', 'NOSCRIPT', 'TEMPLATE', 'SVG', 'CANVAS',
+        'IFRAME', 'HEAD', 'SELECT', 'BUTTON']);
+    const BLOCK = new Set(['ADDRESS', 'ARTICLE', 'ASIDE', 'BLOCKQUOTE', 'BODY', 'DD', 'DETAILS',
+        'DIV', 'DL', 'DT', 'FIELDSET', 'FIGCAPTION', 'FIGURE', 'FOOTER', 'FORM', 'H1', 'H2',
+        'H3', 'H4', 'H5', 'H6', 'HEADER', 'HR', 'HTML', 'LI', 'MAIN', 'NAV', 'OL', 'P', 'PRE',
+        'SECTION', 'SUMMARY', 'TABLE', 'UL']);
+    const hidden = el => el.checkVisibility
+        ? !el.checkVisibility() && getComputedStyle(el).display !== 'contents'
+        : getComputedStyle(el).display === 'none';
+    const skipped = el => SKIP.has(el.tagName.toUpperCase()) || hidden(el);
+    const wrap = (mark, text) => text.trim() ? mark + text.trim() + mark : '';
+
+    const inline = el => Array.from(el.childNodes).map(inlineNode).join('');
+    const inlineNode = n => {
+        if (n.nodeType === Node.TEXT_NODE) return n.textContent.replace(/\s+/g, ' ');
+        if (n.nodeType !== Node.ELEMENT_NODE || skipped(n)) return '';
+        switch (n.tagName) {
+            case 'BR': return '\n';
+            case 'STRONG': case 'B': return wrap('**', inline(n));
+            case 'EM': case 'I': return wrap('_', inline(n));
+            case 'S': case 'DEL': return wrap('~~', inline(n));
+            case 'CODE': return '`' + n.textContent + '`';
+            case 'IMG': return n.getAttribute('src') ? '![' + (n.alt || '') + '](' + n.src + ')' : '';
+            case 'A': {
+                const text = inline(n).trim();
+                return n.getAttribute('href') && text ? '[' + text + '](' + n.href + ')' : text;
+            }
+            default: return BLOCK.has(n.tagName) ? ' ' + inline(n) + ' ' : inline(n);
+        }
+    };
+    const paragraph = text => text.replace(/[ \t]+/g, ' ').replace(/ ?\n ?/g, '\n').trim();
+
+    const blocks = el => {
+        const out = [];
+        let buf = '';
+        const flush = () => {
+            const text = paragraph(buf);
+            if (text) out.push(text);
+            buf = '';
+        };
+        for (const n of el.childNodes) {
+            if (n.nodeType === Node.ELEMENT_NODE && BLOCK.has(n.tagName)) {
+                flush();
+                if (!skipped(n)) out.push(...block(n));
+            } else {
+                buf += inlineNode(n);
+            }
+        }
+        flush();
+        return out;
+    };
+
+    const block = el => {
+        const tag = el.tagName;
+        if (/^H[1-6]$/.test(tag)) {
+            const text = paragraph(inline(el)).replace(/\n/g, ' ');
+            return text ? ['#'.repeat(Number(tag[1])) + ' ' + text] : [];
+        }
+        switch (tag) {
+            case 'HR': return ['---'];
+            case 'PRE': {
+                const code = el.querySelector('code');
+                const lang = ((code && code.className.match(/language-(\S+)/)) || [])[1] || '';
+                return ['```' + lang + '\n' + el.textContent.replace(/\n$/, '') + '\n```'];
+            }
+            case 'BLOCKQUOTE': {
+                const body = blocks(el).join('\n\n');
+                return body ? [body.split('\n').map(l => l ? '> ' + l : '>').join('\n')] : [];
+            }
+            case 'UL': case 'OL': return [list(el)];
+            case 'TABLE': return [table(el)];
+            default: return blocks(el);
+        }
+    };
+
+    const list = el => {
+        let n = Number(el.getAttribute('start')) || 1;
+        return Array.from(el.children)
+            .filter(li => li.tagName === 'LI' && !hidden(li))
+            .map(li => {
+                const marker = el.tagName === 'OL' ? (n++) + '. ' : '- ';
+                const [first, ...rest] = blocks(li).join('\n').split('\n');
+                const indent = ' '.repeat(marker.length);
+                return marker + first + rest.map(l => '\n' + (l ? indent + l : '')).join('');
+            })
+            .join('\n');
+    };
+
+    const table = el => {
+        const rows = Array.from(el.rows).filter(r => !hidden(r)).map(r => Array.from(r.cells)
+            .map(c => paragraph(inline(c)).replace(/\n/g, ' ').replace(/\|/g, '\\|')));
+        if (!rows.length) return '';
+        const width = Math.max(...rows.map(r => r.length));
+        const line = r => '| ' + Array.from({ length: width }, (_, i) => r[i] || '').join(' | ') + ' |';
+        return [line(rows[0]), line(Array(width).fill('---')), ...rows.slice(1).map(line)].join('\n');
+    };
+
+    const out = BLOCK.has(root.tagName) ? block(root) : [paragraph(inline(root))];
+    return out.filter(Boolean).join('\n\n');
+}"#;
+
+// Shared by the IndexedDB commands: promisified requests, opening a database
+// without creating it, and JSON for values JSON.stringify would lose.
+const IDB_HELPERS_JS: &str = r#"
+    const request = r => new Promise((resolve, reject) => {
+        r.onsuccess = () => resolve(r.result);
+        r.onerror = () => reject(r.error);
+    });
+    const openDb = async name => {
+        const names = (await indexedDB.databases()).map(d => d.name);
+        return names.includes(name) ? request(indexedDB.open(name)) : null;
+    };
+    const plain = (key, value) => {
+        if (typeof value === 'bigint') return value.toString();
+        if (value instanceof Map) return Object.fromEntries(value);
+        if (value instanceof Set) return Array.from(value);
+        if (value instanceof Blob) return { type: value.type, size: value.size };
+        if (value instanceof ArrayBuffer) return Array.from(new Uint8Array(value));
+        if (ArrayBuffer.isView(value)) return Array.from(value);
+        return value;
+    };
+"#;
+
+// Lists the origin's IndexedDB databases with their object stores.
+const IDB_LIST_JS: &str = r#"async () => {
+    const out = [];
+    for (const { name, version } of await indexedDB.databases()) {
+        const db = await openDb(name);
+        if (!db) continue;
+        const stores = [];
+        for (const storeName of db.objectStoreNames) {
+            const store = db.transaction(storeName, 'readonly').objectStore(storeName);
+            stores.push({
+                name: storeName,
+                keyPath: store.keyPath,
+                autoIncrement: store.autoIncrement,
+                indexes: Array.from(store.indexNames),
+                count: await request(store.count()),
+            });
+        }
+        db.close();
+        out.push({ name, version, stores });
+    }
+    return JSON.stringify(out);
+}"#;
+
+// Reads up to `limit` records of an object store as {key, value}.
+const IDB_DUMP_JS: &str = r#"async (name, storeName, limit) => {
+    const db = await openDb(name);
+    if (!db) return JSON.stringify({ error:
+        `No IndexedDB database named '${name}'. Use 'plwr idb list' to see them.` });
+    if (!db.objectStoreNames.contains(storeName)) {
+        db.close();
+        return JSON.stringify({ error: `No object store '${storeName}' in '${name}'` });
+    }
+    const store = db.transaction(storeName, 'readonly').objectStore(storeName);
+    const records = [];
+    await new Promise((resolve, reject) => {
+        const cursor = store.openCursor();
+        cursor.onerror = () => reject(cursor.error);
+        cursor.onsuccess = () => {
+            const c = cursor.result;
+            if (!c || (limit !== null && records.length >= limit)) return resolve();
+            records.push({ key: c.key, value: c.value });
+            c.continue();
+        };
+    });
+    db.close();
+    return JSON.stringify({ records }, plain);
+}"#;
+
+#[derive(Clone)]
 enum DialogAction {
     Accept(Option<String>),
     Dismiss,
 }
 
+#[derive(Default)]
+struct DialogState {
+    action: Option<DialogAction>,
+    persist: bool,
+    last_message: Option<String>,
+}
+
+/// Requests the daemon answers itself instead of the network. Checked by
+/// a single context route so rules can change without re-routing.
+#[derive(Default)]
+struct RouteRules {
+    /// `block` URL globs, with the regex each compiles to
+    blocked_urls: Vec<(String, regex::Regex)>,
+    /// `block --type` resource types
+    blocked_types: Vec<String>,
+    /// `block --preset` names and files, with the domains they list
+    blocked_presets: Vec<String>,
+    blocked_domains: std::collections::HashSet<String>,
+    /// Canned responses, oldest first; the newest match wins
+    mocks: Vec<MockRule>,
+    /// `header --match` headers, as (glob, regex, name, value)
+    headers: Vec<(String, regex::Regex, String, String)>,
+    har: Option<HarReplay>,
+    response_wait: Option<ResponseWait>,
+    request_wait: Option<RequestWait>,
+    /// `net save` rules, as (glob, regex, directory)
+    saves: Vec<(String, regex::Regex, std::path::PathBuf)>,
+    /// Files written by `net save` since it started
+    saved: Vec<String>,
+}
+
+impl RouteRules {
+    /// What to do with a request, by the `block`, `mock`, `mock --har` and
+    /// `header --match` rules in that order.
+    fn action(
+        &self,
+        method: &str,
+        url: &str,
+        resource_type: &str,
+        post_data: Option<&[u8]>,
+    ) -> RouteAction {
+        if self.blocked_types.iter().any(|t| t == resource_type)
+            || self.blocked_urls.iter().any(|(_, re)| re.is_match(url))
+            || blocklist::matches(&self.blocked_domains, url)
+        {
+            return RouteAction::Abort("blockedbyclient");
+        }
+        if let Some(mock) = self.mocks.iter().rev().find(|m| m.regex.is_match(url)) {
+            return RouteAction::Fulfill(FulfillOptions {
+                status: Some(mock.status),
+                headers: None,
+                body: Some(mock.body.clone()),
+                content_type: mock.content_type.clone(),
+            });
+        }
+        if let Some(replay) = &self.har {
+            let post_data = post_data.map(String::from_utf8_lossy);
+            match har::lookup(
+                &replay.entries,
+                &replay.dir,
+                method,
+                url,
+                post_data.as_deref(),
+            ) {
+                Some(response) => {
+                    return RouteAction::Fulfill(FulfillOptions {
+                        status: Some(response.status),
+                        headers: Some(fulfill_headers(response.headers)),
+                        body: Some(response.body),
+                        content_type: None,
+                    });
+                }
+                None if !replay.fallback => return RouteAction::Abort("failed"),
+                None => {}
+            }
+        }
+        RouteAction::Continue {
+            extra_headers: self
+                .headers
+                .iter()
+                .filter(|(_, re, _, _)| re.is_match(url))
+                .map(|(_, _, name, value)| (name.clone(), value.clone()))
+                .collect(),
+            wants_response: self
+                .response_wait
+                .as_ref()
+                .is_some_and(|w| w.response.is_none() && w.regex.is_match(url))
+                || self.saves.iter().any(|(_, re, _)| re.is_match(url)),
+        }
+    }
+}
+
+enum RouteAction {
+    Abort(&'static str),
+    Fulfill(FulfillOptions),
+    Continue {
+        extra_headers: Vec<(String, String)>,
+        /// A `wait-response` or `net save` wants to see the response
+        wants_response: bool,
+    },
+}
+
+/// A running `wait-response`; `response` is filled in by the route handler
+struct ResponseWait {
+    regex: regex::Regex,
+    /// `--status` filter like `200` or `2xx`
+    status: Option<String>,
+    response: Option<serde_json::Value>,
+}
+
+/// A running `wait-request`; `request` is filled in by the route handler
+struct RequestWait {
+    regex: regex::Regex,
+    request: Option<serde_json::Value>,
+}
+
+/// `mock <glob>`: a canned response for matching URLs
+struct MockRule {
+    pattern: String,
+    regex: regex::Regex,
+    status: u16,
+    content_type: Option<String>,
+    body: Vec<u8>,
+}
+
+/// `mock --har`: responses recorded in a HAR file
+struct HarReplay {
+    entries: Vec<serde_json::Value>,
+    /// Directory of the HAR, for bodies stored in separate files
+    dir: std::path::PathBuf,
+    /// Let requests the HAR has no response for through instead of aborting
+    fallback: bool,
+}
+
 struct State {
-    _playwright: Playwright,
+    playwright: Playwright,
+    /// The browser plwr launched and how, to launch it again if it
+    /// crashes. None with --cdp and --profile.
+    browser: Option<(Browser, LaunchOptions)>,
+    /// Cookies and storage, taken shortly after commands that could have
+    /// changed them, to restore if the browser crashes
+    recovery: Option<playwright_rs::StorageState>,
+    /// When the browser was last restarted after a crash, and how long to
+    /// wait before restarting it if it crashes again soon
+    restarted: Option<(std::time::Instant, std::time::Duration)>,
     page: Page,
     page_opened: bool,
     headers: HashMap<String, String>,
+    aliases: HashMap<String, String>,
+    scope: Option<String>,
+    /// Named `snapshot save` trees with the selector they were taken from
+    snapshots: HashMap<String, (String, serde_json::Value)>,
+    /// Named `snapshot-state save` snapshots
+    state_snapshots: HashMap<String, StateSnapshot>,
     video: Option<VideoState>,
+    /// Id of the running HAR recorder behind `net log`
+    net_recorder: Option<String>,
+    /// HAR entries taken from earlier recorders, oldest first
+    net_log: Vec<serde_json::Value>,
+    /// How many entries were dropped from the front of `net_log` to bound it
+    net_log_dropped: usize,
+    /// Whether `net capture` asked for response bodies to be kept in the log
+    net_bodies: bool,
+    /// Id of the HAR recorder started by `har-start`
+    har_recording: Option<String>,
+    /// Recorder id and destination for `mock --har --update`
+    har_update: Option<(String, String)>,
+    routes: Arc<Mutex<RouteRules>>,
+    routes_installed: bool,
+    /// DevTools session for `cache`, opened on first use
+    devtools: Option<pw_ext::CdpSession>,
     console_initialized: bool,
     network_initialized: bool,
-    dialog_action: Arc<Mutex<Option<DialogAction>>>,
+    dialog: Arc<Mutex<DialogState>>,
     dialog_installed: bool,
+    download: Arc<Mutex<Option<Download>>>,
+    download_installed: bool,
     clipboard_granted: bool,
+    /// What a replacement context needs to behave like the current one
+    settings: ContextSettings,
+    /// Started with `--profile`, so the context is persistent
+    profile: bool,
+    /// Where `start --persist` saves the session's state
+    persist: Option<std::path::PathBuf>,
     cdp: bool,
+    headed: bool,
+    started: std::time::Instant,
+}
+
+/// Context and page settings made by commands, kept so that `viewport
+/// --scale/--mobile/--touch` can carry them over to the new context it has
+/// to create. Extra headers and route rules live in `State` already.
+#[derive(Default)]
+struct ContextSettings {
+    /// Device scale factor, is_mobile and has_touch can only be given when a
+    /// context is created.
+    scale: Option<f64>,
+    mobile: bool,
+    touch: bool,
+    ignore_https_errors: bool,
+    /// `start --fake-media`, whose camera and microphone permissions have
+    /// to be granted to every new context
+    fake_media: bool,
+    viewport: Option<playwright_rs::Viewport>,
+    credentials: Option<(String, String, Option<String>)>,
+    geolocation: Option<playwright_rs::Geolocation>,
+    /// `emulate` options by their emulateMedia name
+    media: HashMap<&'static str, String>,
+    cache_disabled: bool,
+    sw_bypass: bool,
+    /// Accept-Language value given to `lang`
+    lang: Option<String>,
+}
+
+/// What `snapshot-state restore` puts back.
+struct StateSnapshot {
+    storage_state: playwright_rs::StorageState,
+    headers: HashMap<String, String>,
+    viewport: Option<playwright_rs::Viewport>,
+    /// None if no page was open
+    url: Option<String>,
+}
+
+/// What `start --persist` saves, and restores when the session starts again.
+#[derive(serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct PersistedSession {
+    /// None if no page was open
+    url: Option<String>,
+    headers: HashMap<String, String>,
+    viewport: Option<playwright_rs::Viewport>,
+    storage_state: playwright_rs::StorageState,
 }
 
 struct VideoState {
@@ -226,6 +1013,9 @@ pub async fn run(socket_path: &Path, headed: bool, ignore_cert_errors: bool) ->
     if socket_path.exists() {
         std::fs::remove_file(socket_path)?;
     }
+    if let Some(session) = socket_path.file_stem().and_then(|s| s.to_str()) {
+        logs::init(&logs::path(session)).ok();
+    }
 
     let playwright = match Playwright::launch().await {
         Ok(p) => p,
@@ -245,8 +1035,25 @@ pub async fn run(socket_path: &Path, headed: bool, ignore_cert_errors: bool) ->
 
     let cdp_channel = std::env::var("PLWR_CDP").ok();
     let is_cdp = cdp_channel.is_some();
+    let touch = std::env::var("PLWR_TOUCH").is_ok_and(|v| !v.is_empty());
+    let fake_media = std::env::var("PLWR_FAKE_MEDIA").is_ok_and(|v| !v.is_empty());
+    let profile = std::env::var("PLWR_PROFILE").ok().filter(|v| !v.is_empty());
+    let persist = std::env::var("PLWR_PERSIST_FILE")
+        .ok()
+        .filter(|v| !v.is_empty())
+        .map(std::path::PathBuf::from);
+    let idle_timeout = std::env::var("PLWR_IDLE_TIMEOUT")
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .filter(|&minutes| minutes > 0.0 && minutes.is_finite())
+        .map(|minutes| std::time::Duration::from_secs_f64(minutes * 60.0));
+    // A missing or unreadable file just means starting fresh.
+    let restored: Option<PersistedSession> = persist
+        .as_ref()
+        .and_then(|path| std::fs::read_to_string(path).ok())
+        .and_then(|text| serde_json::from_str(&text).ok());
 
-    let (page, video) = if let Some(ref channel) = cdp_channel {
+    let (page, video, browser) = if let Some(ref channel) = cdp_channel {
         let ws_url = match resolve_cdp_endpoint(channel) {
             Ok(url) => url,
             Err(e) => {
@@ -280,31 +1087,27 @@ pub async fn run(socket_path: &Path, headed: bool, ignore_cert_errors: bool) ->
                 return Err(e.into());
             }
         };
-        (page, None)
+        (page, None, None)
     } else {
         let video_output = std::env::var("PLWR_VIDEO").ok();
 
-        let args = if ignore_cert_errors {
-            Some(vec!["--ignore-certificate-errors".to_string()])
-        } else {
-            None
-        };
-
-        let browser = match playwright
-            .chromium()
-            .launch_with_options(LaunchOptions {
-                headless: Some(!headed),
-                args,
-                ..Default::default()
-            })
-            .await
-        {
-            Ok(b) => b,
-            Err(e) => {
-                println!("{}{}", ERROR_PREFIX, e);
-                return Err(e.into());
+        let mut args = Vec::new();
+        if ignore_cert_errors {
+            args.push("--ignore-certificate-errors".to_string());
+        }
+        // Chromium's fake camera and microphone, answering permission
+        // prompts itself. Without files it shows a test pattern and a beep.
+        if fake_media {
+            args.push("--use-fake-ui-for-media-stream".to_string());
+            args.push("--use-fake-device-for-media-stream".to_string());
+            if let Ok(path) = std::env::var("PLWR_FAKE_VIDEO") {
+                args.push(format!("--use-file-for-fake-video-capture={}", path));
             }
-        };
+            if let Ok(path) = std::env::var("PLWR_FAKE_AUDIO") {
+                args.push(format!("--use-file-for-fake-audio-capture={}", path));
+            }
+        }
+        let args = (!args.is_empty()).then_some(args);
 
         let video = if let Some(ref output_path) = video_output {
             let cache = dirs::cache_dir()
@@ -321,15 +1124,41 @@ pub async fn run(socket_path: &Path, headed: bool, ignore_cert_errors: bool) ->
             None
         };
 
-        let page = if let Some(ref vs) = video {
-            let ctx = match browser
-                .new_context_with_options(BrowserContextOptions {
-                    record_video: Some(RecordVideo {
-                        dir: vs.temp_dir.to_string_lossy().to_string(),
-                        size: None,
-                    }),
-                    ..Default::default()
-                })
+        // The launch flag covers the browser; the context option also covers
+        // `fetch`, which goes through the driver's own HTTP client.
+        let context_options = BrowserContextOptions {
+            record_video: video.as_ref().map(|vs| RecordVideo {
+                dir: vs.temp_dir.to_string_lossy().to_string(),
+                size: None,
+            }),
+            has_touch: touch.then_some(true),
+            ignore_https_errors: ignore_cert_errors.then_some(true),
+            permissions: fake_media.then(media_permissions),
+            viewport: restored.as_ref().and_then(|r| r.viewport.clone()),
+            extra_http_headers: restored
+                .as_ref()
+                .map(|r| r.headers.clone())
+                .filter(|h| !h.is_empty()),
+            // A profile keeps its own cookies and storage.
+            storage_state: restored
+                .as_ref()
+                .filter(|_| profile.is_none())
+                .map(|r| r.storage_state.clone()),
+            ..Default::default()
+        };
+
+        let (page, browser) = if let Some(ref dir) = profile {
+            // A persistent context comes with its own browser and first page.
+            let ctx = match playwright
+                .chromium()
+                .launch_persistent_context_with_options(
+                    dir,
+                    BrowserContextOptions {
+                        headless: Some(!headed),
+                        args,
+                        ..context_options
+                    },
+                )
                 .await
             {
                 Ok(c) => c,
@@ -338,26 +1167,73 @@ pub async fn run(socket_path: &Path, headed: bool, ignore_cert_errors: bool) ->
                     return Err(e.into());
                 }
             };
-            match ctx.new_page().await {
-                Ok(p) => p,
+            let page = match ctx.pages().into_iter().next() {
+                Some(p) => Ok(p),
+                None => ctx.new_page().await,
+            };
+            match page {
+                Ok(p) => (p, None),
                 Err(e) => {
                     println!("{}{}", ERROR_PREFIX, e);
                     return Err(e.into());
                 }
             }
         } else {
-            match browser.new_page().await {
-                Ok(p) => p,
+            let launch_options = LaunchOptions {
+                headless: Some(!headed),
+                args,
+                ..Default::default()
+            };
+            let browser = match playwright
+                .chromium()
+                .launch_with_options(launch_options.clone())
+                .await
+            {
+                Ok(b) => b,
                 Err(e) => {
                     println!("{}{}", ERROR_PREFIX, e);
                     return Err(e.into());
                 }
-            }
+            };
+
+            let page = if video.is_some()
+                || touch
+                || ignore_cert_errors
+                || fake_media
+                || restored.is_some()
+            {
+                let ctx = match browser.new_context_with_options(context_options).await {
+                    Ok(c) => c,
+                    Err(e) => {
+                        println!("{}{}", ERROR_PREFIX, e);
+                        return Err(e.into());
+                    }
+                };
+                match ctx.new_page().await {
+                    Ok(p) => p,
+                    Err(e) => {
+                        println!("{}{}", ERROR_PREFIX, e);
+                        return Err(e.into());
+                    }
+                }
+            } else {
+                match browser.new_page().await {
+                    Ok(p) => p,
+                    Err(e) => {
+                        println!("{}{}", ERROR_PREFIX, e);
+                        return Err(e.into());
+                    }
+                }
+            };
+            (page, Some((browser, launch_options)))
         };
 
-        (page, video)
+        (page, video, browser)
     };
-    let listener = match UnixListener::bind(socket_path) {
+    let listener = match UnixListener::bind(socket_path).and_then(|l| {
+        socket::restrict(socket_path)?;
+        Ok(l)
+    }) {
         Ok(l) => l,
         Err(e) => {
             println!("{}{}", ERROR_PREFIX, e);
@@ -366,66 +1242,273 @@ pub async fn run(socket_path: &Path, headed: bool, ignore_cert_errors: bool) ->
     };
 
     println!("{}", READY_SIGNAL);
+    logs::log(
+        "info",
+        format!("Started (pid {})", std::process::id()),
+        serde_json::json!({"pid": std::process::id(), "headed": headed || is_cdp, "cdp": is_cdp}),
+    );
 
     let mut state = State {
-        _playwright: playwright,
+        playwright,
+        browser,
+        recovery: None,
+        restarted: None,
         page,
         page_opened: false,
         headers: HashMap::new(),
+        aliases: HashMap::new(),
+        scope: None,
+        snapshots: HashMap::new(),
+        state_snapshots: HashMap::new(),
         video,
+        net_recorder: None,
+        net_log: Vec::new(),
+        net_log_dropped: 0,
+        net_bodies: false,
+        har_recording: None,
+        har_update: None,
+        routes: Arc::new(Mutex::new(RouteRules::default())),
+        routes_installed: false,
+        devtools: None,
         console_initialized: false,
         network_initialized: false,
-        dialog_action: Arc::new(Mutex::new(None)),
+        dialog: Arc::new(Mutex::new(DialogState::default())),
+        download: Arc::new(Mutex::new(None)),
+        download_installed: false,
         dialog_installed: false,
         clipboard_granted: false,
+        settings: ContextSettings {
+            touch,
+            ignore_https_errors: ignore_cert_errors,
+            fake_media,
+            ..Default::default()
+        },
+        profile: profile.is_some(),
+        persist,
         cdp: is_cdp,
+        headed: headed || is_cdp,
+        started: std::time::Instant::now(),
     };
 
-    loop {
-        let (stream, _) = listener.accept().await?;
-
-        let resp = async {
-            let (reader, mut writer) = stream.into_split();
-            let mut reader = BufReader::new(reader);
-            let mut line = String::new();
-            reader.read_line(&mut line).await?;
-
-            let req: Request = serde_json::from_str(&line)?;
-            let is_stop = matches!(req.command, Command::Stop);
-            let resp = if !state.page_opened && req.command.requires_page() {
-                Response::err("No page open. Use 'plwr open <url>' first.".to_string())
-            } else {
-                handle_command(&mut state, req.command)
-                    .await
-                    .unwrap_or_else(|e| Response::err(clean_error(e)))
+    if let Some(restored) = restored {
+        state.headers = restored.headers;
+        state.settings.viewport = restored.viewport;
+        if let Some(url) = restored.url {
+            let open = Command::Open {
+                url,
+                timeout: 30000,
+                show_redirects: false,
             };
+            if let Err(e) = handle_command(&mut state, open).await {
+                logs::error(format!("Cannot reopen the persisted page: {}", e));
+            }
+        }
+    }
 
-            let mut buf = serde_json::to_vec(&resp)?;
-            buf.push(b'\n');
-            writer.write_all(&buf).await?;
+    // Connections are read in their own tasks and their commands run here,
+    // one at a time. A connection that stays open, like `batch` or `shell`,
+    // doesn't hold up other clients or the timers between its commands.
+    let (incoming_tx, mut incoming) = tokio::sync::mpsc::channel::<Incoming>(16);
+    let mut persist_tick = tokio::time::interval(std::time::Duration::from_secs(5));
+    // After a long command, save once rather than once per missed tick
+    persist_tick.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut last_active = tokio::time::Instant::now();
+    // When to take the cookies and storage to recover from a crash
+    let mut recovery_due: Option<tokio::time::Instant> = None;
+    loop {
+        let idle_deadline = last_active + idle_timeout.unwrap_or_default();
+        let Incoming {
+            request: req,
+            writer,
+        } = tokio::select! {
+            accepted = listener.accept() => {
+                let stream = accepted?.0;
+                // Other users can't reach the socket, but make sure.
+                if !socket::is_own_user(&stream) {
+                    logs::log("warn", "Refused a connection from another user", serde_json::Value::Null);
+                    continue;
+                }
+                tokio::spawn(read_connection(stream, incoming_tx.clone()));
+                continue;
+            }
+            Some(received) = incoming.recv() => received,
+            _ = persist_tick.tick(), if state.persist.is_some() => {
+                if browser_crashed(&state) {
+                    continue;
+                }
+                if let Err(e) = persist_session(&state).await {
+                    logs::error(format!("Cannot persist the session: {}", e));
+                }
+                continue;
+            }
+            _ = tokio::time::sleep_until(recovery_due.unwrap_or(idle_deadline)), if recovery_due.is_some() => {
+                recovery_due = None;
+                update_recovery(&mut state).await;
+                continue;
+            }
+            _ = tokio::time::sleep_until(idle_deadline), if idle_timeout.is_some() => {
+                logs::info("Stopping after the idle timeout");
+                // Shut down the way `stop` does, so video and state are saved.
+                if state.persist.is_some() {
+                    if let Err(e) = persist_session(&state).await {
+                        logs::error(format!("Cannot persist the session: {}", e));
+                    }
+                }
+                if let Err(e) = handle_command(&mut state, Command::Stop).await {
+                    logs::error(format!("Cannot stop the idle session: {}", e));
+                }
+                break;
+            }
+        };
 
-            Ok::<bool, anyhow::Error>(is_stop)
+        let is_stop = matches!(req.command, Command::Stop);
+        // Listing sessions or checking on one doesn't keep a forgotten
+        // session alive.
+        let is_activity = !matches!(req.command, Command::SessionInfo | Command::Ping);
+        let is_query = req.command.is_query();
+        let sets_storage = req.command.sets_storage();
+        // Save the final state while the browser is still there.
+        if is_stop && state.persist.is_some() && !browser_crashed(&state) {
+            if let Err(e) = persist_session(&state).await {
+                logs::error(format!("Cannot persist the session: {}", e));
+            }
         }
-        .await;
+        let name = serde_json::to_value(&req.command)
+            .ok()
+            .and_then(|c| c["type"].as_str().map(str::to_string))
+            .unwrap_or_default();
+        // `sessions` asks every session all the time
+        let level = match req.command {
+            Command::SessionInfo | Command::Ping => "debug",
+            _ => "info",
+        };
+        let started = std::time::Instant::now();
+        // A browser that crashed since the last command is started again
+        // before running this one. `stop` has nothing to stop. The crash is
+        // seen through the browser's disconnected event, which is_connected
+        // reflects, so there's nothing to poll in between.
+        let crashed = browser_crashed(&state);
+        let restart = if crashed && !is_stop {
+            Some(restart_browser(&mut state).await)
+        } else {
+            None
+        };
+        let mut resp = match &restart {
+            _ if crashed && is_stop => Response::ok_empty(),
+            Some(Err(e)) => Response::err(e.to_string()),
+            _ if !state.page_opened && req.command.requires_page() => {
+                Response::err("No page open. Use 'plwr open <url>' first.".to_string())
+            }
+            _ => match handle_command(&mut state, req.command).await {
+                Ok(resp) => resp,
+                Err(e) if browser_crashed(&state) && !is_stop => {
+                    match restart_browser(&mut state).await {
+                        Ok(()) => Response::err(format!(
+                            "The browser crashed and was restarted before the command finished: {}",
+                            clean_error(e)
+                        )),
+                        Err(e) => Response::err(e.to_string()),
+                    }
+                }
+                Err(e) => Response::err(clean_error(e)),
+            },
+        };
+        if matches!(restart, Some(Ok(()))) {
+            resp.warning = Some(
+                "The browser had crashed and was restarted, with the cookies and storage as of the last command".to_string(),
+            );
+        }
+        log_command(level, &name, started.elapsed(), &resp);
 
-        match resp {
-            Ok(true) => break,
-            Ok(false) => {}
-            Err(e) => eprintln!("connection error: {}", e),
+        let mut buf = serde_json::to_vec(&resp)?;
+        buf.push(b'\n');
+        if let Err(e) = writer.lock().await.write_all(&buf).await {
+            logs::error(format!("Connection error: {}", e));
+        }
+        if is_stop {
+            break;
+        }
+        // Commands that set cookies or storage are taken in right away.
+        // What the page does after other commands is taken at most once a
+        // second, so `batch` and `shell` loops don't wait on it.
+        if sets_storage {
+            recovery_due = None;
+            update_recovery(&mut state).await;
+        } else if !is_query {
+            recovery_due.get_or_insert(tokio::time::Instant::now() + RECOVERY_DELAY);
+        }
+        // Counted from the end of a command, so a long `wait` or `pick`
+        // never times out.
+        if is_activity {
+            last_active = tokio::time::Instant::now();
         }
     }
 
-    if socket_path.exists() {
-        std::fs::remove_file(socket_path)?;
-    }
+    socket::remove(socket_path);
+    logs::info("Stopped");
 
     Ok(())
 }
 
+/// A command from a client, with the connection to answer on.
+struct Incoming {
+    request: Request,
+    writer: Arc<tokio::sync::Mutex<OwnedWriteHalf>>,
+}
+
+/// Passes the commands arriving on a connection on to the daemon's loop.
+/// Usually there's one, but `batch` and `shell` send many.
+async fn read_connection(stream: UnixStream, incoming: tokio::sync::mpsc::Sender<Incoming>) {
+    let (reader, writer) = stream.into_split();
+    let writer = Arc::new(tokio::sync::Mutex::new(writer));
+    let mut lines = BufReader::new(reader).lines();
+    loop {
+        let request = match lines.next_line().await {
+            Ok(Some(line)) => serde_json::from_str(&line).map_err(anyhow::Error::from),
+            Ok(None) => return,
+            Err(e) => Err(e.into()),
+        };
+        let request = match request {
+            Ok(request) => request,
+            Err(e) => {
+                logs::error(format!("Connection error: {}", e));
+                return;
+            }
+        };
+        let writer = writer.clone();
+        if incoming.send(Incoming { request, writer }).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Logs a command by name only, as its arguments can be passwords and such.
+fn log_command(level: &str, name: &str, took: std::time::Duration, resp: &Response) {
+    let ms = took.as_millis() as u64;
+    match &resp.error {
+        None => logs::log(
+            level,
+            format!("{} ({}ms)", name, ms),
+            serde_json::json!({"command": name, "ms": ms, "ok": resp.ok}),
+        ),
+        Some(error) => logs::log(
+            "warn",
+            format!("{} failed after {}ms: {}", name, ms, error),
+            serde_json::json!({"command": name, "ms": ms, "ok": false, "error": error}),
+        ),
+    }
+}
+
 async fn handle_command(state: &mut State, command: Command) -> Result<Response> {
+    let command = resolve_selectors(state, command)?;
+
     // Handle commands that mutate state before borrowing the page
     match command {
-        Command::Open { url, timeout } => {
+        Command::Open {
+            url,
+            timeout,
+            show_redirects,
+        } => {
             if !state.cdp && !state.console_initialized {
                 state.page.add_init_script(CONSOLE_INTERCEPTOR_JS).await?;
                 state.console_initialized = true;
@@ -434,6 +1517,15 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
                 state.page.add_init_script(NETWORK_INTERCEPTOR_JS).await?;
                 state.network_initialized = true;
             }
+            if state.net_recorder.is_none() {
+                start_net_recorder(state).await?;
+            }
+            // The chain is read back from the request log, so start from a
+            // fresh recorder to know which entries belong to this navigation.
+            if show_redirects {
+                sync_net_log(state).await?;
+            }
+            let log_start = state.net_log_dropped + state.net_log.len();
             // Install transient route interception so custom headers are
             // included on the navigation request itself (setExtraHTTPHeaders
             // on the context doesn't reliably cover goto()).
@@ -461,8 +1553,10 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
                             for (k, v) in headers {
                                 merged.insert(k, v);
                             }
+                            // Fall back rather than continue so context
+                            // routes (mock) still see the navigation.
                             route
-                                .continue_(Some(ContinueOptions {
+                                .fallback(Some(ContinueOptions {
                                     headers: Some(merged),
                                     ..Default::default()
                                 }))
@@ -488,34 +1582,158 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
                 pw_ext::page_evaluate_value(&state.page, CONSOLE_INTERCEPTOR_JS).await?;
                 pw_ext::page_evaluate_value(&state.page, NETWORK_INTERCEPTOR_JS).await?;
             }
+            // Always listen for dialogs so `dialog last` can report them; with
+            // no handler armed they are dismissed, same as Playwright's default.
+            install_dialog_handler(state).await?;
             state.page_opened = true;
+            if show_redirects {
+                sync_net_log(state).await?;
+                let start = log_start
+                    .saturating_sub(state.net_log_dropped)
+                    .min(state.net_log.len());
+                let chain = har::redirect_chain(&state.net_log[start..]);
+                return Ok(Response::ok_value(serde_json::Value::Array(chain)));
+            }
+            return Ok(Response::ok_empty());
+        }
+        Command::Header {
+            name,
+            value,
+            url_match: Some(pattern),
+        } => {
+            {
+                let mut rules = state.routes.lock().unwrap();
+                rules
+                    .headers
+                    .retain(|(p, _, n, _)| !(*p == pattern && n.eq_ignore_ascii_case(&name)));
+                let re = glob::url_glob(&pattern);
+                rules.headers.push((pattern, re, name, value));
+            }
+            install_route_handler(state).await?;
             return Ok(Response::ok_empty());
         }
-        Command::Header { name, value } => {
+        Command::Header {
+            name,
+            value,
+            url_match: None,
+        } => {
             state.headers.insert(name, value);
             let ctx = &state.page.context()?;
             pw_ext::set_extra_http_headers(ctx, state.headers.clone()).await?;
             return Ok(Response::ok_empty());
         }
-        Command::HeaderClear => {
-            state.headers.clear();
-            let ctx = &state.page.context()?;
-            pw_ext::set_extra_http_headers(ctx, HashMap::new()).await?;
+        Command::Auth {
+            username,
+            password,
+            origin,
+        } => {
+            let ctx = state.page.context()?;
+            pw_ext::set_http_credentials(&ctx, Some((&username, &password, origin.as_deref())))
+                .await?;
+            state.settings.credentials = Some((username, password, origin));
             return Ok(Response::ok_empty());
         }
-        Command::Cookie { name, value, url } => {
+        Command::AuthClear => {
             let ctx = state.page.context()?;
-            let url = if url.is_empty() {
-                if !state.page_opened {
-                    return Ok(Response::err(
-                        "No page open and no --url provided. Use --url to specify the cookie domain.".to_string(),
-                    ));
-                }
-                state.page.url()
-            } else {
-                url
+            pw_ext::set_http_credentials(&ctx, None).await?;
+            state.settings.credentials = None;
+            return Ok(Response::ok_empty());
+        }
+        Command::Geo {
+            latitude,
+            longitude,
+            accuracy,
+        } => {
+            let ctx = state.page.context()?;
+            pw_ext::grant_permissions(&ctx, &["geolocation"]).await?;
+            let geolocation = playwright_rs::Geolocation {
+                latitude,
+                longitude,
+                accuracy,
             };
-            pw_ext::add_cookie(&ctx, name, value, url).await?;
+            pw_ext::set_geolocation(&ctx, Some(&geolocation)).await?;
+            state.settings.geolocation = Some(geolocation);
+            return Ok(Response::ok_empty());
+        }
+        Command::GeoClear => {
+            // There's no revoking a single permission, so clear them all and
+            // let the clipboard commands grant theirs again when needed.
+            let ctx = state.page.context()?;
+            pw_ext::clear_permissions(&ctx).await?;
+            state.clipboard_granted = false;
+            if state.settings.fake_media {
+                pw_ext::grant_permissions(&ctx, &["camera", "microphone"]).await?;
+            }
+            pw_ext::set_geolocation(&ctx, None).await?;
+            state.settings.geolocation = None;
+            return Ok(Response::ok_empty());
+        }
+        Command::HeaderClear => {
+            // Keep `lang`'s header, or it would disagree with the navigator.
+            state.headers.clear();
+            if let Some(languages) = &state.settings.lang {
+                state
+                    .headers
+                    .insert("Accept-Language".to_string(), languages.clone());
+            }
+            state.routes.lock().unwrap().headers.clear();
+            let ctx = &state.page.context()?;
+            pw_ext::set_extra_http_headers(ctx, state.headers.clone()).await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::Lang { languages } => {
+            state
+                .headers
+                .retain(|name, _| !name.eq_ignore_ascii_case("accept-language"));
+            state
+                .headers
+                .insert("Accept-Language".to_string(), languages.clone());
+            let ctx = &state.page.context()?;
+            pw_ext::set_extra_http_headers(ctx, state.headers.clone()).await?;
+            override_languages(state, Some(&languages)).await?;
+            state.settings.lang = Some(languages);
+            return Ok(Response::ok_empty());
+        }
+        Command::LangClear => {
+            state
+                .headers
+                .retain(|name, _| !name.eq_ignore_ascii_case("accept-language"));
+            let ctx = &state.page.context()?;
+            pw_ext::set_extra_http_headers(ctx, state.headers.clone()).await?;
+            override_languages(state, None).await?;
+            state.settings.lang = None;
+            return Ok(Response::ok_empty());
+        }
+        Command::Cookie {
+            name,
+            value,
+            url,
+            domain,
+            path,
+            expires,
+            http_only,
+            secure,
+            same_site,
+        } => {
+            let ctx = state.page.context()?;
+            let url = if url.is_empty() && domain.is_none() {
+                if !state.page_opened {
+                    return Ok(Response::err(
+                        "No page open and no --url provided. Use --url to specify the cookie domain.".to_string(),
+                    ));
+                }
+                state.page.url()
+            } else {
+                url
+            };
+            let attributes = pw_ext::CookieAttributes {
+                path,
+                expires,
+                http_only,
+                secure,
+                same_site,
+            };
+            pw_ext::add_cookie(&ctx, name, value, url, domain, &attributes).await?;
             return Ok(Response::ok_empty());
         }
         Command::CookieList => {
@@ -543,51 +1761,755 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             pw_ext::clear_cookies(ctx).await?;
             return Ok(Response::ok_empty());
         }
-        Command::Viewport { width, height } => {
-            state
-                .page
-                .set_viewport_size(playwright_rs::Viewport { width, height })
+        Command::CookieImport { cookies } => {
+            let ctx = &state.page.context()?;
+            let count = cookies.len();
+            pw_ext::add_cookies(ctx, cookies).await?;
+            return Ok(Response::ok_value(serde_json::json!(format!(
+                "Imported {} cookie{}",
+                count,
+                if count == 1 { "" } else { "s" }
+            ))));
+        }
+        Command::Alias { name, selector } => {
+            state.aliases.insert(name, selector);
+            return Ok(Response::ok_empty());
+        }
+        Command::AliasList => {
+            let aliases = state
+                .aliases
+                .iter()
+                .map(|(k, v)| (k.clone(), serde_json::Value::String(v.clone())))
+                .collect();
+            return Ok(Response::ok_value(serde_json::Value::Object(aliases)));
+        }
+        Command::AliasClear => {
+            state.aliases.clear();
+            return Ok(Response::ok_empty());
+        }
+        Command::Within { selector } => {
+            state.scope = Some(selector);
+            return Ok(Response::ok_empty());
+        }
+        Command::WithinClear => {
+            state.scope = None;
+            return Ok(Response::ok_empty());
+        }
+        Command::SnapshotSave {
+            name,
+            selector,
+            timeout,
+        } => {
+            let selector = selector
+                .or_else(|| state.scope.clone())
+                .unwrap_or_else(|| "html".to_string());
+            let loc = state.page.locator(&selector).await;
+            wait_for_state(&loc, &selector, timeout, WaitState::Attached).await?;
+            let tree = layout_tree(&state.page, &selector).await?;
+            state.snapshots.insert(name, (selector, tree));
+            return Ok(Response::ok_empty());
+        }
+        Command::SnapshotDiff { name } => {
+            let Some((selector, before)) = state.snapshots.get(&name) else {
+                anyhow::bail!(
+                    "No snapshot named '{}'. Save one with 'plwr snapshot save {}'.",
+                    name,
+                    name
+                );
+            };
+            let after = layout_tree(&state.page, selector).await?;
+            return Ok(Response::ok_value(tree::diff(before, &after)));
+        }
+        Command::NetLog { filter, since } => {
+            let entries = net_log_entries(state, filter.as_deref(), since)
+                .await?
+                .map(har::log_entry)
+                .collect();
+            return Ok(Response::ok_value(serde_json::Value::Array(entries)));
+        }
+        Command::NetSave { pattern, dir } => {
+            std::fs::create_dir_all(&dir)
+                .map_err(|e| anyhow::anyhow!("Cannot create {}: {}", dir, e))?;
+            {
+                let mut rules = state.routes.lock().unwrap();
+                rules.saves.retain(|(p, _, _)| *p != pattern);
+                let re = glob::url_glob(&pattern);
+                rules.saves.push((pattern, re, dir.into()));
+            }
+            install_route_handler(state).await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::NetSaveStop => {
+            let mut rules = state.routes.lock().unwrap();
+            rules.saves.clear();
+            let saved = std::mem::take(&mut rules.saved);
+            return Ok(Response::ok_value(serde_json::json!(saved)));
+        }
+        Command::NetTiming { pattern } => {
+            let regex = glob::url_glob(&pattern);
+            let entries = net_log_entries(state, None, None)
+                .await?
+                .filter(|e| {
+                    e["request"]["url"]
+                        .as_str()
+                        .is_some_and(|u| regex.is_match(u))
+                })
+                .map(har::timing)
+                .collect();
+            return Ok(Response::ok_value(serde_json::Value::Array(entries)));
+        }
+        Command::NetFailures { filter, since } => {
+            let since = match since {
+                Some(since) => since,
+                None => pw_ext::page_evaluate_value(
+                    &state.page,
+                    "() => String(Math.floor(performance.timeOrigin))",
+                )
+                .await?
+                .parse()?,
+            };
+            let since = Some(since);
+            let entries = net_log_entries(state, filter.as_deref(), since)
+                .await?
+                .filter(|e| har::is_failure(e))
+                .map(|e| {
+                    let mut record = har::log_entry(e);
+                    record["initiator"] = serde_json::json!(har::initiator(e));
+                    record
+                })
+                .collect();
+            return Ok(Response::ok_value(serde_json::Value::Array(entries)));
+        }
+        Command::NetBody { pattern } => {
+            sync_net_log(state).await?;
+            let regex = glob::url_glob(&pattern);
+            let Some(entry) = state.net_log.iter().rev().find(|e| {
+                let status = e["response"]["status"].as_i64().unwrap_or(0);
+                status > 0
+                    && !(300..400).contains(&status)
+                    && e["request"]["url"]
+                        .as_str()
+                        .is_some_and(|u| regex.is_match(u))
+            }) else {
+                anyhow::bail!(
+                    "No response matching {} in the request log. See 'plwr net log'.",
+                    pattern
+                );
+            };
+            let url = entry["request"]["url"].as_str().unwrap_or_default();
+            if !state.net_bodies {
+                anyhow::bail!(
+                    "No body recorded for {}. Response bodies are only kept after 'plwr net capture'.",
+                    url
+                );
+            }
+            if entry["response"]["content"]["_dropped"] == true {
+                anyhow::bail!(
+                    "The body of {} was dropped, only the latest {} MB of bodies are kept",
+                    url,
+                    NET_LOG_MAX_BODY_BYTES / (1024 * 1024)
+                );
+            }
+            let body = har::response_body(entry, std::path::Path::new(""))
+                .ok_or_else(|| anyhow::anyhow!("No body recorded for {}", url))?;
+            let mime = entry["response"]["content"]["mimeType"]
+                .as_str()
+                .unwrap_or_default();
+            if mime.contains("json") {
+                if let Ok(json) = serde_json::from_slice(&body) {
+                    return Ok(Response::ok_value(json));
+                }
+            }
+            let text = String::from_utf8(body)
+                .map_err(|_| anyhow::anyhow!("Response body of {} is binary ({})", url, mime))?;
+            return Ok(Response::ok_value(serde_json::Value::String(text)));
+        }
+        Command::NetCapture => {
+            state.net_bodies = true;
+            // Swap the running recorder for one that keeps bodies
+            if state.net_recorder.is_some() {
+                sync_net_log(state).await?;
+            }
+            return Ok(Response::ok_empty());
+        }
+        Command::NetClear => {
+            sync_net_log(state).await?;
+            state.net_log.clear();
+            return Ok(Response::ok_empty());
+        }
+        Command::HarStart { omit_content } => {
+            if state.har_recording.is_some() {
+                anyhow::bail!(
+                    "A HAR recording is already running. Save it with 'plwr har-stop <path>'."
+                );
+            }
+            let content = if omit_content { "omit" } else { "embed" };
+            let har_id = pw_ext::har_start(&state.page.context()?, content).await?;
+            state.har_recording = Some(har_id);
+            return Ok(Response::ok_empty());
+        }
+        Command::HarStop { path } => {
+            let Some(har_id) = state.har_recording.take() else {
+                anyhow::bail!("No HAR recording running. Start one with 'plwr har-start'.");
+            };
+            let har = pw_ext::har_export(&state.page.context()?, &har_id).await?;
+            std::fs::write(&path, har)
+                .map_err(|e| anyhow::anyhow!("Cannot write {}: {}", path, e))?;
+            return Ok(Response::ok_value(serde_json::Value::String(path)));
+        }
+        Command::Block {
+            patterns,
+            types,
+            presets,
+        } => {
+            let mut domains = Vec::new();
+            for preset in &presets {
+                match blocklist::preset(preset) {
+                    Some(list) => domains.extend(list.iter().map(|d| d.to_string())),
+                    None => {
+                        let text = std::fs::read_to_string(preset)
+                            .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", preset, e))?;
+                        let list = blocklist::parse(&text);
+                        if list.is_empty() {
+                            anyhow::bail!("No domains found in {}", preset);
+                        }
+                        domains.extend(list);
+                    }
+                }
+            }
+            {
+                let mut rules = state.routes.lock().unwrap();
+                for pattern in patterns {
+                    if !rules.blocked_urls.iter().any(|(p, _)| *p == pattern) {
+                        let re = glob::url_glob(&pattern);
+                        rules.blocked_urls.push((pattern, re));
+                    }
+                }
+                for t in types {
+                    if !rules.blocked_types.contains(&t) {
+                        rules.blocked_types.push(t);
+                    }
+                }
+                for preset in presets {
+                    if !rules.blocked_presets.contains(&preset) {
+                        rules.blocked_presets.push(preset);
+                    }
+                }
+                rules.blocked_domains.extend(domains);
+            }
+            install_route_handler(state).await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::BlockList => {
+            let rules = state.routes.lock().unwrap();
+            let urls: Vec<&String> = rules.blocked_urls.iter().map(|(p, _)| p).collect();
+            return Ok(Response::ok_value(serde_json::json!({
+                "urls": urls,
+                "types": rules.blocked_types,
+                "presets": rules.blocked_presets,
+            })));
+        }
+        Command::BlockClear => {
+            let mut rules = state.routes.lock().unwrap();
+            rules.blocked_urls.clear();
+            rules.blocked_types.clear();
+            rules.blocked_presets.clear();
+            rules.blocked_domains.clear();
+            return Ok(Response::ok_empty());
+        }
+        Command::Mock {
+            pattern,
+            status,
+            body,
+            body_file,
+            content_type,
+        } => {
+            let body = match body_file {
+                Some(path) => std::fs::read(&path)
+                    .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path, e))?,
+                None => body.unwrap_or_default().into_bytes(),
+            };
+            {
+                let mut rules = state.routes.lock().unwrap();
+                rules.mocks.retain(|m| m.pattern != pattern);
+                rules.mocks.push(MockRule {
+                    regex: glob::url_glob(&pattern),
+                    pattern,
+                    status,
+                    content_type,
+                    body,
+                });
+            }
+            install_route_handler(state).await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::MockList => {
+            let rules = state.routes.lock().unwrap();
+            let mocks: Vec<serde_json::Value> = rules
+                .mocks
+                .iter()
+                .map(|m| {
+                    serde_json::json!({
+                        "pattern": m.pattern,
+                        "status": m.status,
+                        "content_type": m.content_type,
+                        "size": m.body.len(),
+                    })
+                })
+                .collect();
+            return Ok(Response::ok_value(serde_json::Value::Array(mocks)));
+        }
+        Command::MockRemove { pattern } => {
+            let mut rules = state.routes.lock().unwrap();
+            let before = rules.mocks.len();
+            rules.mocks.retain(|m| m.pattern != pattern);
+            if rules.mocks.len() == before {
+                anyhow::bail!("No mock for '{}'. See 'plwr mock --list'.", pattern);
+            }
+            return Ok(Response::ok_empty());
+        }
+        Command::WaitResponse {
+            pattern,
+            status,
+            print_body,
+            selector,
+            timeout,
+        } => {
+            state.routes.lock().unwrap().response_wait = Some(ResponseWait {
+                regex: glob::url_glob(&pattern),
+                status,
+                response: None,
+            });
+            install_route_handler(state).await?;
+            let result = wait_for_route(state, selector.as_deref(), timeout, |rules| {
+                rules.response_wait.as_mut()?.response.take()
+            })
+            .await;
+            state.routes.lock().unwrap().response_wait = None;
+
+            let Some(mut response) = result? else {
+                anyhow::bail!(
+                    "Timeout {}ms exceeded waiting for a response matching {}",
+                    timeout,
+                    pattern
+                );
+            };
+            if !print_body {
+                if let Some(obj) = response.as_object_mut() {
+                    obj.remove("body");
+                }
+            }
+            return Ok(Response::ok_value(response));
+        }
+        Command::WaitRequest {
+            pattern,
+            print,
+            selector,
+            timeout,
+        } => {
+            state.routes.lock().unwrap().request_wait = Some(RequestWait {
+                regex: glob::url_glob(&pattern),
+                request: None,
+            });
+            install_route_handler(state).await?;
+            let result = wait_for_route(state, selector.as_deref(), timeout, |rules| {
+                rules.request_wait.as_mut()?.request.take()
+            })
+            .await;
+            state.routes.lock().unwrap().request_wait = None;
+
+            let Some(request) = result? else {
+                anyhow::bail!(
+                    "Timeout {}ms exceeded waiting for a request matching {}",
+                    timeout,
+                    pattern
+                );
+            };
+            return Ok(if print {
+                Response::ok_value(request)
+            } else {
+                Response::ok_empty()
+            });
+        }
+        Command::MockHar {
+            path,
+            update,
+            not_found_fallback,
+        } => {
+            if update {
+                if state.har_update.is_some() {
+                    anyhow::bail!("Already recording a HAR for mock --update. Save it with 'plwr mock --clear'.");
+                }
+                let har_id = pw_ext::har_start(&state.page.context()?, "embed").await?;
+                state.har_update = Some((har_id, path));
+                return Ok(Response::ok_empty());
+            }
+            let har = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Cannot read HAR {}: {}", path, e))?;
+            let mut har: serde_json::Value = serde_json::from_str(&har)
+                .map_err(|e| anyhow::anyhow!("Invalid HAR {}: {}", path, e))?;
+            let serde_json::Value::Array(entries) = har["log"]["entries"].take() else {
+                anyhow::bail!("Invalid HAR {}: no log.entries", path);
+            };
+            let dir = Path::new(&path)
+                .parent()
+                .map(Path::to_path_buf)
+                .unwrap_or_default();
+            state.routes.lock().unwrap().har = Some(HarReplay {
+                entries,
+                dir,
+                fallback: not_found_fallback,
+            });
+            install_route_handler(state).await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::MockClear => {
+            {
+                let mut rules = state.routes.lock().unwrap();
+                rules.mocks.clear();
+                rules.har = None;
+            }
+            if let Some(path) = save_har_update(state).await? {
+                return Ok(Response::ok_value(serde_json::Value::String(path)));
+            }
+            return Ok(Response::ok_empty());
+        }
+        Command::Viewport {
+            width,
+            height,
+            scale,
+            mobile,
+            touch,
+            timeout,
+        } => {
+            let viewport = playwright_rs::Viewport { width, height };
+            let settings = &state.settings;
+            let scale = scale.or(settings.scale);
+            let mobile = mobile.unwrap_or(settings.mobile);
+            let touch = touch.unwrap_or(settings.touch);
+            if scale != settings.scale || mobile != settings.mobile || touch != settings.touch {
+                ensure_recreatable(state, "Changing --scale, --mobile or --touch")?;
+                state.settings.viewport = Some(viewport);
+                state.settings.scale = scale;
+                state.settings.mobile = mobile;
+                state.settings.touch = touch;
+                recreate_context(state, None, timeout).await?;
+            } else {
+                state.page.set_viewport_size(viewport.clone()).await?;
+                state.settings.viewport = Some(viewport);
+            }
+            return Ok(Response::ok_empty());
+        }
+        Command::StateSave { path } => {
+            let storage_state = pw_ext::storage_state(&state.page.context()?).await?;
+            let json = pw_ext::storage_state_json(storage_state)?;
+            // Like Playwright, create the directory and leave out the
+            // trailing newline.
+            if let Some(dir) = Path::new(&path).parent() {
+                std::fs::create_dir_all(dir)
+                    .map_err(|e| anyhow::anyhow!("Cannot create {}: {}", dir.display(), e))?;
+            }
+            std::fs::write(&path, json)
+                .map_err(|e| anyhow::anyhow!("Cannot write {}: {}", path, e))?;
+            return Ok(Response::ok_value(serde_json::json!(path)));
+        }
+        Command::StateLoad { path, timeout } => {
+            let text = std::fs::read_to_string(&path)
+                .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path, e))?;
+            let storage_state: playwright_rs::StorageState = serde_json::from_str(&text)
+                .map_err(|e| anyhow::anyhow!("{} is not a storage state file: {}", path, e))?;
+            ensure_recreatable(state, "state load")?;
+            recreate_context(state, Some(storage_state), timeout).await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::StateSnapshotSave { name } => {
+            let snapshot = StateSnapshot {
+                storage_state: state.page.context()?.storage_state().await?,
+                headers: state.headers.clone(),
+                viewport: state.settings.viewport.clone(),
+                url: state.page_opened.then(|| state.page.url()),
+            };
+            state.state_snapshots.insert(name, snapshot);
+            return Ok(Response::ok_empty());
+        }
+        Command::StateSnapshotRestore { name, timeout } => {
+            let Some(snapshot) = state.state_snapshots.get(&name) else {
+                anyhow::bail!(
+                    "No state snapshot named '{}'. Save one with 'plwr snapshot-state save {}'.",
+                    name,
+                    name
+                );
+            };
+            ensure_recreatable(state, "snapshot-state restore")?;
+            let storage_state = snapshot.storage_state.clone();
+            let url = snapshot.url.clone();
+            state.headers = snapshot.headers.clone();
+            state.settings.viewport = snapshot.viewport.clone();
+            // Open the snapshot's URL rather than the current one.
+            state.page_opened = false;
+            recreate_context(state, Some(storage_state), timeout).await?;
+            if let Some(url) = url {
+                let open = Command::Open {
+                    url,
+                    timeout,
+                    show_redirects: false,
+                };
+                Box::pin(handle_command(state, open)).await?;
+            }
+            return Ok(Response::ok_empty());
+        }
+        Command::Emulate {
+            media,
+            color_scheme,
+            reduced_motion,
+            forced_colors,
+        } => {
+            let options: Vec<(&str, &str)> = [
+                ("media", media.as_deref()),
+                ("colorScheme", color_scheme.as_deref()),
+                ("reducedMotion", reduced_motion.as_deref()),
+                ("forcedColors", forced_colors.as_deref()),
+            ]
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect();
+            pw_ext::emulate_media(&state.page, &options).await?;
+            for (name, value) in options {
+                if value == "no-override" {
+                    state.settings.media.remove(name);
+                } else {
+                    state.settings.media.insert(name, value.to_string());
+                }
+            }
+            return Ok(Response::ok_empty());
+        }
+        Command::CacheDisable | Command::CacheEnable => {
+            let disabled = matches!(command, Command::CacheDisable);
+            let devtools = devtools(state).await?;
+            devtools
+                .send("Network.enable", serde_json::json!({}))
+                .await?;
+            devtools
+                .send(
+                    "Network.setCacheDisabled",
+                    serde_json::json!({ "cacheDisabled": disabled }),
+                )
+                .await?;
+            state.settings.cache_disabled = disabled;
+            return Ok(Response::ok_empty());
+        }
+        Command::Status => {
+            // Each part gives up on its own, so a stuck page doesn't hang
+            // status, which is how you'd find out about it.
+            let ctx = state.page.context()?;
+            let cookies = status_part(pw_ext::get_cookies(&ctx))
+                .await
+                .map(|cookies| cookies.len());
+            let mut tabs = Vec::new();
+            for page in ctx.pages() {
+                tabs.push(serde_json::json!({
+                    "url": page.url(),
+                    "title": status_part(page.title()).await,
+                }));
+            }
+            let viewport = match &state.settings.viewport {
+                Some(viewport) => serde_json::json!(viewport),
+                // A connected browser keeps its own window size
+                None if state.cdp => serde_json::Value::Null,
+                None => serde_json::json!({ "width": 1280, "height": 720 }),
+            };
+            let (product, heap) = match status_part(devtools(state)).await {
+                Some(devtools) => (
+                    status_part(devtools.send("Browser.getVersion", serde_json::json!({}))).await,
+                    status_part(devtools.send("Runtime.getHeapUsage", serde_json::json!({}))).await,
+                ),
+                None => (None, None),
+            };
+            // "HeadlessChrome/141.0.7390.37"
+            let product = product.as_ref().and_then(|v| v["product"].as_str());
+            let heap = heap.unwrap_or_default();
+            let url = state.page_opened.then(|| state.page.url());
+            let browser_pid = match &state.browser {
+                Some((browser, _)) => tokio::time::timeout(STATUS_TIMEOUT, browser_pid(browser))
+                    .await
+                    .ok()
+                    .flatten(),
+                None => None,
+            };
+            return Ok(Response::ok_value(serde_json::json!({
+                "pid": std::process::id(),
+                "browser": {
+                    "name": "chromium",
+                    "version": product.map(|p| p.split_once('/').map_or(p, |(_, v)| v)),
+                    "pid": browser_pid,
+                },
+                "headed": state.headed,
+                "cdp": state.cdp,
+                "url": url,
+                "tabs": tabs,
+                "headers": state.headers,
+                "cookies": cookies,
+                "viewport": viewport,
+                "memory": {
+                    "jsHeapUsed": heap["usedSize"],
+                    "jsHeapTotal": heap["totalSize"],
+                },
+                "uptime": state.started.elapsed().as_secs(),
+            })));
+        }
+        Command::CacheClear => {
+            devtools(state)
+                .await?
+                .send("Network.clearBrowserCache", serde_json::json!({}))
+                .await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::LoginReplay { steps, timeout } => {
+            let count = steps.len();
+            for (i, step) in steps.into_iter().enumerate() {
+                let commands = match step {
+                    LoginStep::Open { url } => vec![Command::Open {
+                        url,
+                        timeout,
+                        show_redirects: false,
+                    }],
+                    LoginStep::Fill { selector, value } => vec![Command::Fill {
+                        selector,
+                        text: value,
+                        timeout,
+                    }],
+                    LoginStep::Click { selector } => vec![Command::Click {
+                        selector,
+                        timeout,
+                        modifiers: Vec::new(),
+                        button: None,
+                        position: None,
+                        click_count: None,
+                        force: false,
+                        trial: false,
+                    }],
+                    LoginStep::Press { selector, key } => vec![
+                        Command::Focus { selector, timeout },
+                        Command::Press { keys: vec![key] },
+                    ],
+                    LoginStep::Select { selector, value } => vec![Command::Select {
+                        selector,
+                        values: vec![value],
+                        by_label: false,
+                        by_index: false,
+                        timeout,
+                    }],
+                };
+                for command in commands {
+                    let response = Box::pin(handle_command(state, command)).await?;
+                    if !response.ok {
+                        return Ok(Response::err(format!(
+                            "Step {} failed: {}",
+                            i + 1,
+                            response.error.unwrap_or_default()
+                        )));
+                    }
+                }
+            }
+            return Ok(Response::ok_value(serde_json::json!(format!(
+                "Replayed {} steps",
+                count
+            ))));
+        }
+        Command::ClearSiteData {
+            origin,
+            cookies,
+            storage,
+            cache,
+        } => {
+            let url =
+                match origin {
+                    Some(origin) => origin,
+                    None if state.page_opened => state.page.url(),
+                    None => return Ok(Response::err(
+                        "No page open and no --origin provided. Use --origin to specify the site."
+                            .to_string(),
+                    )),
+                };
+            let Some((scheme, rest)) = url.split_once("://") else {
+                anyhow::bail!("Not a URL: {}", url);
+            };
+            let host = rest.split(['/', '?', '#']).next().unwrap_or("");
+            let origin = format!("{}://{}", scheme, host);
+            let mut types = Vec::new();
+            if cookies {
+                types.push("cookies");
+            }
+            if storage {
+                types.extend([
+                    "local_storage",
+                    "indexeddb",
+                    "websql",
+                    "file_systems",
+                    "service_workers",
+                ]);
+            }
+            if cache {
+                types.extend(["cache_storage", "shader_cache"]);
+            }
+            if types.is_empty() {
+                types.push("all");
+            }
+            devtools(state)
+                .await?
+                .send(
+                    "Storage.clearDataForOrigin",
+                    serde_json::json!({ "origin": origin, "storageTypes": types.join(",") }),
+                )
                 .await?;
             return Ok(Response::ok_empty());
         }
+        Command::SwBypass { enabled } => {
+            let devtools = devtools(state).await?;
+            devtools
+                .send("Network.enable", serde_json::json!({}))
+                .await?;
+            devtools
+                .send(
+                    "Network.setBypassServiceWorker",
+                    serde_json::json!({ "bypass": enabled }),
+                )
+                .await?;
+            state.settings.sw_bypass = enabled;
+            return Ok(Response::ok_empty());
+        }
         Command::ClipboardCopy { selector, timeout } => {
             ensure_clipboard_permissions(state).await?;
             let loc = state.page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
 
             // For <img> and <canvas> elements, copies as image/png.
             // For everything else, copies textContent.
-            let escaped = selector.replace('\\', "\\\\").replace('\'', "\\'");
-            let js = format!(
-                r#"async () => {{
-                    const el = document.querySelector('{}');
-                    if (!el) throw new Error('No element found');
-                    const tag = el.tagName.toLowerCase();
-                    if (tag === 'img') {{
-                        const resp = await fetch(el.src);
-                        const blob = await resp.blob();
-                        const pngBlob = await createImageBitmap(blob).then(bmp => {{
-                            const c = document.createElement('canvas');
-                            c.width = bmp.width;
-                            c.height = bmp.height;
-                            c.getContext('2d').drawImage(bmp, 0, 0);
-                            return new Promise(r => c.toBlob(r, 'image/png'));
-                        }});
-                        await navigator.clipboard.write([new ClipboardItem({{'image/png': pngBlob}})]);
-                        return 'image';
-                    }} else if (tag === 'canvas') {{
-                        const blob = await new Promise(r => el.toBlob(r, 'image/png'));
-                        await navigator.clipboard.write([new ClipboardItem({{'image/png': blob}})]);
-                        return 'image';
-                    }} else {{
-                        const text = el.textContent || '';
-                        await navigator.clipboard.writeText(text);
-                        return 'text';
-                    }}
-                }}"#,
-                escaped
-            );
-            pw_ext::page_evaluate_value(&state.page, &js).await?;
+            let js = r#"async el => {
+                const tag = el.tagName.toLowerCase();
+                if (tag === 'img') {
+                    const resp = await fetch(el.src);
+                    const blob = await resp.blob();
+                    const pngBlob = await createImageBitmap(blob).then(bmp => {
+                        const c = document.createElement('canvas');
+                        c.width = bmp.width;
+                        c.height = bmp.height;
+                        c.getContext('2d').drawImage(bmp, 0, 0);
+                        return new Promise(r => c.toBlob(r, 'image/png'));
+                    });
+                    await navigator.clipboard.write([new ClipboardItem({'image/png': pngBlob})]);
+                    return 'image';
+                } else if (tag === 'canvas') {
+                    const blob = await new Promise(r => el.toBlob(r, 'image/png'));
+                    await navigator.clipboard.write([new ClipboardItem({'image/png': blob})]);
+                    return 'image';
+                } else {
+                    const text = el.textContent || '';
+                    await navigator.clipboard.writeText(text);
+                    return 'text';
+                }
+            }"#;
+            pw_ext::locator_eval_on_selector(&state.page, &selector, js).await?;
             return Ok(Response::ok_empty());
         }
         Command::ClipboardPaste => {
@@ -616,23 +2538,83 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             pw_ext::page_evaluate_value(&state.page, js).await?;
             return Ok(Response::ok_empty());
         }
-        _ => {}
-    }
-
-    let page = &state.page;
-
-    match command {
-        Command::Stop => {
-            if state.cdp {
-                state.page.close().await.ok();
-                return Ok(Response::ok_empty());
-            }
-            if let Some(vs) = state.video.take() {
-                let ctx = state.page.context()?;
-                state.page.close().await?;
-                ctx.close().await?;
-                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
-
+        Command::ClipboardGet => {
+            ensure_clipboard_permissions(state).await?;
+            let text = pw_ext::page_evaluate_value(
+                &state.page,
+                "async () => await navigator.clipboard.readText()",
+            )
+            .await?;
+            return Ok(Response::ok_value(serde_json::Value::String(text)));
+        }
+        Command::ClipboardSet { text } => {
+            ensure_clipboard_permissions(state).await?;
+            let js = format!(
+                "async () => {{ await navigator.clipboard.writeText({}); }}",
+                serde_json::to_string(&text)?
+            );
+            pw_ext::page_evaluate_value(&state.page, &js).await?;
+            return Ok(Response::ok_empty());
+        }
+        Command::Download {
+            selector,
+            path,
+            timeout,
+        } => {
+            install_download_handler(state).await?;
+            state.download.lock().unwrap().take();
+            let loc = state.page.locator(&selector).await;
+            loc.click(Some(ClickOptions {
+                timeout: Some(timeout as f64),
+                ..Default::default()
+            }))
+            .await?;
+
+            let start = std::time::Instant::now();
+            let download = loop {
+                if let Some(d) = state.download.lock().unwrap().take() {
+                    break d;
+                }
+                if start.elapsed().as_millis() as u64 > timeout {
+                    anyhow::bail!(
+                        "Timeout {}ms exceeded waiting for a download. [selector: {}]",
+                        timeout,
+                        selector
+                    );
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            };
+
+            let mut dest = std::path::PathBuf::from(path);
+            if dest.is_dir() {
+                dest = dest.join(download.suggested_filename());
+            }
+            download.save_as(&dest).await?;
+            if let Some(failure) = download.failure().await? {
+                anyhow::bail!("Download failed: {}", failure);
+            }
+            return Ok(Response::ok_value(serde_json::Value::String(
+                dest.to_string_lossy().to_string(),
+            )));
+        }
+        _ => {}
+    }
+
+    let page = &state.page;
+
+    match command {
+        Command::Stop => {
+            save_har_update(state).await?;
+            if state.cdp {
+                state.page.close().await.ok();
+                return Ok(Response::ok_empty());
+            }
+            if let Some(vs) = state.video.take() {
+                let ctx = state.page.context()?;
+                state.page.close().await?;
+                ctx.close().await?;
+                tokio::time::sleep(std::time::Duration::from_millis(500)).await;
+
                 let webm = std::fs::read_dir(&vs.temp_dir)?
                     .filter_map(|e| e.ok())
                     .find(|e| e.path().extension().is_some_and(|ext| ext == "webm"))
@@ -656,6 +2638,9 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
                     }
                 }
                 std::fs::remove_dir_all(&vs.temp_dir).ok();
+            } else if state.profile {
+                // Closing the persistent context writes the profile to disk.
+                state.page.context()?.close().await?;
             }
             Ok(Response::ok_empty())
         }
@@ -666,10 +2651,28 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
         }
 
         Command::Url => Ok(Response::ok_value(serde_json::Value::String(page.url()))),
+        Command::Ping => {
+            pw_ext::page_evaluate_value(page, "1").await?;
+            Ok(Response::ok_empty())
+        }
+
+        Command::SessionInfo => {
+            let (url, title) = if state.page_opened {
+                (Some(page.url()), Some(page.title().await?))
+            } else {
+                (None, None)
+            };
+            Ok(Response::ok_value(serde_json::json!({
+                "pid": std::process::id(),
+                "url": url,
+                "title": title,
+                "uptime": state.started.elapsed().as_secs(),
+            })))
+        }
 
         Command::Wait { selector, timeout } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
             Ok(Response::ok_empty())
         }
 
@@ -757,15 +2760,32 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             timeout,
             modifiers,
             button,
+            position,
+            click_count,
+            force,
+            trial,
         } => {
             let loc = page.locator(&selector).await;
-            loc.click(Some(ClickOptions {
-                timeout: Some(timeout as f64),
-                modifiers: parse_modifiers(&modifiers),
-                button: parse_button(button.as_deref()),
-                ..Default::default()
-            }))
-            .await?;
+            let clicked = loc
+                .click(Some(ClickOptions {
+                    timeout: Some(timeout as f64),
+                    modifiers: parse_modifiers(&modifiers),
+                    button: parse_button(button.as_deref()),
+                    position: position.map(|(x, y)| Position { x, y }),
+                    click_count,
+                    force: force.then_some(true),
+                    trial: trial.then_some(true),
+                    ..Default::default()
+                }))
+                .await;
+            match clicked {
+                Ok(()) => Ok(Response::ok_empty()),
+                Err(e) => Ok(error_with_suggestions(page, &selector, e.into()).await),
+            }
+        }
+
+        Command::Tap { selector, timeout } => {
+            pw_ext::locator_tap(page, &selector, timeout).await?;
             Ok(Response::ok_empty())
         }
 
@@ -786,24 +2806,87 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             Ok(Response::ok_empty())
         }
 
-        Command::Press { key } => match page.keyboard().press(&key, None).await {
-            Ok(()) => Ok(Response::ok_empty()),
-            Err(e) => {
-                let msg = clean_error(anyhow::anyhow!(e));
-                if msg.contains("Unknown key") {
-                    Ok(Response::err(format!(
-                        "{msg}\n\n\
-                            Valid keys: a-z A-Z 0-9, \
-                            Backspace Tab Enter Escape Space Delete Insert, \
-                            ArrowUp ArrowDown ArrowLeft ArrowRight Home End PageUp PageDown, \
-                            F1-F12, Control Shift Alt Meta, \
-                            any US keyboard character: !@#$%^&*()_+-=[]{{}}\\|;':\",./<>?`~\n\
-                            Chords: Control+c, Shift+Enter, Alt+Tab, Meta+a"
-                    )))
-                } else {
-                    Ok(Response::err(msg))
+        Command::FillForm { fields, timeout } => {
+            for (selector, value) in fields {
+                let loc = page.locator(&selector).await;
+                let select_opts = Some(SelectOptions {
+                    timeout: Some(timeout as f64),
+                    ..Default::default()
+                });
+                match value {
+                    serde_json::Value::Bool(checked) => {
+                        let opts = Some(CheckOptions {
+                            timeout: Some(timeout as f64),
+                            ..Default::default()
+                        });
+                        if checked {
+                            loc.check(opts).await?;
+                        } else {
+                            loc.uncheck(opts).await?;
+                        }
+                    }
+                    serde_json::Value::Array(items) => {
+                        let values: Vec<SelectOption> = items
+                            .iter()
+                            .map(|v| SelectOption::Value(json_text(v)))
+                            .collect();
+                        loc.select_option_multiple(&values, select_opts).await?;
+                    }
+                    value => {
+                        let text = json_text(&value);
+                        let tag = pw_ext::locator_eval_on_selector_all(
+                            page,
+                            &selector,
+                            "els => els.length ? els[0].tagName : ''",
+                        )
+                        .await?;
+                        if tag == "SELECT" {
+                            loc.select_option(SelectOption::Value(text), select_opts)
+                                .await?;
+                        } else {
+                            loc.fill(
+                                &text,
+                                Some(FillOptions {
+                                    timeout: Some(timeout as f64),
+                                    ..Default::default()
+                                }),
+                            )
+                            .await?;
+                        }
+                    }
                 }
             }
+            Ok(Response::ok_empty())
+        }
+
+        Command::Clear { selector, timeout } => {
+            let loc = page.locator(&selector).await;
+            loc.clear(Some(FillOptions {
+                timeout: Some(timeout as f64),
+                ..Default::default()
+            }))
+            .await?;
+            Ok(Response::ok_empty())
+        }
+
+        Command::Press { keys } => {
+            let keyboard = page.keyboard();
+            for key in keys {
+                if let Err(e) = keyboard.press(&key, None).await {
+                    return Ok(key_error(e));
+                }
+            }
+            Ok(Response::ok_empty())
+        }
+
+        Command::KeyDown { key } => match page.keyboard().down(&key).await {
+            Ok(()) => Ok(Response::ok_empty()),
+            Err(e) => Ok(key_error(e)),
+        },
+
+        Command::KeyUp { key } => match page.keyboard().up(&key).await {
+            Ok(()) => Ok(Response::ok_empty()),
+            Err(e) => Ok(key_error(e)),
         },
 
         Command::Type { text, delay } => {
@@ -813,6 +2896,11 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             Ok(Response::ok_empty())
         }
 
+        Command::InsertText { text } => {
+            page.keyboard().insert_text(&text).await?;
+            Ok(Response::ok_empty())
+        }
+
         Command::Exists { selector } => {
             let loc = page.locator(&selector).await;
             let n = tokio::time::timeout(CHANNEL_TIMEOUT, loc.count())
@@ -826,29 +2914,102 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             Ok(Response::ok_value(serde_json::Value::Bool(n > 0)))
         }
 
-        Command::Text { selector, timeout } => {
+        Command::IsVisible { selector } => {
+            let loc = page.locator(&selector).await;
+            let visible = loc.is_visible().await?;
+            Ok(Response::ok_value(serde_json::Value::Bool(visible)))
+        }
+
+        Command::IsEnabled { selector, timeout } => {
+            let loc = page.locator(&selector).await;
+            wait_for_state(&loc, &selector, timeout, WaitState::Attached).await?;
+            let enabled = loc.is_enabled().await?;
+            Ok(Response::ok_value(serde_json::Value::Bool(enabled)))
+        }
+
+        Command::IsChecked { selector, timeout } => {
+            let loc = page.locator(&selector).await;
+            wait_for_state(&loc, &selector, timeout, WaitState::Attached).await?;
+            let checked = loc.is_checked().await?;
+            Ok(Response::ok_value(serde_json::Value::Bool(checked)))
+        }
+
+        Command::IsEditable { selector, timeout } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Attached).await?;
+            let editable = loc.is_editable().await?;
+            Ok(Response::ok_value(serde_json::Value::Bool(editable)))
+        }
+
+        Command::Text {
+            selector,
+            timeout,
+            all,
+            trim,
+        } => {
+            let loc = page.locator(&selector).await;
+            if let Err(e) = wait_for_state(&loc, &selector, timeout, WaitState::Visible).await {
+                return Ok(error_with_suggestions(page, &selector, e).await);
+            }
+            let clean = |text: String| {
+                if trim {
+                    text.split_whitespace().collect::<Vec<_>>().join(" ")
+                } else {
+                    text
+                }
+            };
+            if all {
+                let js = "els => JSON.stringify(els.map(el => el.textContent || ''))";
+                let val = pw_ext::locator_eval_on_selector_all(page, &selector, js).await?;
+                let texts: Vec<String> = serde_json::from_str(&val)?;
+                let texts = texts
+                    .into_iter()
+                    .map(|t| serde_json::Value::String(clean(t)))
+                    .collect();
+                return Ok(Response::ok_value(serde_json::Value::Array(texts)));
+            }
             let text = loc.text_content().await?.unwrap_or_default();
-            Ok(Response::ok_value(serde_json::Value::String(text)))
+            Ok(Response::ok_value(serde_json::Value::String(clean(text))))
         }
 
         Command::Attr {
             selector,
             name,
             timeout,
+            all,
         } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
+            if all {
+                let js = format!(
+                    "els => JSON.stringify(els.map(el => el.getAttribute({})))",
+                    serde_json::to_string(&name)?
+                );
+                let val = pw_ext::locator_eval_on_selector_all(page, &selector, &js).await?;
+                let values: serde_json::Value = serde_json::from_str(&val)?;
+                return Ok(Response::ok_value(values));
+            }
             match loc.get_attribute(&name).await? {
                 Some(val) => Ok(Response::ok_value(serde_json::Value::String(val))),
                 None => Ok(Response::ok_value(serde_json::Value::Null)),
             }
         }
 
-        Command::Count { selector } => {
-            let loc = page.locator(&selector).await;
-            let n = tokio::time::timeout(CHANNEL_TIMEOUT, loc.count())
+        Command::Count {
+            selector,
+            visible,
+            enabled,
+            matching,
+        } => {
+            let mut filtered = selector.clone();
+            if let Some(visible) = visible {
+                filtered = format!("{} >> visible={}", filtered, visible);
+            }
+            if let Some(regex) = &matching {
+                filtered = format!("{} >> internal:has-text={}", filtered, regex);
+            }
+            let loc = page.locator(&filtered).await;
+            let mut n = tokio::time::timeout(CHANNEL_TIMEOUT, loc.count())
                 .await
                 .map_err(|_| {
                     anyhow::anyhow!(
@@ -856,6 +3017,15 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
                         selector
                     )
                 })??;
+            if let Some(enabled) = enabled {
+                let mut matching = 0;
+                for i in 0..n {
+                    if loc.nth(i as i32).is_enabled().await? == enabled {
+                        matching += 1;
+                    }
+                }
+                n = matching;
+            }
             Ok(Response::ok_value(serde_json::json!(n)))
         }
 
@@ -874,10 +3044,110 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             Ok(Response::ok_empty())
         }
 
+        Command::FileChooser {
+            selector,
+            paths,
+            timeout,
+        } => {
+            // Playwright's fileChooser event isn't exposed by playwright-rs, so
+            // intercept the chooser through DevTools: it's never shown, and we
+            // get the <input type=file> that opened it, attached or not.
+            let session = pw_ext::new_cdp_session(page).await.map_err(|e| {
+                anyhow::anyhow!("Cannot open a DevTools session (Chromium only): {}", e)
+            })?;
+            let mut events = session.events().await;
+            let chosen = async {
+                session.send("Page.enable", serde_json::json!({})).await?;
+                session
+                    .send(
+                        "Page.setInterceptFileChooserDialog",
+                        serde_json::json!({ "enabled": true }),
+                    )
+                    .await?;
+                page.locator(&selector)
+                    .await
+                    .click(Some(ClickOptions {
+                        timeout: Some(timeout as f64),
+                        ..Default::default()
+                    }))
+                    .await?;
+
+                let opened =
+                    tokio::time::timeout(std::time::Duration::from_millis(timeout), async {
+                        while let Some((method, params)) = events.recv().await {
+                            if method == "Page.fileChooserOpened" {
+                                return params["backendNodeId"].as_u64();
+                            }
+                        }
+                        None
+                    })
+                    .await;
+                let Ok(Some(node)) = opened else {
+                    anyhow::bail!(
+                        "Timeout {}ms exceeded waiting for a file chooser. [selector: {}]",
+                        timeout,
+                        selector
+                    );
+                };
+                let input = session
+                    .send(
+                        "DOM.resolveNode",
+                        serde_json::json!({ "backendNodeId": node }),
+                    )
+                    .await?;
+                session
+                    .send(
+                        "DOM.setFileInputFiles",
+                        serde_json::json!({
+                            "files": paths,
+                            "objectId": input["object"]["objectId"],
+                        }),
+                    )
+                    .await?;
+                Ok(())
+            }
+            .await;
+            // Detaching also turns the interception off again.
+            let _ = session.detach().await;
+            chosen?;
+            Ok(Response::ok_empty())
+        }
+
+        Command::Highlight { selector, duration } => {
+            let js = format!(
+                r#"els => {{
+                    const overlays = els.map(el => {{
+                        const r = el.getBoundingClientRect();
+                        const o = document.createElement('div');
+                        o.setAttribute('data-plwr-highlight', '');
+                        Object.assign(o.style, {{
+                            position: 'absolute',
+                            left: (r.left + window.scrollX) + 'px',
+                            top: (r.top + window.scrollY) + 'px',
+                            width: r.width + 'px',
+                            height: r.height + 'px',
+                            outline: '2px solid #e0f',
+                            background: 'rgba(238, 0, 255, 0.15)',
+                            zIndex: '2147483647',
+                            pointerEvents: 'none',
+                        }});
+                        document.documentElement.appendChild(o);
+                        return o;
+                    }});
+                    setTimeout(() => overlays.forEach(o => o.remove()), {duration});
+                    return String(els.length);
+                }}"#
+            );
+            let count = pw_ext::locator_eval_on_selector_all(page, &selector, &js).await?;
+            let count: u64 = count.parse()?;
+            Ok(Response::ok_value(serde_json::json!(count)))
+        }
+
         Command::Select {
             selector,
             values,
             by_label,
+            by_index,
             timeout,
         } => {
             let loc = page.locator(&selector).await;
@@ -888,13 +3158,17 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             let select_values: Vec<SelectOption> = values
                 .into_iter()
                 .map(|v| {
-                    if by_label {
-                        SelectOption::Label(v)
+                    if by_index {
+                        v.parse()
+                            .map(SelectOption::Index)
+                            .map_err(|_| anyhow::anyhow!("Invalid option index '{}'", v))
+                    } else if by_label {
+                        Ok(SelectOption::Label(v))
                     } else {
-                        SelectOption::Value(v)
+                        Ok(SelectOption::Value(v))
                     }
                 })
-                .collect();
+                .collect::<Result<_>>()?;
             if select_values.len() == 1 {
                 loc.select_option(select_values.into_iter().next().unwrap(), opts)
                     .await?;
@@ -904,30 +3178,51 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             Ok(Response::ok_empty())
         }
 
-        Command::Hover { selector, timeout } => {
+        Command::Hover {
+            selector,
+            timeout,
+            force,
+            trial,
+        } => {
             let loc = page.locator(&selector).await;
             loc.hover(Some(HoverOptions {
                 timeout: Some(timeout as f64),
+                force: force.then_some(true),
+                trial: trial.then_some(true),
                 ..Default::default()
             }))
             .await?;
             Ok(Response::ok_empty())
         }
 
-        Command::Check { selector, timeout } => {
+        Command::Check {
+            selector,
+            timeout,
+            force,
+            trial,
+        } => {
             let loc = page.locator(&selector).await;
             loc.check(Some(CheckOptions {
                 timeout: Some(timeout as f64),
+                force: force.then_some(true),
+                trial: trial.then_some(true),
                 ..Default::default()
             }))
             .await?;
             Ok(Response::ok_empty())
         }
 
-        Command::Uncheck { selector, timeout } => {
+        Command::Uncheck {
+            selector,
+            timeout,
+            force,
+            trial,
+        } => {
             let loc = page.locator(&selector).await;
             loc.uncheck(Some(CheckOptions {
                 timeout: Some(timeout as f64),
+                force: force.then_some(true),
+                trial: trial.then_some(true),
                 ..Default::default()
             }))
             .await?;
@@ -939,12 +3234,16 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             timeout,
             modifiers,
             button,
+            force,
+            trial,
         } => {
             let loc = page.locator(&selector).await;
             loc.dblclick(Some(ClickOptions {
                 timeout: Some(timeout as f64),
                 modifiers: parse_modifiers(&modifiers),
                 button: parse_button(button.as_deref()),
+                force: force.then_some(true),
+                trial: trial.then_some(true),
                 ..Default::default()
             }))
             .await?;
@@ -953,7 +3252,7 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
 
         Command::Focus { selector, timeout } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
             loc.click(Some(ClickOptions {
                 trial: Some(true),
                 timeout: Some(timeout as f64),
@@ -966,32 +3265,112 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
 
         Command::Blur { selector, timeout } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
             pw_ext::locator_blur(page, &selector).await?;
             Ok(Response::ok_empty())
         }
 
         Command::InnerHtml { selector, timeout } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
             let html = loc.inner_html().await?;
             Ok(Response::ok_value(serde_json::Value::String(html)))
         }
 
+        Command::InnerText {
+            selector,
+            timeout,
+            all,
+        } => {
+            let loc = page.locator(&selector).await;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
+            if all {
+                let js = "els => JSON.stringify(els.map(el => el.innerText))";
+                let val = pw_ext::locator_eval_on_selector_all(page, &selector, js).await?;
+                let texts: serde_json::Value = serde_json::from_str(&val)?;
+                return Ok(Response::ok_value(texts));
+            }
+            let text = loc.inner_text().await?;
+            Ok(Response::ok_value(serde_json::Value::String(text)))
+        }
+
         Command::InputValue { selector, timeout } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
             let val = loc.input_value(None).await?;
             Ok(Response::ok_value(serde_json::Value::String(val)))
         }
 
         Command::ScrollIntoView { selector, timeout } => {
             let loc = page.locator(&selector).await;
-            wait_for_visible(&loc, &selector, timeout).await?;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
             pw_ext::locator_scroll_into_view(page, &selector).await?;
             Ok(Response::ok_empty())
         }
 
+        Command::Scroll {
+            selector,
+            by,
+            to,
+            timeout,
+        } => {
+            let action = match (by, to.as_deref()) {
+                (Some((x, y)), _) => format!(
+                    "el.scrollBy({{left: {}, top: {}, behavior: 'instant'}})",
+                    x, y
+                ),
+                (None, Some("top")) => "el.scrollTo({top: 0, behavior: 'instant'})".to_string(),
+                (None, Some("bottom")) => {
+                    "el.scrollTo({top: el.scrollHeight, behavior: 'instant'})".to_string()
+                }
+                (None, Some("left")) => "el.scrollTo({left: 0, behavior: 'instant'})".to_string(),
+                (None, Some("right")) => {
+                    "el.scrollTo({left: el.scrollWidth, behavior: 'instant'})".to_string()
+                }
+                (None, Some(other)) => anyhow::bail!(
+                    "Unknown scroll edge '{}'. Use top, bottom, left, or right.",
+                    other
+                ),
+                (None, None) => anyhow::bail!("Nothing to scroll: pass --by or --to"),
+            };
+            let js = format!("el => {{ {}; }}", action);
+            match selector {
+                // Scroll within a scrollable element (e.g. a sidebar or chat pane)
+                Some(sel) => {
+                    let loc = page.locator(&sel).await;
+                    wait_for_state(&loc, &sel, timeout, WaitState::Visible).await?;
+                    pw_ext::locator_eval_on_selector(page, &sel, &js).await?;
+                }
+                None => {
+                    let wrapper = format!(
+                        "() => {{ ({})(document.scrollingElement || document.documentElement); }}",
+                        js
+                    );
+                    pw_ext::page_evaluate_value(page, &wrapper).await?;
+                }
+            }
+            Ok(Response::ok_empty())
+        }
+
+        Command::Bbox { selector, timeout } => {
+            let loc = page.locator(&selector).await;
+            wait_for_state(&loc, &selector, timeout, WaitState::Visible).await?;
+            let js = r#"el => {
+                const r = el.getBoundingClientRect();
+                return {
+                    x: r.x,
+                    y: r.y,
+                    width: r.width,
+                    height: r.height,
+                    in_viewport: r.bottom > 0 && r.right > 0
+                        && r.top < window.innerHeight && r.left < window.innerWidth,
+                };
+            }"#;
+            let val = pw_ext::locator_eval_on_selector(page, &selector, js).await?;
+            let bbox: serde_json::Value = serde_json::from_str(&val)?;
+            Ok(Response::ok_value(bbox))
+        }
+
         Command::ComputedStyle {
             selector,
             properties,
@@ -1043,18 +3422,30 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             Ok(Response::ok_value(styles))
         }
 
-        Command::DialogAccept { prompt_text } => {
+        Command::DialogAccept {
+            prompt_text,
+            persist,
+        } => {
             install_dialog_handler(state).await?;
-            *state.dialog_action.lock().unwrap() = Some(DialogAction::Accept(prompt_text));
+            let mut dialog = state.dialog.lock().unwrap();
+            dialog.action = Some(DialogAction::Accept(prompt_text));
+            dialog.persist = persist;
             Ok(Response::ok_empty())
         }
 
-        Command::DialogDismiss => {
+        Command::DialogDismiss { persist } => {
             install_dialog_handler(state).await?;
-            *state.dialog_action.lock().unwrap() = Some(DialogAction::Dismiss);
+            let mut dialog = state.dialog.lock().unwrap();
+            dialog.action = Some(DialogAction::Dismiss);
+            dialog.persist = persist;
             Ok(Response::ok_empty())
         }
 
+        Command::DialogLast => match state.dialog.lock().unwrap().last_message.clone() {
+            Some(message) => Ok(Response::ok_value(serde_json::Value::String(message))),
+            None => Ok(Response::err("No dialog has been shown".to_string())),
+        },
+
         Command::Console => {
             let val = pw_ext::page_evaluate_value(
                 page,
@@ -1131,7 +3522,174 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             pw_ext::page_evaluate_value(page, "() => { window.__plwr_network = []; }").await?;
             Ok(Response::ok_empty())
         }
+        Command::SwList => {
+            let js = r#"async () => {
+                if (!navigator.serviceWorker) return '[]';
+                const registrations = await navigator.serviceWorker.getRegistrations();
+                return JSON.stringify(registrations.map(r => {
+                    const worker = r.active || r.waiting || r.installing;
+                    return {
+                        scope: r.scope,
+                        script: worker ? worker.scriptURL : null,
+                        state: worker ? worker.state : null,
+                    };
+                }));
+            }"#;
+            let val = pw_ext::page_evaluate_value(page, js).await?;
+            Ok(Response::ok_value(serde_json::from_str(&val)?))
+        }
+        Command::CacheStorageList => {
+            let js = r#"async () => {
+                if (!window.caches) return '[]';
+                const out = [];
+                for (const name of await caches.keys()) {
+                    const cache = await caches.open(name);
+                    out.push({ name, count: (await cache.keys()).length });
+                }
+                return JSON.stringify(out);
+            }"#;
+            let val = pw_ext::page_evaluate_value(page, js).await?;
+            Ok(Response::ok_value(serde_json::from_str(&val)?))
+        }
+        Command::CacheStorageDump { name } => {
+            let js = format!(
+                r#"async () => {{
+                    const name = {};
+                    if (!window.caches || !(await caches.has(name))) return 'null';
+                    const cache = await caches.open(name);
+                    const out = [];
+                    for (const request of await cache.keys()) {{
+                        const response = await cache.match(request);
+                        out.push({{
+                            url: request.url,
+                            method: request.method,
+                            status: response ? response.status : null,
+                            contentType: response ? response.headers.get('content-type') : null,
+                            size: response ? (await response.blob()).size : null,
+                        }});
+                    }}
+                    return JSON.stringify(out);
+                }}"#,
+                serde_json::to_string(&name)?
+            );
+            let val = pw_ext::page_evaluate_value(page, &js).await?;
+            if val == "null" {
+                return Ok(Response::err(format!(
+                    "No cache named '{}'. Use 'plwr cachestorage list' to see them.",
+                    name
+                )));
+            }
+            Ok(Response::ok_value(serde_json::from_str(&val)?))
+        }
+        Command::IdbList => {
+            let js = format!(
+                "async () => {{ {} return ({})(); }}",
+                IDB_HELPERS_JS, IDB_LIST_JS
+            );
+            let val = pw_ext::page_evaluate_value(page, &js).await?;
+            Ok(Response::ok_value(serde_json::from_str(&val)?))
+        }
+        Command::IdbDump { db, store, limit } => {
+            let js = format!(
+                "async () => {{ {} return ({})({}, {}, {}); }}",
+                IDB_HELPERS_JS,
+                IDB_DUMP_JS,
+                serde_json::to_string(&db)?,
+                serde_json::to_string(&store)?,
+                serde_json::to_string(&limit)?
+            );
+            let val = pw_ext::page_evaluate_value(page, &js).await?;
+            let mut result: serde_json::Value = serde_json::from_str(&val)?;
+            if let Some(error) = result["error"].as_str() {
+                return Ok(Response::err(error.to_string()));
+            }
+            Ok(Response::ok_value(result["records"].take()))
+        }
+        Command::SwUnregister => {
+            let js = r#"async () => {
+                if (!navigator.serviceWorker) return;
+                const registrations = await navigator.serviceWorker.getRegistrations();
+                await Promise.all(registrations.map(r => r.unregister()));
+            }"#;
+            pw_ext::page_evaluate_value(page, js).await?;
+            Ok(Response::ok_empty())
+        }
+
+        Command::Fetch {
+            method,
+            url,
+            mut headers,
+            body,
+            body_file,
+            include,
+            timeout,
+        } => {
+            let href = pw_ext::page_evaluate_value(
+                page,
+                &format!(
+                    "new URL({}, location.href).href",
+                    serde_json::to_string(&url)?
+                ),
+            )
+            .await
+            .map_err(|_| anyhow::anyhow!("Invalid URL: {}", url))?;
+            let url: String = serde_json::from_str(&href).unwrap_or(href);
+
+            // `header --match` rules are applied by the route handler, which
+            // API requests don't go through.
+            for (_, re, name, value) in &state.routes.lock().unwrap().headers {
+                if re.is_match(&url) && !headers.iter().any(|(n, _)| n.eq_ignore_ascii_case(name)) {
+                    headers.push((name.clone(), value.clone()));
+                }
+            }
+            let body = match (body, body_file) {
+                (_, Some(path)) => Some(
+                    std::fs::read(&path)
+                        .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", path, e))?,
+                ),
+                (body, None) => body.map(String::into_bytes),
+            };
+            let ctx = page.context()?;
+            let response = pw_ext::api_fetch(
+                &ctx,
+                &method,
+                &url,
+                &headers,
+                body.as_deref(),
+                timeout as f64,
+            )
+            .await?;
 
+            let is_json = response.headers.iter().any(|(name, value)| {
+                name.eq_ignore_ascii_case("content-type") && value.contains("json")
+            });
+            let body = match serde_json::from_slice::<serde_json::Value>(&response.body) {
+                Ok(json) if is_json => json,
+                _ => String::from_utf8_lossy(&response.body).to_string().into(),
+            };
+            if include {
+                let headers: serde_json::Map<String, serde_json::Value> = response
+                    .headers
+                    .into_iter()
+                    .map(|(name, value)| (name.to_ascii_lowercase(), value.into()))
+                    .collect();
+                return Ok(Response::ok_value(serde_json::json!({
+                    "status": response.status,
+                    "headers": headers,
+                    "body": body,
+                })));
+            }
+            if response.status >= 400 {
+                anyhow::bail!(
+                    "{} {} returned {} {}",
+                    method,
+                    url,
+                    response.status,
+                    response.status_text
+                );
+            }
+            Ok(Response::ok_value(body))
+        }
         Command::Eval { js } => {
             let wrapper = format!(
                 "() => {{ const __r = ({}); return typeof __r === 'object' ? JSON.stringify(__r) : __r; }}",
@@ -1151,66 +3709,875 @@ async fn handle_command(state: &mut State, command: Command) -> Result<Response>
             }
         }
 
-        Command::Screenshot { selector, path, .. } => {
-            let bytes = match &selector {
-                Some(sel) => {
-                    let loc = page.locator(sel).await;
-                    loc.screenshot(None).await?
-                }
-                None => page.screenshot(None).await?,
-            };
-            std::fs::write(&path, &bytes)?;
-            Ok(Response::ok_value(serde_json::Value::String(format!(
-                "Saved {} bytes to {}",
-                bytes.len(),
-                path
-            ))))
+        Command::Screenshot {
+            selector,
+            path,
+            timeout,
+            mask,
+            padding,
+            full_page,
+            format,
+            quality,
+            clip,
+            compare,
+            annotate,
+        } => {
+            let format = format.as_deref().unwrap_or("png");
+            let options = ScreenshotOptions {
+                screenshot_type: Some(match format {
+                    "jpeg" => ScreenshotType::Jpeg,
+                    _ => ScreenshotType::Png,
+                }),
+                quality: quality.filter(|_| format == "jpeg"),
+                full_page: Some(full_page),
+                clip: clip.map(|(x, y, width, height)| ScreenshotClip {
+                    x,
+                    y,
+                    width,
+                    height,
+                }),
+                timeout: Some(timeout as f64),
+                ..Default::default()
+            };
+            // The overlays come off again even if one selector fails
+            let bytes: Result<Vec<u8>> = async {
+                for sel in &mask {
+                    pw_ext::locator_eval_on_selector_all(page, sel, MASK_INSTALL_JS).await?;
+                }
+                for sel in &annotate {
+                    let js = format!(
+                        "els => ({})(els, {})",
+                        ANNOTATE_INSTALL_JS,
+                        serde_json::to_string(sel)?
+                    );
+                    pw_ext::locator_eval_on_selector_all(page, sel, &js).await?;
+                }
+                screenshot(page, selector.as_deref(), padding, options, timeout).await
+            }
+            .await;
+            let removed = if !mask.is_empty() || !annotate.is_empty() {
+                pw_ext::page_evaluate_value(page, OVERLAY_REMOVE_JS)
+                    .await
+                    .map(drop)
+            } else {
+                Ok(())
+            };
+            let mut bytes = bytes?;
+            removed?;
+            if format == "webp" {
+                bytes = png_to_webp(page, &bytes, quality).await?;
+            }
+            std::fs::write(&path, &bytes)?;
+            let saved = format!("Saved {} bytes to {}", bytes.len(), path);
+            let Some(compare) = compare else {
+                return Ok(Response::ok_value(serde_json::Value::String(saved)));
+            };
+            let verdict = compare_screenshot(page, &bytes, &compare).await?;
+            Ok(Response::ok_value(serde_json::Value::String(format!(
+                "{}\n{}",
+                saved, verdict
+            ))))
+        }
+
+        Command::Find { text, limit } => {
+            let js = format!(
+                "() => ({})({}, {}, {})",
+                FIND_CANDIDATES_JS,
+                serde_json::to_string(&text)?,
+                limit,
+                UNIQUE_SELECTOR_JS
+            );
+            let val = pw_ext::page_evaluate_value(page, &js).await?;
+            let mut found: Vec<serde_json::Value> = serde_json::from_str(&val)?;
+            for item in &mut found {
+                let Some(selectors) = item.get_mut("selectors").and_then(|s| s.as_array_mut())
+                else {
+                    continue;
+                };
+                let mut kept = Vec::new();
+                for sel in selectors.drain(..) {
+                    let Some(s) = sel.as_str() else { continue };
+                    if kept.contains(&sel) {
+                        continue;
+                    }
+                    if page.locator(s).await.count().await.unwrap_or(0) == 1 {
+                        kept.push(sel);
+                    }
+                }
+                *selectors = kept;
+            }
+            Ok(Response::ok_value(serde_json::Value::Array(found)))
+        }
+
+        Command::SelectorFor { selector, timeout } => {
+            let loc = page.locator(&selector).await;
+            wait_for_state(&loc, &selector, timeout, WaitState::Attached).await?;
+            let js = format!("els => ({})(els[0])", UNIQUE_SELECTOR_JS);
+            let unique = pw_ext::locator_eval_on_selector_all(page, &selector, &js).await?;
+            Ok(Response::ok_value(serde_json::Value::String(unique)))
+        }
+
+        Command::Pick { timeout } => {
+            if !state.headed {
+                anyhow::bail!(
+                    "pick needs a visible browser. Start the session with 'plwr start --headed'."
+                );
+            }
+            let install = format!("() => ({})({})", PICK_INSTALL_JS, UNIQUE_SELECTOR_JS);
+            let start = std::time::Instant::now();
+            loop {
+                // Navigating away drops the picker, so put it back
+                let status = match pw_ext::page_evaluate_value(page, PICK_POLL_JS).await {
+                    Ok(status) => status,
+                    Err(_) => "missing".to_string(),
+                };
+                if let Some(selector) = status.strip_prefix("picked:") {
+                    return Ok(Response::ok_value(serde_json::Value::String(
+                        selector.to_string(),
+                    )));
+                }
+                match status.as_str() {
+                    "cancelled" => anyhow::bail!("Pick cancelled"),
+                    "missing" => {
+                        pw_ext::page_evaluate_value(page, &install).await.ok();
+                    }
+                    _ => {}
+                }
+                if start.elapsed().as_millis() as u64 > timeout {
+                    pw_ext::page_evaluate_value(page, PICK_CANCEL_JS).await.ok();
+                    anyhow::bail!(
+                        "Timeout {}ms exceeded waiting for an element to be picked.",
+                        timeout
+                    );
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
         }
 
-        Command::Tree { selector, .. } => {
-            let walk_js = r#"el => {
-                function walk(el) {
-                    const node = { tag: el.tagName ? el.tagName.toLowerCase() : '#text' };
-                    if (el.id) node.id = el.id;
-                    if (el.className && typeof el.className === 'string' && el.className.trim())
-                        node.class = el.className.trim().split(/\s+/);
-                    if (el.attributes) {
-                        const attrs = {};
-                        for (const a of el.attributes) {
-                            if (a.name !== 'id' && a.name !== 'class' && !a.name.startsWith('data-plwr'))
-                                attrs[a.name] = a.value;
+        Command::LoginRecord { until, timeout } => {
+            if !state.headed {
+                anyhow::bail!(
+                    "login record needs a visible browser. Start the session with 'plwr start --headed'."
+                );
+            }
+            #[derive(serde::Deserialize)]
+            struct Poll {
+                steps: Vec<LoginStep>,
+                stopped: bool,
+                installed: bool,
+            }
+            let until = until.as_deref().map(glob::url_glob);
+            let install = format!(
+                "() => ({})({})",
+                LOGIN_RECORD_INSTALL_JS, UNIQUE_SELECTOR_JS
+            );
+            let mut steps = vec![LoginStep::Open { url: page.url() }];
+            let start = std::time::Instant::now();
+            loop {
+                // Navigating away drops the recorder, so put it back
+                let poll = pw_ext::page_evaluate_value(page, LOGIN_RECORD_POLL_JS)
+                    .await
+                    .ok()
+                    .and_then(|v| serde_json::from_str::<Poll>(&v).ok());
+                if let Some(poll) = poll {
+                    for step in poll.steps {
+                        // Typing fires an input event per keystroke; keep
+                        // the last value.
+                        if let (
+                            LoginStep::Fill { selector, .. },
+                            Some(LoginStep::Fill { selector: last, .. }),
+                        ) = (&step, steps.last())
+                        {
+                            if selector == last {
+                                steps.pop();
+                            }
                         }
-                        if (Object.keys(attrs).length > 0) node.attrs = attrs;
+                        steps.push(step);
+                    }
+                    if poll.stopped {
+                        break;
+                    }
+                    if !poll.installed {
+                        pw_ext::page_evaluate_value(page, &install).await.ok();
                     }
-                    const text = Array.from(el.childNodes)
-                        .filter(n => n.nodeType === 3)
-                        .map(n => n.textContent.trim())
-                        .filter(t => t)
-                        .join(' ');
-                    if (text) node.text = text;
-                    const children = Array.from(el.children).map(walk);
-                    if (children.length > 0) node.children = children;
-                    return node;
                 }
-                return JSON.stringify(walk(el));
+                if until.as_ref().is_some_and(|re| re.is_match(&page.url())) {
+                    break;
+                }
+                if start.elapsed().as_millis() as u64 > timeout {
+                    pw_ext::page_evaluate_value(page, LOGIN_RECORD_STOP_JS)
+                        .await
+                        .ok();
+                    anyhow::bail!(
+                        "Timeout {}ms exceeded while recording. Press Escape in the browser to stop.",
+                        timeout
+                    );
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            }
+            pw_ext::page_evaluate_value(page, LOGIN_RECORD_STOP_JS)
+                .await
+                .ok();
+            Ok(Response::ok_value(serde_json::to_value(steps)?))
+        }
+
+        Command::Meta => {
+            let js = r#"() => {
+                const content = sel => {
+                    const el = document.querySelector(sel);
+                    return el ? el.getAttribute('content') : null;
+                };
+                const canonical = document.querySelector('link[rel=canonical][href]');
+                const tags = prefix => {
+                    const found = {};
+                    const metas = document.querySelectorAll(
+                        `meta[property^="${prefix}:"], meta[name^="${prefix}:"]`);
+                    for (const meta of metas) {
+                        const key = (meta.getAttribute('property') || meta.getAttribute('name'))
+                            .slice(prefix.length + 1);
+                        const value = meta.getAttribute('content');
+                        if (!(key in found)) found[key] = value;
+                        else if (Array.isArray(found[key])) found[key].push(value);
+                        else found[key] = [found[key], value];
+                    }
+                    return found;
+                };
+                return JSON.stringify({
+                    title: document.title,
+                    description: content('meta[name=description]'),
+                    canonical: canonical ? canonical.href : null,
+                    robots: content('meta[name=robots]'),
+                    og: tags('og'),
+                    twitter: tags('twitter'),
+                });
             }"#;
+            let val = pw_ext::page_evaluate_value(page, js).await?;
+            let meta: serde_json::Value = serde_json::from_str(&val)?;
+            Ok(Response::ok_value(meta))
+        }
+
+        Command::StructuredData { microdata } => {
+            let js = format!(
+                r#"() => {{
+                    const found = [];
+                    for (const script of document.querySelectorAll('script[type="application/ld+json"]')) {{
+                        try {{
+                            found.push(JSON.parse(script.textContent));
+                        }} catch (e) {{
+                            found.push({{ parse_error: e.message, text: script.textContent.trim() }});
+                        }}
+                    }}
+                    if (!{microdata}) return JSON.stringify(found);
+
+                    const value = el => {{
+                        const tag = el.tagName;
+                        if (tag === 'META') return el.getAttribute('content');
+                        if (['IMG', 'AUDIO', 'VIDEO', 'SOURCE', 'IFRAME', 'EMBED', 'TRACK'].includes(tag)) return el.src;
+                        if (['A', 'AREA', 'LINK'].includes(tag)) return el.href;
+                        if (tag === 'OBJECT') return el.data;
+                        if (tag === 'TIME' && el.hasAttribute('datetime')) return el.getAttribute('datetime');
+                        if (tag === 'DATA' || tag === 'METER') return el.getAttribute('value');
+                        return el.textContent.replace(/\s+/g, ' ').trim();
+                    }};
+                    const item = scope => {{
+                        const out = {{}};
+                        if (scope.getAttribute('itemtype')) out['@type'] = scope.getAttribute('itemtype');
+                        const walk = node => {{
+                            for (const child of node.children) {{
+                                const names = (child.getAttribute('itemprop') || '').split(/\s+/).filter(Boolean);
+                                const nested = child.hasAttribute('itemscope');
+                                const v = names.length ? (nested ? item(child) : value(child)) : null;
+                                for (const name of names) {{
+                                    if (!(name in out)) out[name] = v;
+                                    else if (Array.isArray(out[name])) out[name].push(v);
+                                    else out[name] = [out[name], v];
+                                }}
+                                if (!nested) walk(child);
+                            }}
+                        }};
+                        walk(scope);
+                        return out;
+                    }};
+                    for (const scope of document.querySelectorAll('[itemscope]:not([itemprop])')) {{
+                        found.push(item(scope));
+                    }}
+                    return JSON.stringify(found);
+                }}"#
+            );
+            let val = pw_ext::page_evaluate_value(page, &js).await?;
+            let data: serde_json::Value = serde_json::from_str(&val)?;
+            Ok(Response::ok_value(data))
+        }
+
+        Command::AuditA11y {
+            tags,
+            axe_url,
+            timeout,
+        } => {
+            let axe_url = match axe_url {
+                Some(url) => Some(url),
+                None if AXE_SOURCE.is_empty() => Some(AXE_URL.to_string()),
+                // Evaluated rather than added as a <script>, so the page's
+                // Content-Security-Policy doesn't get in the way.
+                None => {
+                    let loaded = pw_ext::page_evaluate_value(page, "() => !!window.axe").await?;
+                    if loaded != "true" {
+                        let js = format!("() => {{\n{}\n}}", AXE_SOURCE);
+                        pw_ext::page_evaluate_value(page, &js).await?;
+                    }
+                    None
+                }
+            };
+            let js = format!(
+                "() => ({})({}, {})",
+                AXE_AUDIT_JS,
+                serde_json::to_string(&axe_url)?,
+                serde_json::to_string(&tags)?
+            );
+            let val = tokio::time::timeout(
+                std::time::Duration::from_millis(timeout),
+                pw_ext::page_evaluate_value(page, &js),
+            )
+            .await
+            .map_err(|_| anyhow::anyhow!("Timeout {}ms exceeded running axe-core.", timeout))??;
+            let violations: serde_json::Value = serde_json::from_str(&val)?;
+            Ok(Response::ok_value(violations))
+        }
+
+        Command::Markdown { selector, timeout } => {
+            let sel = selector.as_deref().unwrap_or("body");
+            let loc = page.locator(sel).await;
+            wait_for_state(&loc, sel, timeout, WaitState::Attached).await?;
+            let val = pw_ext::locator_eval_on_selector(page, sel, MARKDOWN_JS).await?;
+            let markdown: String = serde_json::from_str(&val)?;
+            Ok(Response::ok_value(serde_json::Value::String(markdown)))
+        }
+
+        Command::Links {
+            selector,
+            same_origin,
+            unique,
+            timeout,
+        } => {
             let sel = selector.as_deref().unwrap_or("html");
-            let val = pw_ext::locator_eval_on_selector(page, sel, walk_js).await?;
-            let json_str: String = serde_json::from_str(&val).unwrap_or(val);
-            let tree: serde_json::Value = serde_json::from_str(&json_str)?;
+            let loc = page.locator(sel).await;
+            wait_for_state(&loc, sel, timeout, WaitState::Attached).await?;
+            let js = format!(
+                r#"root => {{
+                    const seen = new Set();
+                    return Array.from(root.querySelectorAll('a[href]')).flatMap(a => {{
+                        const url = a.href;
+                        if ({same_origin} && new URL(url).origin !== location.origin) return [];
+                        if ({unique} && seen.has(url)) return [];
+                        seen.add(url);
+                        return [{{
+                            text: a.innerText.replace(/\s+/g, ' ').trim(),
+                            href: a.getAttribute('href'),
+                            absolute_url: url,
+                            rel: a.getAttribute('rel'),
+                            target: a.getAttribute('target'),
+                        }}];
+                    }});
+                }}"#
+            );
+            let val = pw_ext::locator_eval_on_selector(page, sel, &js).await?;
+            let links: serde_json::Value = serde_json::from_str(&val)?;
+            Ok(Response::ok_value(links))
+        }
+
+        Command::Table {
+            selector,
+            csv,
+            header_row,
+            timeout,
+        } => {
+            let loc = page.locator(&selector).await;
+            wait_for_state(&loc, &selector, timeout, WaitState::Attached).await?;
+            let js = format!(
+                r#"table => {{
+                    const text = cell => cell.innerText.replace(/\s+/g, ' ').trim();
+                    const cells = tr => Array.from(tr.cells)
+                        .flatMap(cell => Array(cell.colSpan || 1).fill(text(cell)));
+                    let rows = Array.from(table.rows);
+                    let header = null;
+                    const head = table.tHead && table.tHead.rows.length
+                        ? table.tHead.rows[table.tHead.rows.length - 1] : null;
+                    if (head) {{
+                        header = cells(head);
+                        rows = rows.filter(tr => tr.parentElement !== table.tHead);
+                    }} else if ({header_row} && rows.length) {{
+                        header = cells(rows.shift());
+                    }}
+                    return {{ header, rows: rows.map(cells) }};
+                }}"#
+            );
+            let val = pw_ext::locator_eval_on_selector(page, &selector, &js).await?;
+            let parsed: serde_json::Value = serde_json::from_str(&val)?;
+            let header: Option<Vec<String>> = serde_json::from_value(parsed["header"].clone())?;
+            let rows: Vec<Vec<String>> = serde_json::from_value(parsed["rows"].clone())?;
+
+            if csv {
+                let lines: Vec<String> = header
+                    .iter()
+                    .chain(rows.iter())
+                    .map(|row| {
+                        row.iter()
+                            .map(|c| csv_field(c))
+                            .collect::<Vec<_>>()
+                            .join(",")
+                    })
+                    .collect();
+                return Ok(Response::ok_value(serde_json::Value::String(
+                    lines.join("\n"),
+                )));
+            }
+            let rows = match header {
+                Some(header) => rows
+                    .into_iter()
+                    .map(|row| {
+                        let object = row
+                            .into_iter()
+                            .enumerate()
+                            .map(|(i, cell)| {
+                                let key = match header.get(i) {
+                                    Some(name) if !name.is_empty() => name.clone(),
+                                    _ => format!("column{}", i + 1),
+                                };
+                                (key, serde_json::Value::String(cell))
+                            })
+                            .collect();
+                        serde_json::Value::Object(object)
+                    })
+                    .collect(),
+                None => rows.into_iter().map(|row| row.into()).collect(),
+            };
+            Ok(Response::ok_value(serde_json::Value::Array(rows)))
+        }
+
+        Command::Tree {
+            selector,
+            outline,
+            depth,
+            max_text,
+            only,
+            ..
+        } => {
+            let options = serde_json::json!({
+                "depth": depth,
+                "maxText": max_text,
+                "only": only,
+                "layout": false,
+            });
+            let tree = dom_tree(page, selector.as_deref().unwrap_or("html"), options).await?;
+            if outline {
+                return Ok(Response::ok_value(serde_json::json!(tree::outline(&tree))));
+            }
             Ok(Response::ok_value(tree))
         }
 
         Command::Open { .. }
         | Command::Header { .. }
         | Command::HeaderClear
+        | Command::Lang { .. }
+        | Command::LangClear
+        | Command::Auth { .. }
+        | Command::AuthClear
+        | Command::Geo { .. }
+        | Command::GeoClear
         | Command::Cookie { .. }
         | Command::CookieList
         | Command::CookieClear
+        | Command::CookieImport { .. }
+        | Command::Alias { .. }
+        | Command::AliasList
+        | Command::AliasClear
+        | Command::Within { .. }
+        | Command::WithinClear
+        | Command::SnapshotSave { .. }
+        | Command::SnapshotDiff { .. }
+        | Command::NetLog { .. }
+        | Command::NetFailures { .. }
+        | Command::NetTiming { .. }
+        | Command::NetSave { .. }
+        | Command::NetSaveStop
+        | Command::NetBody { .. }
+        | Command::NetCapture
+        | Command::NetClear
+        | Command::HarStart { .. }
+        | Command::HarStop { .. }
+        | Command::Block { .. }
+        | Command::BlockList
+        | Command::BlockClear
+        | Command::Mock { .. }
+        | Command::MockList
+        | Command::MockRemove { .. }
+        | Command::MockHar { .. }
+        | Command::MockClear
+        | Command::WaitResponse { .. }
+        | Command::WaitRequest { .. }
         | Command::Viewport { .. }
+        | Command::StateSave { .. }
+        | Command::StateLoad { .. }
+        | Command::StateSnapshotSave { .. }
+        | Command::StateSnapshotRestore { .. }
+        | Command::Emulate { .. }
+        | Command::CacheDisable
+        | Command::CacheEnable
+        | Command::Status
+        | Command::CacheClear
+        | Command::LoginReplay { .. }
+        | Command::ClearSiteData { .. }
+        | Command::SwBypass { .. }
         | Command::ClipboardCopy { .. }
-        | Command::ClipboardPaste => unreachable!(),
+        | Command::ClipboardPaste
+        | Command::ClipboardGet
+        | Command::ClipboardSet { .. }
+        | Command::Download { .. } => unreachable!(),
+    }
+}
+
+/// Saves what `start --persist` restores. Written to a temporary file first
+/// so a crash mid-write doesn't lose the previous state.
+async fn persist_session(state: &State) -> Result<()> {
+    use std::io::Write;
+    use std::os::unix::fs::{DirBuilderExt, OpenOptionsExt};
+
+    let Some(path) = &state.persist else {
+        return Ok(());
+    };
+    let saved = PersistedSession {
+        url: state.page_opened.then(|| state.page.url()),
+        headers: state.headers.clone(),
+        viewport: state.settings.viewport.clone(),
+        storage_state: state.page.context()?.storage_state().await?,
+    };
+    // The cookies are in there, so only the user can read it
+    if let Some(dir) = path.parent() {
+        std::fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(dir)?;
+    }
+    let tmp = path.with_extension("json.tmp");
+    std::fs::remove_file(&tmp).ok();
+    let mut file = std::fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(0o600)
+        .open(&tmp)?;
+    file.write_all(serde_json::to_string_pretty(&saved)?.as_bytes())?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+/// What `start --fake-media` grants: the fake devices are useless if the
+/// page can't use them.
+fn media_permissions() -> Vec<String> {
+    vec!["camera".to_string(), "microphone".to_string()]
+}
+
+/// Fails unless `recreate_context` can replace the context. `what` names
+/// the command or options that need it.
+fn ensure_recreatable(state: &State, what: &str) -> Result<()> {
+    if state.cdp {
+        anyhow::bail!("{} needs a browser started by plwr, not --cdp", what);
+    }
+    if state.profile {
+        anyhow::bail!("{} can't be used with --profile", what);
+    }
+    if state.video.is_some() || state.har_recording.is_some() || state.har_update.is_some() {
+        anyhow::bail!("{} can't be used while recording video or a HAR", what);
+    }
+    Ok(())
+}
+
+/// Replaces the browser context with one made from `state.settings`, for
+/// options Playwright only takes when a context is created. Cookies and
+/// local storage carry over unless `storage_state` replaces them, as do the
+/// request log and the settings made with other commands. The current page
+/// is loaded again. Call `ensure_recreatable` first.
+async fn recreate_context(
+    state: &mut State,
+    storage_state: Option<playwright_rs::StorageState>,
+    timeout: u64,
+) -> Result<()> {
+    let old_ctx = state.page.context()?;
+    let browser = old_ctx
+        .browser()
+        .ok_or_else(|| anyhow::anyhow!("The browser context has no browser"))?;
+
+    // The request log's recorder belongs to the old context, so take what
+    // it has before closing it.
+    let logging = state.net_recorder.is_some();
+    if logging {
+        sync_net_log(state).await?;
+    }
+    let storage_state = match storage_state {
+        Some(storage_state) => storage_state,
+        None => old_ctx.storage_state().await?,
+    };
+    let url = state.page.url();
+    replace_context(state, &browser, Some(storage_state), url, logging, timeout).await
+}
+
+/// Replaces the browser context with a new one in `browser`, made from
+/// `state.settings`, and loads `url` in it if a page was open. `logging`
+/// restarts the request log's recorder in the new context.
+async fn replace_context(
+    state: &mut State,
+    browser: &Browser,
+    storage_state: Option<playwright_rs::StorageState>,
+    url: String,
+    logging: bool,
+    timeout: u64,
+) -> Result<()> {
+    let old_ctx = state.page.context()?;
+    let settings = &state.settings;
+    let ctx = browser
+        .new_context_with_options(BrowserContextOptions {
+            viewport: settings.viewport.clone(),
+            device_scale_factor: settings.scale,
+            is_mobile: settings.mobile.then_some(true),
+            has_touch: settings.touch.then_some(true),
+            ignore_https_errors: settings.ignore_https_errors.then_some(true),
+            geolocation: settings.geolocation.clone(),
+            permissions: Some(
+                settings
+                    .fake_media
+                    .then(media_permissions)
+                    .into_iter()
+                    .flatten()
+                    .chain(
+                        settings
+                            .geolocation
+                            .is_some()
+                            .then(|| "geolocation".to_string()),
+                    )
+                    .collect(),
+            ),
+            extra_http_headers: (!state.headers.is_empty()).then(|| state.headers.clone()),
+            storage_state,
+            // Only after a crash: `recreate_context` isn't used while recording
+            record_video: state.video.as_ref().map(|vs| RecordVideo {
+                dir: vs.temp_dir.to_string_lossy().to_string(),
+                size: None,
+            }),
+            ..Default::default()
+        })
+        .await?;
+    if let Some((username, password, origin)) = &settings.credentials {
+        pw_ext::set_http_credentials(&ctx, Some((username, password, origin.as_deref()))).await?;
+    }
+    let page = ctx.new_page().await?;
+    let media: Vec<(&str, &str)> = settings
+        .media
+        .iter()
+        .map(|(name, value)| (*name, value.as_str()))
+        .collect();
+    if !media.is_empty() {
+        pw_ext::emulate_media(&page, &media).await?;
+    }
+    old_ctx.close().await?;
+
+    state.page = page;
+    state.net_recorder = None;
+    state.devtools = None;
+    state.console_initialized = false;
+    state.network_initialized = false;
+    state.dialog_installed = false;
+    state.download_installed = false;
+    state.clipboard_granted = false;
+    if state.routes_installed {
+        state.routes_installed = false;
+        install_route_handler(state).await?;
+    }
+    if logging {
+        start_net_recorder(state).await?;
+    }
+    let (cache_disabled, sw_bypass) = (state.settings.cache_disabled, state.settings.sw_bypass);
+    if cache_disabled || sw_bypass {
+        let devtools = devtools(state).await?;
+        devtools
+            .send("Network.enable", serde_json::json!({}))
+            .await?;
+        devtools
+            .send(
+                "Network.setCacheDisabled",
+                serde_json::json!({ "cacheDisabled": cache_disabled }),
+            )
+            .await?;
+        devtools
+            .send(
+                "Network.setBypassServiceWorker",
+                serde_json::json!({ "bypass": sw_bypass }),
+            )
+            .await?;
+    }
+    if let Some(languages) = state.settings.lang.clone() {
+        override_languages(state, Some(&languages)).await?;
+    }
+    if state.page_opened {
+        let open = Command::Open {
+            url,
+            timeout,
+            show_redirects: false,
+        };
+        Box::pin(handle_command(state, open)).await?;
+    }
+    Ok(())
+}
+
+/// How soon after a command the cookies and storage it may have changed are
+/// taken for crash recovery.
+const RECOVERY_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+
+/// How long `status` waits for each thing it asks the browser.
+const STATUS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(2);
+
+/// One part of `status`, or None if it failed or took too long.
+async fn status_part<T, E>(call: impl std::future::Future<Output = Result<T, E>>) -> Option<T> {
+    tokio::time::timeout(STATUS_TIMEOUT, call).await.ok()?.ok()
+}
+
+/// Takes the cookies and storage to restore if the browser plwr launched
+/// crashes.
+async fn update_recovery(state: &mut State) {
+    if state.browser.is_none() || browser_crashed(state) {
+        return;
+    }
+    let Ok(ctx) = state.page.context() else {
+        return;
+    };
+    match ctx.storage_state().await {
+        Ok(storage_state) => state.recovery = Some(storage_state),
+        Err(e) => logs::log(
+            "warn",
+            format!("Cannot take the cookies and storage to recover: {}", e),
+            serde_json::Value::Null,
+        ),
+    }
+}
+
+/// The process id of the browser plwr launched, as Chromium reports it.
+async fn browser_pid(browser: &Browser) -> Option<u64> {
+    let devtools = pw_ext::new_browser_cdp_session(browser).await.ok()?;
+    let info = devtools
+        .send("SystemInfo.getProcessInfo", serde_json::json!({}))
+        .await;
+    devtools.detach().await.ok();
+    info.ok()?["processInfo"]
+        .as_array()?
+        .iter()
+        .find(|p| p["type"] == "browser")?["id"]
+        .as_u64()
+}
+
+/// Whether the browser plwr launched has gone away.
+fn browser_crashed(state: &State) -> bool {
+    state
+        .browser
+        .as_ref()
+        .is_some_and(|(browser, _)| !browser.is_connected())
+}
+
+const RESTART_ATTEMPTS: u32 = 5;
+const RESTART_MIN_DELAY: std::time::Duration = std::time::Duration::from_secs(1);
+const RESTART_MAX_DELAY: std::time::Duration = std::time::Duration::from_secs(30);
+/// A crash this long after the last restart starts the backoff over
+const RESTART_RESET: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Launches the browser again after a crash and restores the session in
+/// it: the settings made with commands, the cookies and storage of the last
+/// snapshot, and the page. A failed launch is retried with exponential
+/// backoff, and a browser that keeps crashing waits longer each time.
+async fn restart_browser(state: &mut State) -> Result<()> {
+    let mut delay = match state.restarted {
+        Some((at, delay)) if at.elapsed() < RESTART_RESET => delay,
+        _ => std::time::Duration::ZERO,
+    };
+    logs::error("The browser crashed");
+    let mut attempt = 1;
+    loop {
+        tokio::time::sleep(delay).await;
+        delay = (delay * 2).clamp(RESTART_MIN_DELAY, RESTART_MAX_DELAY);
+        match relaunch_browser(state).await {
+            Ok(()) => {
+                logs::info("Restarted the browser");
+                state.restarted = Some((std::time::Instant::now(), delay));
+                update_recovery(state).await;
+                return Ok(());
+            }
+            Err(e) if attempt == RESTART_ATTEMPTS => {
+                anyhow::bail!("The browser crashed and couldn't be restarted: {}", e)
+            }
+            Err(e) => logs::log(
+                "warn",
+                format!("Cannot restart the browser (attempt {}): {}", attempt, e),
+                serde_json::Value::Null,
+            ),
+        }
+        attempt += 1;
+    }
+}
+
+async fn relaunch_browser(state: &mut State) -> Result<()> {
+    let Some((_, options)) = state.browser.clone() else {
+        anyhow::bail!("Only a browser plwr launched can be restarted");
+    };
+    let browser = state
+        .playwright
+        .chromium()
+        .launch_with_options(options.clone())
+        .await?;
+    let url = state.page.url();
+    let logging = state.net_recorder.take().is_some();
+    // Recordings lived in the old browser
+    state.har_recording = None;
+    state.har_update = None;
+    let restored = async {
+        state.page = browser.new_page().await?;
+        state.browser = Some((browser.clone(), options));
+        let storage_state = state.recovery.clone();
+        replace_context(state, &browser, storage_state, url, logging, 30000).await
+    }
+    .await;
+    // The next attempt launches another one
+    if restored.is_err() {
+        let _ = browser.close().await;
     }
+    restored
+}
+
+/// Makes `navigator.language(s)` and `Intl` agree with an Accept-Language
+/// value like "sv-SE,en;q=0.8", or undoes that with `None`. The header
+/// itself is sent as an extra HTTP header, which keeps the q-values as given.
+async fn override_languages(state: &mut State, languages: Option<&str>) -> Result<()> {
+    let devtools = devtools(state).await?;
+    let user_agent = devtools
+        .send("Browser.getVersion", serde_json::json!({}))
+        .await?["userAgent"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let tags: Vec<&str> = languages
+        .unwrap_or_default()
+        .split(',')
+        .filter_map(|lang| lang.split(';').next())
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty() && *tag != "*")
+        .collect();
+    devtools
+        .send(
+            "Emulation.setUserAgentOverride",
+            serde_json::json!({ "userAgent": user_agent, "acceptLanguage": tags.join(",") }),
+        )
+        .await?;
+    let locale = match tags.first() {
+        Some(tag) => serde_json::json!({ "locale": tag }),
+        None => serde_json::json!({}),
+    };
+    devtools.send("Emulation.setLocaleOverride", locale).await?;
+    Ok(())
 }
 
 async fn ensure_clipboard_permissions(state: &mut State) -> Result<()> {
@@ -1223,17 +4590,466 @@ async fn ensure_clipboard_permissions(state: &mut State) -> Result<()> {
     Ok(())
 }
 
+async fn install_download_handler(state: &mut State) -> Result<()> {
+    if state.download_installed {
+        return Ok(());
+    }
+    let slot = Arc::clone(&state.download);
+    state
+        .page
+        .on_download(move |download| {
+            let slot = Arc::clone(&slot);
+            async move {
+                logs::info(format!(
+                    "Download of {} from {}",
+                    download.suggested_filename(),
+                    download.url()
+                ));
+                *slot.lock().unwrap() = Some(download);
+                Ok(())
+            }
+        })
+        .await?;
+    state.download_installed = true;
+    Ok(())
+}
+
+/// How much of the request log a long session keeps: past this many
+/// entries the oldest are dropped, and past this many bytes of bodies the
+/// oldest entries lose theirs.
+const NET_LOG_MAX_ENTRIES: usize = 10_000;
+const NET_LOG_MAX_BODY_BYTES: usize = 64 * 1024 * 1024;
+
+/// Moves what the HAR recorder has seen so far into `state.net_log` and
+/// starts a fresh recorder, since exporting stops the old one.
+async fn sync_net_log(state: &mut State) -> Result<()> {
+    let ctx = state.page.context()?;
+    if let Some(har_id) = state.net_recorder.take() {
+        let mut har: serde_json::Value =
+            serde_json::from_str(&pw_ext::har_export(&ctx, &har_id).await?)?;
+        if let serde_json::Value::Array(entries) = har["log"]["entries"].take() {
+            state.net_log.extend(entries);
+            trim_net_log(state);
+        }
+    }
+    start_net_recorder(state).await
+}
+
+/// Starts the HAR recorder behind `net log`. Bodies are what make the
+/// driver's copy of the HAR grow, so they're left out until `net capture`.
+async fn start_net_recorder(state: &mut State) -> Result<()> {
+    let content = if state.net_bodies { "embed" } else { "omit" };
+    state.net_recorder = Some(pw_ext::har_start(&state.page.context()?, content).await?);
+    Ok(())
+}
+
+/// Keeps the request log within NET_LOG_MAX_ENTRIES and
+/// NET_LOG_MAX_BODY_BYTES, dropping the oldest first.
+fn trim_net_log(state: &mut State) {
+    if state.net_log.len() > NET_LOG_MAX_ENTRIES {
+        let excess = state.net_log.len() - NET_LOG_MAX_ENTRIES;
+        state.net_log.drain(..excess);
+        state.net_log_dropped += excess;
+    }
+    let mut bytes = 0;
+    for entry in state.net_log.iter_mut().rev() {
+        let Some(content) = entry
+            .pointer_mut("/response/content")
+            .and_then(|c| c.as_object_mut())
+        else {
+            continue;
+        };
+        let Some(len) = content.get("text").and_then(|t| t.as_str()).map(str::len) else {
+            continue;
+        };
+        if bytes + len > NET_LOG_MAX_BODY_BYTES {
+            // Everything older goes too, so a small old body doesn't outlive
+            // a newer one.
+            bytes = NET_LOG_MAX_BODY_BYTES;
+            content.remove("text");
+            content.remove("encoding");
+            content.insert("_dropped".to_string(), serde_json::Value::Bool(true));
+        } else {
+            bytes += len;
+        }
+    }
+}
+
+/// The request log entries whose URL matches `filter` (a regex) and that
+/// started at or after `since`.
+async fn net_log_entries<'a>(
+    state: &'a mut State,
+    filter: Option<&str>,
+    since: Option<i64>,
+) -> Result<impl Iterator<Item = &'a serde_json::Value>> {
+    sync_net_log(state).await?;
+    let url_regex = filter
+        .map(regex::Regex::new)
+        .transpose()
+        .map_err(|e| anyhow::anyhow!("Invalid URL regex: {}", e))?;
+    Ok(state
+        .net_log
+        .iter()
+        .filter(move |e| {
+            url_regex
+                .as_ref()
+                .is_none_or(|re| e["request"]["url"].as_str().is_some_and(|u| re.is_match(u)))
+        })
+        .filter(move |e| since.is_none_or(|since| har::timestamp(e) >= Some(since))))
+}
+
+/// Writes the HAR recorded by `mock --har --update`, if one is running,
+/// and returns its path.
+async fn save_har_update(state: &mut State) -> Result<Option<String>> {
+    let Some((har_id, path)) = state.har_update.take() else {
+        return Ok(None);
+    };
+    let har = pw_ext::har_export(&state.page.context()?, &har_id).await?;
+    std::fs::write(&path, har).map_err(|e| anyhow::anyhow!("Cannot write {}: {}", path, e))?;
+    Ok(Some(path))
+}
+
+async fn install_route_handler(state: &mut State) -> Result<()> {
+    if state.routes_installed {
+        return Ok(());
+    }
+    let rules_ref = Arc::clone(&state.routes);
+    state
+        .page
+        .context()?
+        .route("**/*", move |route| {
+            handle_route(Arc::clone(&rules_ref), route)
+        })
+        .await?;
+    state.routes_installed = true;
+    Ok(())
+}
+
+/// Clicks `selector`, if given, then polls the route rules with `take`
+/// until it returns something. Returns `None` on timeout.
+async fn wait_for_route(
+    state: &State,
+    selector: Option<&str>,
+    timeout: u64,
+    mut take: impl FnMut(&mut RouteRules) -> Option<serde_json::Value>,
+) -> Result<Option<serde_json::Value>> {
+    if let Some(selector) = selector {
+        let loc = state.page.locator(selector).await;
+        loc.click(Some(ClickOptions {
+            timeout: Some(timeout as f64),
+            ..Default::default()
+        }))
+        .await?;
+    }
+    let start = std::time::Instant::now();
+    loop {
+        if let Some(value) = take(&mut state.routes.lock().unwrap()) {
+            return Ok(Some(value));
+        }
+        if start.elapsed().as_millis() as u64 > timeout {
+            return Ok(None);
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+    }
+}
+
+/// Applies the route rules to a request. Requests a `wait-response` is
+/// waiting for are fetched here so the response can be seen before the
+/// page gets it.
+async fn handle_route(
+    rules_ref: Arc<Mutex<RouteRules>>,
+    route: Route,
+) -> playwright_rs::Result<()> {
+    let request = route.request();
+    let url = request.url().to_string();
+    let post_data = request.initializer()["postData"]
+        .as_str()
+        .and_then(|data| BASE64_STANDARD.decode(data).ok());
+    let mut headers: HashMap<String, String> = HashMap::new();
+    for h in request.initializer()["headers"]
+        .as_array()
+        .into_iter()
+        .flatten()
+    {
+        if let (Some(n), Some(v)) = (h["name"].as_str(), h["value"].as_str()) {
+            headers.insert(n.to_string(), v.to_string());
+        }
+    }
+    record_request(
+        &rules_ref,
+        request.method(),
+        &url,
+        &headers,
+        post_data.as_deref(),
+    );
+
+    let action = rules_ref.lock().unwrap().action(
+        request.method(),
+        &url,
+        request.resource_type(),
+        post_data.as_deref(),
+    );
+    let (extra_headers, wants_response) = match action {
+        RouteAction::Abort(error_code) => return route.abort(Some(error_code)).await,
+        RouteAction::Fulfill(reply) => {
+            record_response(&rules_ref, request.method(), &url, &reply);
+            save_response(&rules_ref, &url, &reply);
+            return route.fulfill(Some(reply)).await;
+        }
+        RouteAction::Continue {
+            extra_headers,
+            wants_response,
+        } => (extra_headers, wants_response),
+    };
+
+    if extra_headers.is_empty() && !wants_response {
+        return route.fallback(None).await;
+    }
+    for (name, value) in extra_headers {
+        headers.retain(|n, _| !n.eq_ignore_ascii_case(&name));
+        headers.insert(name, value);
+    }
+    if !wants_response {
+        return route
+            .fallback(Some(ContinueOptions {
+                headers: Some(headers),
+                ..Default::default()
+            }))
+            .await;
+    }
+
+    // Redirects are passed on as they are, so the browser follows them
+    // (and routes the next hop) itself.
+    let fetched = route
+        .fetch(Some(FetchOptions {
+            headers: Some(headers),
+            method: Some(request.method().to_string()),
+            post_data_bytes: post_data,
+            max_redirects: Some(0),
+            ..Default::default()
+        }))
+        .await?;
+    // The body comes back decoded.
+    let reply = FulfillOptions {
+        status: Some(fetched.status),
+        headers: Some(fulfill_headers(fetched.headers)),
+        body: Some(fetched.body),
+        content_type: None,
+    };
+    record_response(&rules_ref, request.method(), &url, &reply);
+    save_response(&rules_ref, &url, &reply);
+    route.fulfill(Some(reply)).await
+}
+
+/// Folds a decoded response's headers into the map `route.fulfill` takes,
+/// dropping the original encoding and length, which no longer apply.
+/// Repeated headers are joined: Set-Cookie with newlines, as Playwright
+/// expects, and the rest with commas.
+fn fulfill_headers(headers: Vec<(String, String)>) -> HashMap<String, String> {
+    let mut folded: HashMap<String, String> = HashMap::new();
+    for (name, value) in headers {
+        let name = name.to_ascii_lowercase();
+        if matches!(
+            name.as_str(),
+            "content-encoding" | "content-length" | "transfer-encoding"
+        ) {
+            continue;
+        }
+        let separator = if name == "set-cookie" { "\n" } else { ", " };
+        folded
+            .entry(name)
+            .and_modify(|joined| {
+                joined.push_str(separator);
+                joined.push_str(&value);
+            })
+            .or_insert(value);
+    }
+    folded
+}
+
+/// Hands a request to the pending `wait-request`, if it's waiting for it.
+fn record_request(
+    rules_ref: &Arc<Mutex<RouteRules>>,
+    method: &str,
+    url: &str,
+    headers: &HashMap<String, String>,
+    post_data: Option<&[u8]>,
+) {
+    let mut rules = rules_ref.lock().unwrap();
+    let Some(wait) = rules.request_wait.as_mut() else {
+        return;
+    };
+    if wait.request.is_some() || !wait.regex.is_match(url) {
+        return;
+    }
+    let headers: serde_json::Map<String, serde_json::Value> = headers
+        .iter()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.clone().into()))
+        .collect();
+    let is_json = headers
+        .get("content-type")
+        .and_then(|ct| ct.as_str())
+        .is_some_and(|ct| ct.contains("json"));
+    let post_data = post_data.map(|data| match serde_json::from_slice(data) {
+        Ok(json) if is_json => json,
+        _ => serde_json::Value::from(String::from_utf8_lossy(data).to_string()),
+    });
+    wait.request = Some(serde_json::json!({
+        "method": method,
+        "url": url,
+        "headers": headers,
+        "post_data": post_data,
+    }));
+}
+
+/// Writes a successful response's body into the directory of each `net save`
+/// rule matching its URL.
+fn save_response(rules_ref: &Arc<Mutex<RouteRules>>, url: &str, reply: &FulfillOptions) {
+    if !(200..300).contains(&reply.status.unwrap_or(200)) {
+        return;
+    }
+    let dirs: Vec<std::path::PathBuf> = rules_ref
+        .lock()
+        .unwrap()
+        .saves
+        .iter()
+        .filter(|(_, re, _)| re.is_match(url))
+        .map(|(_, _, dir)| dir.clone())
+        .collect();
+    for dir in dirs {
+        let path = save_path(&dir, url);
+        if std::fs::write(&path, reply.body.as_deref().unwrap_or_default()).is_ok() {
+            let path = path.to_string_lossy().to_string();
+            rules_ref.lock().unwrap().saved.push(path);
+        }
+    }
+}
+
+/// A file in `dir` named after the last segment of the URL's path, with
+/// anything but letters, digits, `.`, `-` and `_` replaced. A number is
+/// added before the extension rather than overwrite an existing file.
+fn save_path(dir: &Path, url: &str) -> std::path::PathBuf {
+    let path = url
+        .split_once("://")
+        .map_or(url, |(_, rest)| rest.split_once('/').map_or("", |(_, p)| p));
+    let path = path.split(['?', '#']).next().unwrap_or("");
+    let name: String = path
+        .rsplit('/')
+        .find(|s| !s.is_empty())
+        .unwrap_or("index")
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .take(100)
+        .collect();
+    let name = name.trim_start_matches('.');
+    let name = if name.is_empty() { "index" } else { name };
+    let (stem, ext) = match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => (stem, format!(".{}", ext)),
+        _ => (name, String::new()),
+    };
+    let mut candidate = dir.join(name);
+    let mut n = 1;
+    while candidate.exists() {
+        candidate = dir.join(format!("{}-{}{}", stem, n, ext));
+        n += 1;
+    }
+    candidate
+}
+
+/// Hands a response to the pending `wait-response`, if it's waiting for it.
+fn record_response(
+    rules_ref: &Arc<Mutex<RouteRules>>,
+    method: &str,
+    url: &str,
+    reply: &FulfillOptions,
+) {
+    let mut rules = rules_ref.lock().unwrap();
+    let Some(wait) = rules.response_wait.as_mut() else {
+        return;
+    };
+    let status = reply.status.unwrap_or(200);
+    if wait.response.is_some()
+        || !wait.regex.is_match(url)
+        || wait
+            .status
+            .as_deref()
+            .is_some_and(|s| !status_matches(s, status))
+    {
+        return;
+    }
+    let mut headers: serde_json::Map<String, serde_json::Value> = reply
+        .headers
+        .iter()
+        .flatten()
+        .map(|(name, value)| (name.to_ascii_lowercase(), value.clone().into()))
+        .collect();
+    if let Some(content_type) = &reply.content_type {
+        headers.insert("content-type".to_string(), content_type.clone().into());
+    }
+    let body = reply.body.as_deref().unwrap_or_default();
+    let is_json = headers
+        .get("content-type")
+        .and_then(|ct| ct.as_str())
+        .is_some_and(|ct| ct.contains("json"));
+    let body = match serde_json::from_slice::<serde_json::Value>(body) {
+        Ok(json) if is_json => json,
+        _ => String::from_utf8_lossy(body).to_string().into(),
+    };
+    wait.response = Some(serde_json::json!({
+        "method": method,
+        "url": url,
+        "status": status,
+        "headers": headers,
+        "body": body,
+    }));
+}
+
+/// Whether `status` matches a `--status` filter like `200` or `2xx`.
+fn status_matches(filter: &str, status: u16) -> bool {
+    let status = status.to_string();
+    filter.len() == status.len()
+        && filter
+            .chars()
+            .zip(status.chars())
+            .all(|(f, s)| f == 'x' || f == s)
+}
+
+async fn devtools(state: &mut State) -> Result<&pw_ext::CdpSession> {
+    if state.devtools.is_none() {
+        let session = pw_ext::new_cdp_session(&state.page).await.map_err(|e| {
+            anyhow::anyhow!("Cannot open a DevTools session (Chromium only): {}", e)
+        })?;
+        state.devtools = Some(session);
+    }
+    Ok(state.devtools.as_ref().unwrap())
+}
+
 async fn install_dialog_handler(state: &mut State) -> Result<()> {
     if state.dialog_installed {
         return Ok(());
     }
-    let action_ref = Arc::clone(&state.dialog_action);
+    let dialog_ref = Arc::clone(&state.dialog);
     state
         .page
         .on_dialog(move |dialog| {
-            let action_ref = Arc::clone(&action_ref);
+            let dialog_ref = Arc::clone(&dialog_ref);
             async move {
-                let action = action_ref.lock().unwrap().take();
+                let action = {
+                    let mut d = dialog_ref.lock().unwrap();
+                    d.last_message = Some(dialog.message().to_string());
+                    logs::info(format!("Dialog: {}", dialog.message()));
+                    if d.persist {
+                        d.action.clone()
+                    } else {
+                        d.action.take()
+                    }
+                };
                 match action {
                     Some(DialogAction::Accept(text)) => dialog.accept(text.as_deref()).await,
                     Some(DialogAction::Dismiss) => dialog.dismiss().await,
@@ -1246,11 +5062,180 @@ async fn install_dialog_handler(state: &mut State) -> Result<()> {
     Ok(())
 }
 
-async fn wait_for_visible(loc: &Locator, selector: &str, timeout: u64) -> Result<()> {
+/// Screenshots the page, or the element matching `selector`. With
+/// `padding` the element is captured from the page with that much of its
+/// surroundings around it.
+async fn screenshot(
+    page: &Page,
+    selector: Option<&str>,
+    padding: Option<u32>,
+    mut options: ScreenshotOptions,
+    timeout: u64,
+) -> Result<Vec<u8>> {
+    let Some(sel) = selector else {
+        return Ok(page.screenshot(Some(options)).await?);
+    };
+    let loc = page.locator(sel).await;
+    let Some(padding) = padding else {
+        options.full_page = None;
+        return Ok(loc.screenshot(Some(options)).await?);
+    };
+    wait_for_state(&loc, sel, timeout, WaitState::Visible).await?;
+    pw_ext::locator_scroll_into_view(page, sel).await?;
+    let js = r#"el => {
+        const r = el.getBoundingClientRect();
+        return [r.left, r.top, r.right, r.bottom];
+    }"#;
+    let (left, top, right, bottom): (f64, f64, f64, f64) =
+        serde_json::from_str(&pw_ext::locator_eval_on_selector(page, sel, js).await?)?;
+    let pad = padding as f64;
+    let (x, y) = ((left - pad).max(0.0), (top - pad).max(0.0));
+    options.clip = Some(ScreenshotClip {
+        x,
+        y,
+        width: right + pad - x,
+        height: bottom + pad - y,
+    });
+    Ok(page.screenshot(Some(options)).await?)
+}
+
+/// The DOM under the first element matching `selector` as `tree` JSON.
+async fn dom_tree(
+    page: &Page,
+    selector: &str,
+    options: serde_json::Value,
+) -> Result<serde_json::Value> {
+    let js = format!("el => ({})(el, {})", TREE_JS, options);
+    let val = pw_ext::locator_eval_on_selector(page, selector, &js).await?;
+    Ok(serde_json::from_str(&val)?)
+}
+
+/// The full DOM tree with element rects, as stored by `snapshot save`.
+async fn layout_tree(page: &Page, selector: &str) -> Result<serde_json::Value> {
+    let options = serde_json::json!({
+        "depth": null,
+        "maxText": null,
+        "only": null,
+        "layout": true,
+    });
+    dom_tree(page, selector, options).await
+}
+
+/// Compares a screenshot against its baseline image. Returns a summary when
+/// they match; otherwise writes a diff image (differing pixels in red) and
+/// fails. A missing baseline is created from the screenshot, which also fails
+/// so that CI notices.
+async fn compare_screenshot(
+    page: &Page,
+    bytes: &[u8],
+    compare: &ScreenshotCompare,
+) -> Result<String> {
+    #[derive(serde::Deserialize)]
+    struct ImageDiff {
+        size: (u32, u32),
+        #[serde(default)]
+        baseline_size: Option<(u32, u32)>,
+        #[serde(default)]
+        diff_pixels: u64,
+        #[serde(default)]
+        diff_png: String,
+    }
+
+    let baseline = match std::fs::read(&compare.baseline) {
+        Ok(baseline) => baseline,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            std::fs::write(&compare.baseline, bytes)?;
+            anyhow::bail!(
+                "Baseline {} didn't exist, so this screenshot was saved as the baseline.",
+                compare.baseline
+            );
+        }
+        Err(e) => anyhow::bail!("Cannot read baseline {}: {}", compare.baseline, e),
+    };
+    let js = format!(
+        "() => ({})({}, {}, {})",
+        IMAGE_DIFF_JS,
+        serde_json::to_string(&BASE64_STANDARD.encode(bytes))?,
+        serde_json::to_string(&BASE64_STANDARD.encode(&baseline))?,
+        compare.threshold
+    );
+    let diff: ImageDiff = serde_json::from_str(&pw_ext::page_evaluate_value(page, &js).await?)?;
+
+    if let Some((w, h)) = diff.baseline_size {
+        anyhow::bail!(
+            "Screenshot is {}x{} but baseline {} is {}x{}.",
+            diff.size.0,
+            diff.size.1,
+            compare.baseline,
+            w,
+            h
+        );
+    }
+    let total = diff.size.0 as u64 * diff.size.1 as u64;
+    let ratio = diff.diff_pixels as f64 / total.max(1) as f64;
+    let allowed = compare.diff_pixels_allowed(total);
+    if diff.diff_pixels > allowed {
+        std::fs::write(&compare.diff_path, BASE64_STANDARD.decode(&diff.diff_png)?)?;
+        anyhow::bail!(
+            "Screenshot differs from {}: {} pixels ({:.2}%) differ, {} allowed. Diff saved to {}",
+            compare.baseline,
+            diff.diff_pixels,
+            ratio * 100.0,
+            allowed,
+            compare.diff_path
+        );
+    }
+    Ok(format!(
+        "Matches {} ({} of {} pixels differ)",
+        compare.baseline, diff.diff_pixels, total
+    ))
+}
+
+/// Re-encodes a PNG screenshot as WebP with the browser's canvas encoder,
+/// since Playwright itself only produces PNG and JPEG.
+async fn png_to_webp(page: &Page, png: &[u8], quality: Option<u8>) -> Result<Vec<u8>> {
+    let js = format!(
+        r#"async () => {{
+            const bytes = Uint8Array.from(atob({png}), c => c.charCodeAt(0));
+            const bitmap = await createImageBitmap(new Blob([bytes], {{ type: 'image/png' }}));
+            const canvas = document.createElement('canvas');
+            canvas.width = bitmap.width;
+            canvas.height = bitmap.height;
+            canvas.getContext('2d').drawImage(bitmap, 0, 0);
+            const url = canvas.toDataURL('image/webp', {quality});
+            if (!url.startsWith('data:image/webp'))
+                throw new Error('This browser cannot encode WebP screenshots');
+            return url.slice(url.indexOf(',') + 1);
+        }}"#,
+        png = serde_json::to_string(&BASE64_STANDARD.encode(png))?,
+        quality = quality.map_or("undefined".to_string(), |q| (q as f64 / 100.0).to_string()),
+    );
+    let webp = pw_ext::page_evaluate_value(page, &js).await?;
+    Ok(BASE64_STANDARD.decode(webp)?)
+}
+
+#[derive(Clone, Copy)]
+enum WaitState {
+    Attached,
+    Visible,
+}
+
+/// Polls until `loc` matches something (and, for `Visible`, the first match
+/// is visible), or fails with Playwright's timeout message.
+async fn wait_for_state(
+    loc: &Locator,
+    selector: &str,
+    timeout: u64,
+    state: WaitState,
+) -> Result<()> {
     let start = std::time::Instant::now();
     loop {
-        let n = loc.count().await.unwrap_or_default();
-        if n > 0 && loc.first().is_visible().await.unwrap_or(false) {
+        let ready = loc.count().await.unwrap_or_default() > 0
+            && match state {
+                WaitState::Attached => true,
+                WaitState::Visible => loc.first().is_visible().await.unwrap_or(false),
+            };
+        if ready {
             return Ok(());
         }
         if start.elapsed().as_millis() as u64 > timeout {
@@ -1260,6 +5245,137 @@ async fn wait_for_visible(loc: &Locator, selector: &str, timeout: u64) -> Result
     }
 }
 
+/// Expand `@name` aliases in every selector the command takes, then put the
+/// `within` scope in front of them. Layout anchors (`--right-of @name`) are
+/// expanded too; Playwright looks them up inside the same scope.
+fn resolve_selectors(state: &State, command: Command) -> Result<Command> {
+    let scope = match command {
+        Command::Alias { .. } | Command::Within { .. } => None,
+        _ => state.scope.as_deref(),
+    };
+    map_selectors(command, |selector| {
+        let selector = expand_anchor_aliases(&state.aliases, selector)?;
+        let selector = expand_alias(&state.aliases, &selector)?;
+        Ok(match scope {
+            Some(scope) => format!("{} >> {}", scope, selector),
+            None => selector,
+        })
+    })
+}
+
+fn map_selectors(command: Command, mut f: impl FnMut(&str) -> Result<String>) -> Result<Command> {
+    let mut value = serde_json::to_value(command)?;
+    if let Some(fields) = value.as_object_mut() {
+        for (key, field) in fields.iter_mut() {
+            let selectors: Vec<&mut serde_json::Value> = match key.as_str() {
+                "selector" => vec![field],
+                "selectors" | "mask" | "annotate" => {
+                    field.as_array_mut().into_iter().flatten().collect()
+                }
+                // fill-form: [[selector, value], ...]
+                "fields" => field
+                    .as_array_mut()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(|pair| pair.get_mut(0))
+                    .collect(),
+                _ => continue,
+            };
+            for sel in selectors {
+                if let Some(s) = sel.as_str() {
+                    *sel = serde_json::Value::String(f(s)?);
+                }
+            }
+        }
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// Expands the aliases used as layout anchors, as in `input:right-of(@label)`.
+fn expand_anchor_aliases(aliases: &HashMap<String, String>, selector: &str) -> Result<String> {
+    let re = regex::Regex::new(r":(right-of|left-of|above|below|near)\(@([^()]+)\)").unwrap();
+    let mut expanded = String::new();
+    let mut last = 0;
+    for caps in re.captures_iter(selector) {
+        let whole = caps.get(0).unwrap();
+        let anchor = expand_alias(aliases, &format!("@{}", &caps[2]))?;
+        expanded.push_str(&selector[last..whole.start()]);
+        expanded.push_str(&format!(":{}({})", &caps[1], anchor));
+        last = whole.end();
+    }
+    expanded.push_str(&selector[last..]);
+    Ok(expanded)
+}
+
+fn expand_alias(aliases: &HashMap<String, String>, selector: &str) -> Result<String> {
+    if !selector
+        .split(">>")
+        .any(|part| part.trim().starts_with('@'))
+    {
+        return Ok(selector.to_string());
+    }
+    let parts = selector
+        .split(">>")
+        .map(|part| match part.trim().strip_prefix('@') {
+            Some(name) => aliases.get(name).cloned().ok_or_else(|| {
+                anyhow::anyhow!(
+                    "Unknown alias '@{}'. Define it with 'plwr alias {} <selector>'.",
+                    name,
+                    name
+                )
+            }),
+            None => Ok(part.trim().to_string()),
+        })
+        .collect::<Result<Vec<_>>>()?;
+    Ok(parts.join(" >> "))
+}
+
+/// Timeouts on a selector that matches nothing get "did you mean" hints.
+async fn error_with_suggestions(page: &Page, selector: &str, e: anyhow::Error) -> Response {
+    let msg = clean_error(e);
+    if !msg.starts_with("Timeout") {
+        return Response::err(msg);
+    }
+    let suggestions = suggestions::for_selector(page, selector, UNIQUE_SELECTOR_JS).await;
+    if suggestions.is_empty() {
+        return Response::err(msg);
+    }
+    let hints: Vec<String> = suggestions.iter().map(|s| format!("  {}", s)).collect();
+    Response::err(format!("{}\n\nDid you mean:\n{}", msg, hints.join("\n")))
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn json_text(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn key_error(e: playwright_rs::Error) -> Response {
+    let msg = clean_error(anyhow::anyhow!(e));
+    if msg.contains("Unknown key") {
+        Response::err(format!(
+            "{msg}\n\n\
+                Valid keys: a-z A-Z 0-9, \
+                Backspace Tab Enter Escape Space Delete Insert, \
+                ArrowUp ArrowDown ArrowLeft ArrowRight Home End PageUp PageDown, \
+                F1-F12, Control Shift Alt Meta, \
+                any US keyboard character: !@#$%^&*()_+-=[]{{}}\\|;':\",./<>?`~\n\
+                Chords: Control+c, Shift+Enter, Alt+Tab, Meta+a"
+        ))
+    } else {
+        Response::err(msg)
+    }
+}
+
 fn parse_modifiers(modifiers: &[String]) -> Option<Vec<KeyboardModifier>> {
     if modifiers.is_empty() {
         return None;
@@ -1280,6 +5396,7 @@ fn parse_modifiers(modifiers: &[String]) -> Option<Vec<KeyboardModifier>> {
 
 fn parse_button(button: Option<&str>) -> Option<MouseButton> {
     button.map(|b| match b {
+        "left" => MouseButton::Left,
         "right" => MouseButton::Right,
         "middle" => MouseButton::Middle,
         other => panic!("Unknown button: {}", other),
@@ -1306,7 +5423,7 @@ fn resolve_cdp_endpoint(arg: &str) -> Result<String> {
     }
 }
 
-fn chrome_user_data_dir(channel: &str) -> std::path::PathBuf {
+pub fn chrome_user_data_dir(channel: &str) -> std::path::PathBuf {
     if let Ok(dir) = std::env::var("PLWR_CDP_USER_DATA_DIR") {
         return std::path::PathBuf::from(dir);
     }
@@ -0,0 +1,35 @@
+use regex::Regex;
+
+/// Compiles a URL glob the way Playwright's `route` reads them: `**`
+/// matches anything, `*` anything but `/`, and `{a,b}` either alternative.
+/// Everything else, including `?`, is literal. The glob must match the
+/// whole URL.
+pub fn url_glob(glob: &str) -> Regex {
+    let mut re = String::from("^");
+    let mut chars = glob.chars().peekable();
+    let mut in_group = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '{' if !in_group => {
+                in_group = true;
+                re.push('(');
+            }
+            '}' if in_group => {
+                in_group = false;
+                re.push(')');
+            }
+            ',' if in_group => re.push('|'),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    if in_group {
+        re.push(')');
+    }
+    re.push('$');
+    Regex::new(&re).expect("escaped glob is a valid regex")
+}
@@ -0,0 +1,233 @@
+use base64::prelude::{Engine, BASE64_STANDARD};
+use serde_json::{json, Value};
+use std::path::Path;
+
+/// A recorded response to replay with `route.fulfill`.
+pub struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+/// Summarizes a HAR entry as a `net log` record: method, URL, status,
+/// type, size, duration and start time (ms since the epoch). Failed
+/// requests have a null status and an `error`.
+pub fn log_entry(entry: &Value) -> Value {
+    let request = &entry["request"];
+    let response = &entry["response"];
+    let status = response["status"].as_i64().filter(|&s| s > 0);
+    let size = ["_transferSize", "bodySize"]
+        .iter()
+        .filter_map(|k| response[*k].as_i64())
+        .find(|&n| n >= 0);
+    let mut record = json!({
+        "method": request["method"],
+        "url": request["url"],
+        "status": status,
+        "type": resource_type(entry),
+        "size": size,
+        "duration": entry["time"].as_f64().map(|t| t.round() as i64),
+        "ts": timestamp(entry),
+    });
+    if let Some(error) = response["_failureText"].as_str() {
+        record["error"] = json!(error);
+    }
+    record
+}
+
+/// The redirect chain of a navigation, from the HAR entries recorded while it
+/// ran: the first document request and each document request it was
+/// redirected to, as {url, status, headers}.
+pub fn redirect_chain(entries: &[Value]) -> Vec<Value> {
+    let documents = entries
+        .iter()
+        .filter(|e| e["_resourceType"].as_str() == Some("document"));
+    let mut chain = Vec::new();
+    for entry in documents {
+        let response = &entry["response"];
+        let status = response["status"].as_i64().filter(|&s| s > 0);
+        let mut headers = serde_json::Map::new();
+        for h in response["headers"].as_array().into_iter().flatten() {
+            let (Some(name), Some(value)) = (h["name"].as_str(), h["value"].as_str()) else {
+                continue;
+            };
+            let name = name.to_ascii_lowercase();
+            let value = match headers.get(&name).and_then(|v| v.as_str()) {
+                Some(prev) => format!("{}, {}", prev, value),
+                None => value.to_string(),
+            };
+            headers.insert(name, json!(value));
+        }
+        chain.push(json!({
+            "url": entry["request"]["url"],
+            "status": status,
+            "headers": headers,
+        }));
+        if !status.is_some_and(|s| (300..400).contains(&s)) {
+            break;
+        }
+    }
+    chain
+}
+
+/// Where the time went for a request, in ms: the HAR timings under their
+/// usual names (`ttfb` for wait, `download` for receive), with `connect`
+/// excluding the TLS handshake. Phases that didn't happen are null.
+pub fn timing(entry: &Value) -> Value {
+    let timings = &entry["timings"];
+    let phase = |name: &str| timings[name].as_f64().filter(|&t| t >= 0.0);
+    let ms = |t: Option<f64>| t.map(|t| (t * 10.0).round() / 10.0);
+    let tls = phase("ssl");
+    let connect = phase("connect").map(|c| c - tls.unwrap_or(0.0));
+    json!({
+        "method": entry["request"]["method"],
+        "url": entry["request"]["url"],
+        "status": entry["response"]["status"].as_i64().filter(|&s| s > 0),
+        "dns": ms(phase("dns")),
+        "connect": ms(connect),
+        "tls": ms(tls),
+        "send": ms(phase("send")),
+        "ttfb": ms(phase("wait")),
+        "download": ms(phase("receive")),
+        "total": ms(entry["time"].as_f64()),
+        "ts": timestamp(entry),
+    })
+}
+
+/// Whether the request failed or got a 4xx/5xx status.
+pub fn is_failure(entry: &Value) -> bool {
+    entry["response"]["status"]
+        .as_i64()
+        .is_none_or(|s| s <= 0 || s >= 400)
+}
+
+/// The page or stylesheet that made the request, from its Referer header.
+pub fn initiator(entry: &Value) -> Option<&str> {
+    entry["request"]["headers"].as_array()?.iter().find(|h| {
+        h["name"]
+            .as_str()
+            .is_some_and(|n| n.eq_ignore_ascii_case("referer"))
+    })?["value"]
+        .as_str()
+}
+
+/// Start time of a HAR entry in ms since the epoch.
+pub fn timestamp(entry: &Value) -> Option<i64> {
+    entry["startedDateTime"].as_str().and_then(parse_iso8601)
+}
+
+/// The recorded response to a request, matched by method and URL (ignoring
+/// the fragment), preferring an entry with the same post data. Recorded
+/// redirects are followed to the final response. Bodies stored next to the
+/// HAR (`content._file`) are read from `dir`.
+pub fn lookup(
+    entries: &[Value],
+    dir: &Path,
+    method: &str,
+    url: &str,
+    post_data: Option<&str>,
+) -> Option<Response> {
+    let mut method = method.to_string();
+    let mut url = url.split('#').next().unwrap_or(url).to_string();
+    for _ in 0..10 {
+        let candidates: Vec<&Value> = entries
+            .iter()
+            .filter(|e| {
+                e["request"]["method"].as_str() == Some(&method)
+                    && e["request"]["url"]
+                        .as_str()
+                        .is_some_and(|u| u.split('#').next() == Some(&url))
+            })
+            .collect();
+        let entry = candidates
+            .iter()
+            .find(|e| e["request"]["postData"]["text"].as_str() == post_data)
+            .or(candidates.first())?;
+        let response = &entry["response"];
+        let status = response["status"].as_u64().filter(|&s| s > 0)? as u16;
+        let redirect = response["redirectURL"].as_str().unwrap_or("");
+        if (300..400).contains(&status) && !redirect.is_empty() {
+            if !matches!(status, 307 | 308) {
+                method = "GET".to_string();
+            }
+            url = redirect.to_string();
+            continue;
+        }
+
+        let headers = response["headers"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|h| {
+                Some((
+                    h["name"].as_str()?.to_string(),
+                    h["value"].as_str()?.to_string(),
+                ))
+            })
+            .collect();
+        return Some(Response {
+            status,
+            headers,
+            body: response_body(entry, dir)?,
+        });
+    }
+    None
+}
+
+/// The recorded (already decoded) response body of a HAR entry. Bodies
+/// stored next to the HAR (`content._file`) are read from `dir`.
+pub fn response_body(entry: &Value, dir: &Path) -> Option<Vec<u8>> {
+    let content = &entry["response"]["content"];
+    if let Some(file) = content["_file"].as_str() {
+        return std::fs::read(dir.join(file)).ok();
+    }
+    let text = content["text"].as_str().unwrap_or("");
+    if content["encoding"].as_str() == Some("base64") {
+        BASE64_STANDARD.decode(text).ok()
+    } else {
+        Some(text.as_bytes().to_vec())
+    }
+}
+
+/// The same type names `network` uses, from Playwright's resource type
+/// when the HAR has it and the response Content-Type otherwise.
+fn resource_type(entry: &Value) -> &'static str {
+    match entry["_resourceType"].as_str().unwrap_or("") {
+        "document" => return "doc",
+        "stylesheet" => return "css",
+        "script" => return "js",
+        "image" => return "img",
+        "font" => return "font",
+        "media" => return "media",
+        "fetch" => return "fetch",
+        "xhr" => return "xhr",
+        "websocket" => return "ws",
+        "manifest" => return "manifest",
+        _ => {}
+    }
+    let mime = entry["response"]["content"]["mimeType"]
+        .as_str()
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let mime = mime.split(';').next().unwrap_or("").trim();
+    match mime {
+        "text/html" | "application/xhtml+xml" => "doc",
+        "text/css" => "css",
+        "application/wasm" => "wasm",
+        "application/manifest+json" => "manifest",
+        m if m.contains("javascript") || m.contains("ecmascript") => "js",
+        m if m.starts_with("image/") => "img",
+        m if m.starts_with("font/") || m.contains("font-woff") => "font",
+        m if m.starts_with("audio/") || m.starts_with("video/") => "media",
+        m if m.contains("json") || m.contains("xml") => "fetch",
+        _ => "other",
+    }
+}
+
+/// Parses the `YYYY-MM-DDTHH:MM:SS.sssZ` timestamps Playwright writes into
+/// HARs (always UTC) to ms since the epoch.
+fn parse_iso8601(s: &str) -> Option<i64> {
+    let time =
+        time::OffsetDateTime::parse(s, &time::format_description::well_known::Rfc3339).ok()?;
+    Some((time.unix_timestamp_nanos() / 1_000_000) as i64)
+}
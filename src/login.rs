@@ -0,0 +1,64 @@
+use crate::protocol::LoginStep;
+use std::path::PathBuf;
+
+/// Where recorded logins are kept: `$PLWR_LOGINS`, or `logins` in plwr's
+/// config directory.
+pub fn dir() -> PathBuf {
+    if let Ok(dir) = std::env::var("PLWR_LOGINS") {
+        return PathBuf::from(dir);
+    }
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("plwr")
+        .join("logins")
+}
+
+/// The file of a recorded login. Names can't reach outside the logins
+/// directory or be hidden files.
+fn path(name: &str) -> Result<PathBuf, String> {
+    check_name(name)?;
+    Ok(dir().join(format!("{}.json", name)))
+}
+
+pub fn check_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\']) {
+        return Err(format!(
+            "Invalid login name '{}'. Names can't contain / or \\ or start with a dot.",
+            name
+        ));
+    }
+    Ok(())
+}
+
+/// Saves a recorded login and returns where. What was typed is in there,
+/// passwords too, so only the user can read the file.
+pub fn save(name: &str, steps: &[LoginStep]) -> Result<PathBuf, String> {
+    use std::io::Write;
+    use std::os::unix::fs::OpenOptionsExt;
+
+    let path = path(name)?;
+    let write = || -> std::io::Result<()> {
+        std::fs::create_dir_all(dir())?;
+        let mut file = std::fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)?;
+        let json = serde_json::to_string_pretty(steps).map_err(std::io::Error::other)?;
+        file.write_all((json + "\n").as_bytes())
+    };
+    write().map_err(|e| format!("Cannot write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+pub fn load(name: &str) -> Result<Vec<LoginStep>, String> {
+    let path = path(name)?;
+    let text = std::fs::read_to_string(&path).map_err(|_| {
+        format!(
+            "No recorded login named '{}'. Record one with 'plwr login record {}'.",
+            name, name
+        )
+    })?;
+    serde_json::from_str(&text).map_err(|e| format!("Invalid login {}: {}", path.display(), e))
+}
@@ -0,0 +1,157 @@
+use serde_json::{json, Value};
+use std::fs::File;
+use std::io::{Read, Write};
+use std::os::unix::fs::MetadataExt;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+/// The daemon's log, one JSON object per line, when it has one.
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+pub const LEVELS: [&str; 4] = ["debug", "info", "warn", "error"];
+
+/// Where a session's daemon logs to. The log of the session's previous run
+/// is kept next to it, with `.1` added.
+pub fn path(session: &str) -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("plwr")
+        .join("logs")
+        .join(format!("{}.log", session))
+}
+
+/// Starts a new log for a session that's starting, keeping the last one.
+pub fn open_new(path: &Path) -> std::io::Result<File> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    if path.exists() {
+        std::fs::rename(path, previous(path))?;
+    }
+    open(path)
+}
+
+fn previous(path: &Path) -> PathBuf {
+    let mut name = path.as_os_str().to_owned();
+    name.push(".1");
+    PathBuf::from(name)
+}
+
+fn open(path: &Path) -> std::io::Result<File> {
+    std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+}
+
+/// Makes `log` write to `path`, appending to what's there.
+pub fn init(path: &Path) -> std::io::Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    *LOG.lock().unwrap() = Some(open(path)?);
+    Ok(())
+}
+
+/// Logs `msg` with the fields of `fields`, an object, alongside.
+pub fn log(level: &str, msg: impl Into<String>, fields: Value) {
+    let mut guard = LOG.lock().unwrap();
+    let Some(file) = guard.as_mut() else {
+        return;
+    };
+    let mut entry = json!({
+        "time": now(),
+        "level": level,
+        "msg": msg.into(),
+    });
+    if let (Value::Object(entry), Value::Object(fields)) = (&mut entry, fields) {
+        entry.extend(fields);
+    }
+    let mut line = entry.to_string();
+    line.push('\n');
+    file.write_all(line.as_bytes()).ok();
+}
+
+pub fn info(msg: impl Into<String>) {
+    log("info", msg, Value::Null);
+}
+
+pub fn error(msg: impl Into<String>) {
+    log("error", msg, Value::Null);
+}
+
+/// A log line the way `plwr logs` shows it, or None if it's below
+/// `min_level`. Lines that aren't entries, like a panic the daemon wrote to
+/// stderr, are shown as they are.
+pub fn format(line: &str, min_level: &str) -> Option<String> {
+    let Ok(entry) = serde_json::from_str::<Value>(line) else {
+        return Some(line.to_string());
+    };
+    let level = entry["level"].as_str().unwrap_or("info");
+    if rank(level) < rank(min_level) {
+        return None;
+    }
+    let time = entry["time"].as_str().unwrap_or("");
+    // 2026-01-02T03:04:05.678Z as 2026-01-02 03:04:05.678
+    let time = time.trim_end_matches('Z').replacen('T', " ", 1);
+    Some(format!(
+        "{} {:<5} {}",
+        time,
+        level.to_uppercase(),
+        entry["msg"].as_str().unwrap_or("")
+    ))
+}
+
+/// Prints the log in `file` from `path` at `min_level` and up. With
+/// `follow`, keeps printing what's added, moving on to the new log when the
+/// session starts again.
+pub async fn show(
+    mut file: File,
+    path: &Path,
+    min_level: &str,
+    follow: bool,
+) -> std::io::Result<()> {
+    let mut pending = String::new();
+    loop {
+        // Checked before reading so the end of the old log isn't missed
+        let replaced = follow
+            && match (file.metadata(), std::fs::metadata(path)) {
+                (Ok(old), Ok(new)) => old.ino() != new.ino(),
+                _ => false,
+            };
+        file.read_to_string(&mut pending)?;
+        // Only whole lines, the rest may still be being written
+        while let Some(end) = pending.find('\n') {
+            if let Some(line) = format(&pending[..end], min_level) {
+                println!("{}", line);
+            }
+            pending.drain(..=end);
+        }
+        if !follow {
+            if let Some(line) = format(&pending, min_level).filter(|_| !pending.is_empty()) {
+                println!("{}", line);
+            }
+            return Ok(());
+        }
+        if replaced {
+            file = File::open(path)?;
+            pending.clear();
+        } else {
+            tokio::time::sleep(std::time::Duration::from_millis(250)).await;
+        }
+    }
+}
+
+fn rank(level: &str) -> usize {
+    LEVELS.iter().position(|l| *l == level).unwrap_or(1)
+}
+
+/// The current time as `YYYY-MM-DDTHH:MM:SS.sssZ`.
+fn now() -> String {
+    let format = time::macros::format_description!(
+        "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:3]Z"
+    );
+    time::OffsetDateTime::now_utc()
+        .format(&format)
+        .unwrap_or_default()
+}
@@ -1,12 +1,24 @@
+mod batch;
+mod blocklist;
+mod chrome_cookies;
 mod client;
 mod daemon;
+mod glob;
+mod har;
+mod login;
+mod logs;
 mod protocol;
 mod pw_ext;
+mod script;
+mod socket;
+mod suggestions;
+mod tree;
+mod viewport;
 
-use crate::protocol::Command;
-use clap::{CommandFactory, Parser, Subcommand};
-use std::collections::HashSet;
-use std::path::PathBuf;
+use crate::protocol::{Command, LoginStep, Response, ScreenshotCompare};
+use clap::{CommandFactory, FromArgMatches, Parser, Subcommand};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
 use std::process::ExitCode;
 
 #[derive(Parser)]
@@ -48,10 +60,22 @@ const EXAMPLES: &str = "\x1b[1;4mExamples:\x1b[0m
   Start the browser and navigate:
     plwr start                           # start headless browser
     plwr start --headed                  # start with visible window
+    plwr start --fake-media              # fake camera and mic for video calls
+    plwr start --profile ~/.plwr-profile # stay logged in between sessions
+    plwr start --persist                 # pick up where you left off after a reboot
+    plwr start --idle-timeout 30         # stop by itself after 30 idle minutes
     plwr open https://example.com
     plwr text h1                         # \"Example Domain\"
     plwr attr a href                     # \"https://www.iana.org/...\"
     plwr stop
+    plwr sessions                        # list running sessions
+    plwr stop --all                      # stop every running session
+    plwr status                          # PID, URL, tabs, cookies, memory...
+    plwr ping                            # is the session alive and answering?
+    plwr logs --follow                   # what the session's daemon is doing
+    plwr batch < commands.txt            # many commands over one connection
+    plwr run checkout.plwr               # run a script of plwr commands
+    plwr shell -- bash scrape.sh         # one connection for a whole script
 
   Fill a form and submit:
     plwr fill '#email' 'alice@test.com'
@@ -64,6 +88,24 @@ const EXAMPLES: &str = "\x1b[1;4mExamples:\x1b[0m
     plwr click 'li.item >> nth=2'        # third match
     plwr text ':nth-match(li.item, 2)'   # alternative syntax
 
+  Mouse and form controls:
+    plwr hover '.dropdown-trigger'       # reveal a hover menu
+    plwr dblclick '.editable-cell'
+    plwr check '#agree-terms'
+    plwr uncheck '#newsletter'
+
+  Target elements the way a user sees them instead of by selector:
+    plwr click --role button --name 'Sign in'
+    plwr fill --role textbox --name Email 'alice@test.com'
+    plwr text --role heading --name Welcome --exact
+    plwr fill --label 'Email address' 'alice@test.com'
+    plwr fill --placeholder 'Search…' 'shoes'
+    plwr text --alt 'Company logo'
+    plwr hover --title 'More options'
+    plwr click --text-matches '^\\d+ items? in cart$'
+    plwr fill 'input' --right-of 'text=Quantity' 3
+    plwr click 'button' --below '#search' --near 'text=Filters'
+
   Chain with shell conditionals:
     plwr exists '.cookie-banner' && plwr click '.accept-cookies'
 
@@ -76,12 +118,55 @@ const EXAMPLES: &str = "\x1b[1;4mExamples:\x1b[0m
     plwr cookie --list                   # show all as JSON
     plwr cookie --clear
 
+  Log in once, reuse it later:
+    plwr state save auth.json            # cookies + localStorage
+    plwr state load auth.json            # in another session or CI run
+    plwr login replay example            # redo a recorded login when it expires
+    plwr import-profile --from-chrome    # cookies from your Chrome profile
+    plwr snapshot-state save checkpoint  # roll back later with restore
+
+  Inspect network traffic:
+    plwr net log --filter '/api/'        # requests since the session started
+    plwr net log --since \"$(date +%s000)\"
+    plwr net body '**/api/products*'     # reuse data the page fetched
+    plwr net timing '**/api/**'          # find slow endpoints
+    plwr net save '**/*.pdf' --dir docs/ # keep every PDF the page loads
+    plwr net failures --fail             # broken assets fail CI
+    plwr net clear
+    plwr har-start                       # record a HAR...
+    plwr open https://example.com
+    plwr har-stop capture.har            # ...and save it
+    plwr mock --har capture.har          # replay it without the network
+    plwr mock '**/api/user' --body-file user.json  # canned API response
+    plwr block --type image,font         # skip heavy assets when scraping
+    plwr block --preset trackers --preset ads  # cleaner screenshots
+    plwr wait-response '**/api/orders*' --then '#submit' --print-body
+    plwr wait-request '**/track/event' --print  # check an analytics call
+    plwr fetch GET /api/me               # API call with the page's cookies
+    plwr cache disable                   # measure cold loads
+    plwr clear-site-data                 # back to a first-time visitor
+    plwr idb dump app-db todos           # IndexedDB records as JSON
+    plwr sw bypass on                    # keep a PWA's cache out of the way
+    plwr cachestorage dump precache-v2   # what the service worker cached
+    plwr auth admin:hunter2 --origin https://staging.example.com
+
   Run JavaScript:
     plwr eval 'document.title'
     plwr eval '({count: document.querySelectorAll(\"li\").length})'
 
   Inspect the DOM:
     plwr tree '.sidebar'                 # JSON tree of element
+    plwr table 'table.results' --format csv
+    plwr links 'nav' --same-origin       # JSON list of links
+    plwr markdown 'article'              # element as Markdown
+    plwr snapshot save home              # remember the DOM and layout...
+    plwr snapshot diff home              # ...and list what changed since
+    plwr meta                            # title, description, og:, twitter:
+    plwr structured-data                 # parsed JSON-LD blocks
+    plwr audit a11y --tags wcag2aa       # axe-core violations, exit 1 if any
+    plwr find 'Add to cart'              # suggest selectors for some text
+    plwr pick                            # click an element to get a selector
+    plwr selector-for 'text=Add to cart' # stable selector for a match
     plwr count '.search-result'          # number of matches
 
   Screenshot and video:
@@ -92,13 +177,26 @@ const EXAMPLES: &str = "\x1b[1;4mExamples:\x1b[0m
 
   Adjust viewport for responsive testing:
     plwr viewport 375 667               # iPhone SE
+    plwr viewport --preset mobile        # phone: 390x844 at 3x, touch
     plwr screenshot --path mobile.png
     plwr viewport 1280 720              # desktop
 
+  Emulate the user's environment:
+    plwr geo 59.33 18.07                 # allow and fake the location
+    plwr click '#find-nearest-store'
+    plwr geo --clear
+    plwr emulate --color-scheme dark     # screenshot dark mode
+    plwr lang 'sv-SE,en;q=0.8'           # header, navigator.language, Intl
+    plwr emulate --media print           # check the print stylesheet
+    plwr computed-style '.sidebar' display
+
   Keyboard input:
+    plwr focus '#search'                 # put the cursor in a field
     plwr press Enter
     plwr press Control+a                 # select all
     plwr press Meta+c                    # copy (macOS)
+    plwr press Tab Tab Enter             # keyboard navigation
+    plwr blur '#email'                   # trigger blur validation
 
   Sessions — each session is an independent browser with its own
   cookies, headers, and page state:
@@ -171,8 +269,11 @@ const EXAMPLES: &str = "\x1b[1;4mExamples:\x1b[0m
   PLAYWRIGHT_HEADED        Show browser window (set to any value)
   PLWR_SESSION             Default session name (default: \"default\")
   PLWR_TIMEOUT             Default timeout in ms (default: 5000)
-  PLWR_IGNORE_CERT_ERRORS  Ignore TLS/SSL certificate errors
-  PLWR_CDP                 Chrome channel for CDP connection (stable, beta, canary, dev)";
+  PLWR_IGNORE_HTTPS_ERRORS Ignore TLS/SSL certificate errors
+  PLWR_CDP                 Chrome channel for CDP connection (stable, beta, canary, dev)
+  PLWR_PROFILE             Persistent browser profile directory
+  PLWR_PERSIST             Keep session state across restarts (set to true)
+  PLWR_IDLE_TIMEOUT        Stop sessions idle for this many minutes";
 
 #[derive(Subcommand)]
 enum Cmd {
@@ -184,9 +285,10 @@ enum Cmd {
         /// Record video of the session, saved to this path on stop (.webm, .mp4, etc.)
         #[arg(long)]
         video: Option<String>,
-        /// Ignore TLS/SSL certificate errors (useful behind corporate proxies)
-        #[arg(long)]
-        ignore_cert_errors: bool,
+        /// Ignore TLS/SSL certificate errors (self-signed certs on local or
+        /// staging environments, corporate proxies)
+        #[arg(long, alias = "ignore-cert-errors")]
+        ignore_https_errors: bool,
         /// Connect to your running Chrome via CDP.
         /// Value is a channel name, user-data-dir path, or ws:// URL.
         /// Channels: stable (default), beta, canary, dev.
@@ -194,12 +296,56 @@ enum Cmd {
         /// Enable in Chrome: chrome://inspect/#remote-debugging
         #[arg(long, env = "PLWR_CDP", num_args = 0..=1, default_missing_value = "stable")]
         cdp: Option<String>,
+        /// Enable touch events in the browser context (required for `tap`)
+        #[arg(long)]
+        touch: bool,
+        /// Give pages a fake camera and microphone, with permission to use
+        /// them, for video-call and camera-capture UIs
+        #[arg(long)]
+        fake_media: bool,
+        /// Video for the fake camera (.y4m or .mjpeg)
+        #[arg(long, requires = "fake_media")]
+        fake_video: Option<String>,
+        /// Audio for the fake microphone (.wav)
+        #[arg(long, requires = "fake_media")]
+        fake_audio: Option<String>,
+        /// Keep the browser profile (cookies, logins, storage, extensions)
+        /// in this directory, so it survives `stop` and `start`
+        #[arg(long, env = "PLWR_PROFILE", value_name = "DIR")]
+        profile: Option<String>,
+        /// Save cookies, storage, headers, viewport and the current URL every
+        /// few seconds, and restore them when this session is started again
+        #[arg(long, env = "PLWR_PERSIST")]
+        persist: bool,
+        /// Stop the session once it has had no commands for this many minutes
+        #[arg(long, env = "PLWR_IDLE_TIMEOUT", value_name = "MINUTES")]
+        idle_timeout: Option<f64>,
     },
     /// Stop the browser
-    Stop,
+    ///
+    /// Examples:
+    ///   plwr stop
+    ///   plwr stop --all                     # every running session
+    Stop {
+        /// Stop every running session, not just this one
+        #[arg(long)]
+        all: bool,
+    },
 
     /// Navigate to a URL
-    Open { url: String },
+    ///
+    /// With --show-redirects, prints the HTTP redirect chain as JSON: each
+    /// hop's url, status and response headers, ending with the final page.
+    ///
+    /// Examples:
+    ///   plwr open https://example.com
+    ///   plwr open https://example.com/login --show-redirects
+    Open {
+        url: String,
+        /// Print each redirect hop as JSON
+        #[arg(long)]
+        show_redirects: bool,
+    },
     /// Reload the current page
     Reload,
     /// Print the current page URL
@@ -219,6 +365,48 @@ enum Cmd {
         #[arg(required = true)]
         selectors: Vec<String>,
     },
+    /// Wait for a response whose URL matches a glob and print it as JSON.
+    ///
+    /// Prints method, url, status and headers (and the body with
+    /// --print-body; JSON bodies are parsed). Use --then to click something
+    /// after waiting has started, so a fast response isn't missed.
+    ///
+    /// Examples:
+    ///   plwr wait-response '**/api/orders*' --then '#submit'
+    ///   plwr wait-response '**/api/orders*' --status 2xx --print-body
+    WaitResponse {
+        /// URL glob (`**` matches anything, `*` anything but `/`)
+        pattern: String,
+        /// Only accept these statuses, e.g. 200 or 2xx
+        #[arg(long, value_parser = parse_status_filter)]
+        status: Option<String>,
+        /// Include the response body
+        #[arg(long)]
+        print_body: bool,
+        /// Click this element once waiting has started
+        #[arg(long, value_name = "SELECTOR")]
+        then: Option<String>,
+    },
+    /// Wait for the page to send a request whose URL matches a glob.
+    ///
+    /// With --print, prints method, url, headers and post_data as JSON
+    /// (JSON bodies are parsed). Requests that are blocked or mocked still
+    /// count, so this works for analytics and beacon calls you don't want
+    /// to reach the server.
+    ///
+    /// Examples:
+    ///   plwr wait-request '**/track/event' --print
+    ///   plwr wait-request '**/api/save' --then '#save' --print
+    WaitRequest {
+        /// URL glob (`**` matches anything, `*` anything but `/`)
+        pattern: String,
+        /// Print the request as JSON
+        #[arg(long)]
+        print: bool,
+        /// Click this element once waiting has started
+        #[arg(long, value_name = "SELECTOR")]
+        then: Option<String>,
+    },
 
     /// Click an element matching a CSS selector
     Click {
@@ -241,12 +429,82 @@ enum Cmd {
         /// Hold Shift during click
         #[arg(long)]
         shift: bool,
+        /// Mouse button to click with
+        #[arg(long, value_parser = ["left", "right", "middle"], conflicts_with_all = ["right", "middle"])]
+        button: Option<String>,
+        /// Modifier keys to hold during click, comma-separated (e.g. Control,Shift)
+        #[arg(long, value_delimiter = ',', value_parser = ["Alt", "Control", "Meta", "Shift"])]
+        modifiers: Vec<String>,
+        /// Click position relative to the element's top-left corner, as X,Y
+        #[arg(long, value_parser = parse_xy)]
+        position: Option<(f64, f64)>,
+        /// Number of clicks (e.g. 3 to select a paragraph)
+        #[arg(long)]
+        count: Option<u32>,
+        /// Skip actionability checks (e.g. element covered by an overlay)
+        #[arg(long)]
+        force: bool,
+        /// Only run actionability checks, don't click
+        #[arg(long)]
+        trial: bool,
     },
+    /// Tap an element matching a CSS selector (requires `start --touch` or
+    /// `viewport --touch`)
+    Tap { selector: String },
     /// Fill text into an input matching a CSS selector
     Fill { selector: String, text: String },
 
-    /// Press a keyboard key or chord (e.g. Enter, Escape, Control+c)
-    Press { key: String },
+    /// Fill many fields at once from a JSON or YAML file mapping selectors to
+    /// values.
+    ///
+    /// Strings and numbers are filled in (or selected, for <select>),
+    /// booleans check or uncheck, and arrays select multiple options.
+    /// Fields are filled in file order. Use - to read from stdin.
+    ///
+    /// Example data.json:
+    ///   {"#name": "Alice", "#country": "se", "#agree": true, "#tags": ["a", "b"]}
+    ///
+    /// Or data.yaml:
+    ///   "#name": Alice
+    ///   "#agree": true
+    FillForm {
+        /// Path to the JSON or YAML file (- for stdin)
+        file: String,
+    },
+
+    /// Clear the value of an input, textarea, or contenteditable element
+    Clear { selector: String },
+
+    /// Press keyboard keys or chords (e.g. Enter, Escape, Control+c)
+    ///
+    /// Several keys are pressed in order, in a single round trip.
+    ///
+    /// Examples:
+    ///   plwr press Enter
+    ///   plwr press Tab Tab Enter
+    ///   plwr press ArrowDown --repeat 5
+    Press {
+        /// Keys or chords to press, in order
+        #[arg(required = true)]
+        keys: Vec<String>,
+        /// Press the whole sequence this many times
+        #[arg(long, default_value_t = 1)]
+        repeat: usize,
+    },
+
+    /// Hold down or release a key, e.g. to keep Shift held across clicks.
+    ///
+    /// Examples:
+    ///   plwr key down Shift               # hold Shift
+    ///   plwr click '#row-1'
+    ///   plwr click '#row-5'               # shift-click range selection
+    ///   plwr key up Shift                 # release Shift
+    Key {
+        /// "down" (press and hold) or "up" (release)
+        action: String,
+        /// Key name (e.g. Shift, Control, Alt, Meta, a)
+        key: String,
+    },
 
     /// Type text by sending individual key events for each character
     Type {
@@ -256,26 +514,166 @@ enum Cmd {
         delay: Option<f64>,
     },
 
+    /// Insert text at the focused element without sending key events (emoji, CJK, etc.)
+    InsertText { text: String },
+
     /// Exit 0 if selector exists, exit 1 if not (for && chaining)
     Exists { selector: String },
+    /// Exit 0 if the element is visible, exit 1 if not (or if it doesn't exist)
+    IsVisible { selector: String },
+    /// Exit 0 if the element is enabled, exit 1 if disabled
+    IsEnabled { selector: String },
+    /// Exit 0 if the checkbox or radio button is checked, exit 1 if not
+    IsChecked { selector: String },
+    /// Exit 0 if the element is editable (enabled and not readonly), exit 1 if not
+    IsEditable { selector: String },
 
     /// Print the textContent of the first matching element
-    Text { selector: String },
+    Text {
+        selector: String,
+        /// Print a JSON array with the text of every match
+        #[arg(long)]
+        all: bool,
+        /// Collapse runs of whitespace and trim the ends
+        #[arg(long)]
+        trim: bool,
+    },
     /// Print the value of an attribute on the first matching element
-    Attr { selector: String, name: String },
+    Attr {
+        selector: String,
+        name: String,
+        /// Print a JSON array with the attribute of every match (null where missing)
+        #[arg(long)]
+        all: bool,
+    },
 
     /// Print the number of elements matching a CSS selector
-    Count { selector: String },
+    ///
+    /// The filters only count matches in that state and can be combined.
+    ///
+    /// Examples:
+    ///   plwr count 'li.item' --visible
+    ///   plwr count 'button' --enabled
+    ///   plwr count 'tr' --matching '^Error'
+    Count {
+        selector: String,
+        /// Only count visible elements
+        #[arg(long, conflicts_with = "hidden")]
+        visible: bool,
+        /// Only count hidden elements
+        #[arg(long)]
+        hidden: bool,
+        /// Only count enabled elements
+        #[arg(long, conflicts_with = "disabled")]
+        enabled: bool,
+        /// Only count disabled elements
+        #[arg(long)]
+        disabled: bool,
+        /// Only count elements whose text matches this regex (or /regex/flags)
+        #[arg(long, value_name = "REGEX")]
+        matching: Option<String>,
+    },
+
+    /// Save the session's cookies and local storage to a file, or replace
+    /// them with a saved one, to reuse a login across sessions and CI runs.
+    ///
+    /// The file is byte for byte what Playwright's `storageState({ path })`
+    /// writes, so it also works with Playwright's `storageState` option and
+    /// `--save-storage`. Loading
+    /// replaces the browser context and loads the current page again, which
+    /// isn't possible with --cdp or while recording video or a HAR.
+    ///
+    /// Examples:
+    ///   plwr state save auth.json
+    ///   plwr state load auth.json
+    State {
+        #[arg(value_parser = ["save", "load"])]
+        action: String,
+        /// Storage state JSON file
+        path: String,
+    },
+
+    /// Save a named snapshot of the session's state in the daemon, or go back
+    /// to one, to try something destructive and roll back.
+    ///
+    /// A snapshot holds cookies, local storage, extra headers, the viewport
+    /// and the current URL. Restoring replaces the browser context and opens
+    /// the snapshot's URL, which isn't possible with --cdp or while
+    /// recording video or a HAR.
+    ///
+    /// Examples:
+    ///   plwr snapshot-state save logged-in
+    ///   plwr click 'text=Delete account'
+    ///   plwr snapshot-state restore logged-in
+    SnapshotState {
+        /// save or restore
+        #[arg(value_parser = ["save", "restore"])]
+        action: String,
+        /// Snapshot name
+        name: String,
+    },
+
+    /// Copy cookies from a local Chrome profile into the session, to
+    /// automate sites you're already logged into.
+    ///
+    /// Reads the profile's cookie store (Chrome can keep running) and
+    /// decrypts it with the key in the macOS Keychain or the Linux secret
+    /// service. Needs the sqlite3 command. The profile is the directory name
+    /// in Chrome's user data dir, e.g. "Default" or "Profile 1".
+    ///
+    /// Examples:
+    ///   plwr import-profile --from-chrome
+    ///   plwr import-profile --from-chrome 'Profile 1' --origin github.com
+    ImportProfile {
+        /// Chrome profile to read (default: Default)
+        #[arg(
+            long,
+            required = true,
+            num_args = 0..=1,
+            default_missing_value = "Default",
+            value_name = "PROFILE"
+        )]
+        from_chrome: Option<String>,
+        /// Only import cookies sent to this site
+        #[arg(long)]
+        origin: Option<String>,
+    },
 
     /// Set a cookie (use --list to show all, --clear to remove all)
+    ///
+    /// The cookie applies to the current page's URL unless --url or
+    /// --domain says otherwise. Without --expires it's a session cookie.
+    ///
+    /// Examples:
+    ///   plwr cookie session_id abc123
+    ///   plwr cookie token xyz --domain .example.com --secure --http-only
+    ///   plwr cookie consent yes --expires $(date -d '+1 year' +%s) --same-site lax
     Cookie {
         /// Cookie name (omit for --list or --clear)
         name: Option<String>,
         /// Cookie value
         value: Option<String>,
         /// URL the cookie applies to (defaults to current page URL)
-        #[arg(long)]
+        #[arg(long, conflicts_with = "domain")]
         url: Option<String>,
+        /// Domain the cookie applies to; a leading dot includes subdomains
+        #[arg(long)]
+        domain: Option<String>,
+        /// Path the cookie applies to (default /)
+        #[arg(long)]
+        path: Option<String>,
+        /// Expiry as Unix time in seconds
+        #[arg(long)]
+        expires: Option<f64>,
+        /// Hide the cookie from JavaScript
+        #[arg(long)]
+        http_only: bool,
+        /// Only send the cookie over HTTPS
+        #[arg(long)]
+        secure: bool,
+        /// SameSite attribute
+        #[arg(long, value_parser = ["strict", "lax", "none"], ignore_case = true)]
+        same_site: Option<String>,
         /// List all cookies as JSON
         #[arg(long)]
         list: bool,
@@ -284,35 +682,253 @@ enum Cmd {
         clear: bool,
     },
 
-    /// Set the browser viewport size
+    /// Name a selector for this session, usable as @name wherever a selector
+    /// is accepted (use --list to show all, --clear to remove all).
+    ///
+    /// Examples:
+    ///   plwr alias submit-btn 'form#checkout button[type=submit]'
+    ///   plwr click @submit-btn
+    ///   plwr text '@results >> li >> nth=0'
+    Alias {
+        /// Alias name (omit for --list or --clear)
+        name: Option<String>,
+        /// Selector the alias stands for (may use other aliases)
+        selector: Option<String>,
+        /// List all aliases as JSON
+        #[arg(long)]
+        list: bool,
+        /// Remove all aliases
+        #[arg(long)]
+        clear: bool,
+    },
+
+    /// Resolve later selectors inside an element (use --clear to reset).
+    ///
+    /// Every selector is prefixed with `<selector> >> ` until cleared, so
+    /// commands only see what's inside it. A new scope replaces the old one.
+    ///
+    /// Examples:
+    ///   plwr within '#checkout-modal'
+    ///   plwr fill '[name=card]' 4242424242424242
+    ///   plwr click 'text=Pay'
+    ///   plwr within --clear
+    Within {
+        /// Selector for the element to scope to
+        #[arg(required_unless_present = "clear")]
+        selector: Option<String>,
+        /// Go back to resolving selectors against the whole page
+        #[arg(long, conflicts_with = "selector")]
+        clear: bool,
+    },
+
+    /// Set the browser viewport size, and optionally the device it emulates
+    ///
+    /// --scale, --mobile and --touch can only be set on a new browser
+    /// context, so changing them replaces the context: cookies, local
+    /// storage and settings from other commands carry over and the current
+    /// page is loaded again. They stay as set until changed
+    /// (e.g. `--mobile=false`).
+    ///
+    /// --preset sets all of them at once: mobile (390x844), tablet
+    /// (820x1180), desktop (1280x720), 4k (3840x2160), or one defined in
+    /// ~/.config/plwr/viewports.json (or $PLWR_VIEWPORTS), like
+    /// {"laptop": {"width": 1440, "height": 900, "scale": 2}}.
+    ///
+    /// Examples:
+    ///   plwr viewport 1280 720
+    ///   plwr viewport 1280 720 --scale 2       # high-DPI screenshots
+    ///   plwr viewport 390 844 --scale 3 --mobile --touch
+    ///   plwr viewport --preset mobile
     Viewport {
         /// Width in pixels
-        width: u32,
+        #[arg(required_unless_present = "preset")]
+        width: Option<u32>,
         /// Height in pixels
-        height: u32,
+        #[arg(required_unless_present = "preset")]
+        height: Option<u32>,
+        /// Named device: mobile, tablet, desktop, 4k or a user preset
+        #[arg(long, conflicts_with_all = ["width", "height"])]
+        preset: Option<String>,
+        /// Device scale factor (device pixels per CSS pixel)
+        #[arg(long)]
+        scale: Option<f64>,
+        /// Emulate a mobile browser: meta viewport tags apply and the page
+        /// can zoom
+        #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+        mobile: Option<bool>,
+        /// Enable touch events (required for `tap`)
+        #[arg(long, num_args = 0..=1, default_missing_value = "true")]
+        touch: Option<bool>,
     },
 
     /// Set an extra HTTP header sent with every request (use --clear to remove all)
+    ///
+    /// With --match the header is only added to requests whose URL matches
+    /// the glob (`**` matches anything, `*` anything but `/`).
+    ///
+    /// Examples:
+    ///   plwr header Authorization 'Bearer tok_xxx'
+    ///   plwr header --match '**/api/**' X-Feature-Flag on
     Header {
         /// Header name (omit to clear all headers)
         name: Option<String>,
         /// Header value
         value: Option<String>,
+        /// Only add the header to requests whose URL matches this glob
+        #[arg(long = "match", value_name = "GLOB")]
+        url_match: Option<String>,
         /// Clear all extra headers
         #[arg(long)]
         clear: bool,
     },
 
+    /// Set the user's preferred languages: the Accept-Language header and
+    /// navigator.language(s) together (Chromium only, use --clear to stop)
+    ///
+    /// Takes an Accept-Language value; the first language also becomes the
+    /// locale for Intl date and number formatting. Can be set before the
+    /// first `open`.
+    ///
+    /// Examples:
+    ///   plwr lang sv-SE
+    ///   plwr lang 'sv-SE,en;q=0.8'
+    ///   plwr lang --clear
+    Lang {
+        /// Accept-Language value, e.g. 'sv-SE,en;q=0.8'
+        #[arg(required_unless_present = "clear")]
+        languages: Option<String>,
+        /// Go back to the browser's own languages
+        #[arg(long, conflicts_with = "languages")]
+        clear: bool,
+    },
+
+    /// Answer HTTP basic/digest auth challenges with these credentials
+    /// (use --clear to stop)
+    ///
+    /// Applies to every page in the session and can be set before the first
+    /// `open`. With --origin, credentials are only sent to that origin.
+    ///
+    /// Examples:
+    ///   plwr auth admin:hunter2
+    ///   plwr auth admin:hunter2 --origin https://staging.example.com
+    ///   plwr auth --clear
+    Auth {
+        /// Credentials as user:password
+        #[arg(required_unless_present = "clear", value_parser = parse_credentials)]
+        credentials: Option<(String, String)>,
+        /// Only send the credentials to this origin
+        #[arg(long)]
+        origin: Option<String>,
+        /// Forget the credentials
+        #[arg(long, conflicts_with_all = ["credentials", "origin"])]
+        clear: bool,
+    },
+
+    /// Grant the geolocation permission and report this position to pages
+    /// (use --clear to stop)
+    ///
+    /// Applies to every page in the session and can be set before the first
+    /// `open`. After --clear, pages are denied the position again.
+    ///
+    /// Examples:
+    ///   plwr geo 59.33 18.07
+    ///   plwr geo -33.87 151.21 --accuracy 10
+    ///   plwr geo --clear
+    #[command(allow_negative_numbers = true)]
+    Geo {
+        /// Latitude, -90 to 90
+        #[arg(required_unless_present = "clear")]
+        latitude: Option<f64>,
+        /// Longitude, -180 to 180
+        #[arg(required_unless_present = "clear")]
+        longitude: Option<f64>,
+        /// Accuracy in meters
+        #[arg(long)]
+        accuracy: Option<f64>,
+        /// Stop reporting a position
+        #[arg(long, conflicts_with_all = ["latitude", "longitude", "accuracy"])]
+        clear: bool,
+    },
+
+    /// Emulate CSS media features, e.g. to screenshot dark mode or inspect
+    /// print styles without making a PDF
+    ///
+    /// Settings last until changed and can be made before the first `open`.
+    /// Features not given are left as they are; --reset goes back to the
+    /// browser's own values.
+    ///
+    /// Examples:
+    ///   plwr emulate --color-scheme dark
+    ///   plwr emulate --media print
+    ///   plwr emulate --reduced-motion reduce
+    ///   plwr emulate --forced-colors active
+    ///   plwr emulate --reset
+    Emulate {
+        /// Media type
+        #[arg(long, value_parser = ["screen", "print"])]
+        media: Option<String>,
+        /// prefers-color-scheme
+        #[arg(long, value_parser = ["dark", "light", "no-preference"])]
+        color_scheme: Option<String>,
+        /// prefers-reduced-motion
+        #[arg(long, value_parser = ["reduce", "no-preference"])]
+        reduced_motion: Option<String>,
+        /// forced-colors, as in Windows High Contrast mode
+        #[arg(long, value_parser = ["active", "none"])]
+        forced_colors: Option<String>,
+        /// Stop emulating all media features
+        #[arg(
+            long,
+            conflicts_with_all = ["media", "color_scheme", "reduced_motion", "forced_colors"]
+        )]
+        reset: bool,
+    },
+
     /// Set files on a file input element (e.g. for upload)
+    #[command(alias = "upload")]
     InputFiles {
         /// CSS selector for the file input
         selector: String,
-        /// File paths to set (omit to clear)
+        /// File paths to set
         #[arg(trailing_var_arg = true)]
         paths: Vec<String>,
+        /// Clear the selected files (the same as giving no paths)
+        #[arg(long, conflicts_with = "paths")]
+        clear: bool,
+    },
+
+    /// Click an element that opens a file chooser and supply files to it.
+    ///
+    /// For upload buttons that open the chooser from JavaScript (e.g. via a
+    /// hidden or detached <input type=file>) instead of exposing an input.
+    ///
+    /// Examples:
+    ///   plwr filechooser '#upload-btn' ./photo.jpg
+    ///   plwr filechooser '.dropzone' a.txt b.txt
+    Filechooser {
+        /// CSS selector for the element that opens the file chooser
+        selector: String,
+        /// File paths to supply
+        #[arg(required = true, trailing_var_arg = true)]
+        paths: Vec<String>,
+    },
+
+    /// Click an element that starts a download and save the downloaded file.
+    ///
+    /// Prints the path the file was saved to.
+    ///
+    /// Examples:
+    ///   plwr download 'a.export'                  # save to ./<suggested name>
+    ///   plwr download 'a.export' out.csv          # save as out.csv
+    ///   plwr download '#report-btn' ~/reports/    # save into a directory
+    Download {
+        /// CSS selector for the element that triggers the download
+        selector: String,
+        /// File or directory to save to (default: current directory)
+        path: Option<String>,
     },
 
-    /// Select option(s) in a <select> element by value
+    /// Select option(s) in a <select> element by value, label, or index
     Select {
         /// CSS selector for the <select> element
         selector: String,
@@ -322,15 +938,59 @@ enum Cmd {
         /// Match by visible label text instead of value attribute
         #[arg(long)]
         label: bool,
+        /// Match by zero-based option index instead of value attribute
+        #[arg(long, conflicts_with = "label")]
+        index: bool,
     },
 
     /// Hover over an element matching a CSS selector
-    Hover { selector: String },
+    Hover {
+        selector: String,
+        /// Skip actionability checks (e.g. element covered by an overlay)
+        #[arg(long)]
+        force: bool,
+        /// Only run actionability checks, don't hover
+        #[arg(long)]
+        trial: bool,
+    },
+
+    /// Outline all elements matching a selector and print how many matched.
+    ///
+    /// The overlay is removed after --duration milliseconds; the command
+    /// returns immediately, so a following screenshot will include it.
+    ///
+    /// Examples:
+    ///   plwr highlight '.card'                # in a --headed session
+    ///   plwr highlight 'li.item' --duration 10000
+    ///   plwr highlight 'li.item' && plwr screenshot --path items.png
+    Highlight {
+        /// CSS selector for the elements to highlight
+        selector: String,
+        /// How long to keep the overlay, in milliseconds
+        #[arg(long, default_value_t = 3000)]
+        duration: u64,
+    },
 
     /// Check a checkbox or radio button
-    Check { selector: String },
+    Check {
+        selector: String,
+        /// Skip actionability checks (e.g. element covered by an overlay)
+        #[arg(long)]
+        force: bool,
+        /// Only run actionability checks, don't check
+        #[arg(long)]
+        trial: bool,
+    },
     /// Uncheck a checkbox
-    Uncheck { selector: String },
+    Uncheck {
+        selector: String,
+        /// Skip actionability checks (e.g. element covered by an overlay)
+        #[arg(long)]
+        force: bool,
+        /// Only run actionability checks, don't uncheck
+        #[arg(long)]
+        trial: bool,
+    },
 
     /// Double-click an element matching a CSS selector
     Dblclick {
@@ -353,6 +1013,12 @@ enum Cmd {
         /// Hold Shift during double-click
         #[arg(long)]
         shift: bool,
+        /// Skip actionability checks (e.g. element covered by an overlay)
+        #[arg(long)]
+        force: bool,
+        /// Only run actionability checks, don't double-click
+        #[arg(long)]
+        trial: bool,
     },
 
     /// Focus an element matching a CSS selector
@@ -363,11 +1029,33 @@ enum Cmd {
     /// Print the innerHTML of the first matching element
     InnerHtml { selector: String },
 
+    /// Print the rendered text (innerText) of the first matching element
+    ///
+    /// Unlike `text`, hidden elements are left out and line breaks follow
+    /// the layout, so the result matches what a user sees.
+    InnerText {
+        selector: String,
+        /// Print a JSON array with the text of every match
+        #[arg(long)]
+        all: bool,
+    },
+
     /// Print the value of an input, textarea, or select element
+    #[command(alias = "value")]
     InputValue { selector: String },
 
-    /// Scroll an element into view
-    Scroll { selector: String },
+    /// Scroll an element into view, or scroll the page or a scrollable element
+    #[command(alias = "scroll-into-view")]
+    Scroll {
+        /// Element to scroll into view (or to scroll within, with --by/--to)
+        selector: Option<String>,
+        /// Scroll by an offset in pixels, as X,Y (e.g. 0,800)
+        #[arg(long, value_parser = parse_xy, allow_hyphen_values = true, conflicts_with = "to")]
+        by: Option<(f64, f64)>,
+        /// Scroll to an edge
+        #[arg(long, value_parser = ["top", "bottom", "left", "right"])]
+        to: Option<String>,
+    },
 
     /// Copy content from an element to the browser clipboard (text or images)
     ClipboardCopy { selector: String },
@@ -375,7 +1063,29 @@ enum Cmd {
     /// Paste from the browser clipboard at the currently focused element
     ClipboardPaste,
 
+    /// Read or write the browser clipboard as text.
+    ///
+    /// Examples:
+    ///   plwr clipboard get                 # print clipboard text
+    ///   plwr clipboard set 'hello'         # put text on the clipboard
+    Clipboard {
+        /// "get" (print clipboard text) or "set" (write text to clipboard)
+        action: String,
+        /// Text to write (for "set")
+        text: Option<String>,
+    },
+
+    /// Print an element's bounding box as JSON (x, y, width, height, in_viewport).
+    ///
+    /// Coordinates are in CSS pixels relative to the viewport. in_viewport is
+    /// true if any part of the element is visible without scrolling.
+    Bbox {
+        /// CSS selector for the element
+        selector: String,
+    },
+
     /// Print computed CSS styles for an element (all styles if no properties given)
+    #[command(alias = "style")]
     ComputedStyle {
         /// CSS selector for the element
         selector: String,
@@ -400,6 +1110,28 @@ enum Cmd {
         text: Option<String>,
     },
 
+    /// Handle browser dialogs (alert/confirm/prompt), or print the last one's message.
+    ///
+    /// `accept` and `dismiss` arm a handler for the next dialog, like
+    /// `next-dialog`. With --persist the handler stays armed for every
+    /// following dialog until another one replaces it.
+    ///
+    /// Examples:
+    ///   plwr dialog accept                  # click OK on the next dialog
+    ///   plwr dialog accept --text 'Alice'   # answer the next prompt
+    ///   plwr dialog dismiss --persist       # cancel every dialog from now on
+    ///   plwr dialog last                    # message of the most recent dialog
+    Dialog {
+        /// "accept", "dismiss" or "last"
+        action: String,
+        /// Text to enter in a prompt() dialog before accepting
+        #[arg(long)]
+        text: Option<String>,
+        /// Keep handling every dialog instead of just the next one
+        #[arg(long)]
+        persist: bool,
+    },
+
     /// Print captured browser console logs as JSON (automatically captured after open)
     Console {
         /// Clear the console log buffer
@@ -423,29 +1155,778 @@ enum Cmd {
         include_ws_messages: bool,
     },
 
-    /// Evaluate arbitrary JavaScript in page context, print the result
-    Eval { js: String },
+    /// Print every request the browser has made this session as JSON.
+    ///
+    /// Unlike `network`, which reads a buffer inside the page, the log is
+    /// recorded by the daemon, so it survives navigations and covers
+    /// requests from every page. Each entry has method, url, status, type,
+    /// size (bytes transferred), duration (ms) and ts (start time, ms since
+    /// the epoch); failed requests have a null status and an error.
+    ///
+    /// `net capture` makes the log keep response bodies from then on, and
+    /// `net body <glob>` prints the body of the most recent response whose
+    /// URL matches, already decompressed; JSON bodies are printed as JSON.
+    ///
+    /// `net timing <glob>` breaks down where the time went for each matching
+    /// request (ms): dns, connect, tls, send, ttfb (waiting for the first
+    /// byte), download and total. Phases that didn't happen, like DNS on a
+    /// reused connection, are null.
+    ///
+    /// `net save <glob> --dir DIR` writes the body of every successful
+    /// response matching the glob into DIR as it arrives, named after the
+    /// last part of the URL path. `net save --stop` stops all saving and
+    /// prints the files written.
+    ///
+    /// `net failures` lists the requests since the current page was loaded
+    /// that failed or got a 4xx/5xx status, with the page that made them
+    /// (initiator). With --fail it exits non-zero when there are any.
+    ///
+    /// Examples:
+    ///   plwr net log
+    ///   plwr net log --filter '/api/'
+    ///   plwr net log --since "$(date +%s000)"
+    ///   plwr net capture
+    ///   plwr net body '**/api/products*'
+    ///   plwr net timing '**/api/**'
+    ///   plwr net save '**/*.pdf' --dir downloads/
+    ///   plwr net failures --fail
+    ///   plwr net clear
+    Net {
+        /// log: print the recorded requests, capture: keep response bodies,
+        /// body: print the last response body matching a URL glob, timing:
+        /// print timings of requests matching a URL glob, save: write
+        /// matching responses to disk, failures: print failed requests,
+        /// clear: forget them
+        #[arg(value_parser = ["log", "capture", "body", "timing", "save", "failures", "clear"])]
+        action: String,
+        /// URL glob, for `net body`, `net timing` and `net save`
+        pattern: Option<String>,
+        /// Only requests whose URL matches this regex
+        #[arg(long)]
+        filter: Option<String>,
+        /// Only requests started at or after this time (ms since the epoch)
+        #[arg(long)]
+        since: Option<i64>,
+        /// Exit non-zero if `net failures` finds any
+        #[arg(long)]
+        fail: bool,
+        /// Directory for `net save`
+        #[arg(long, default_value = ".")]
+        dir: PathBuf,
+        /// Stop `net save` and print the files it wrote
+        #[arg(long)]
+        stop: bool,
+    },
 
-    /// Take a screenshot (optionally of a specific element)
-    Screenshot {
+    /// Start recording a HAR of all traffic in the session.
+    ///
+    /// Can run before the first `open` so the initial navigation is
+    /// included. Response bodies are embedded unless --omit-content is set.
+    /// Save the recording with `har-stop`.
+    ///
+    /// Examples:
+    ///   plwr har-start
+    ///   plwr har-start --omit-content
+    HarStart {
+        /// Leave response bodies out of the HAR
         #[arg(long)]
-        selector: Option<String>,
-        #[arg(long, default_value = "screenshot.png")]
+        omit_content: bool,
+    },
+
+    /// Stop the HAR recording started by `har-start` and save it.
+    ///
+    /// The file opens in browser devtools (Network > Import HAR) and other
+    /// HAR viewers. Prints the path it was saved to.
+    ///
+    /// Examples:
+    ///   plwr har-stop capture.har
+    HarStop {
+        /// Where to write the HAR file
         path: String,
     },
 
-    /// Dump the DOM tree as JSON (optionally rooted at a selector)
-    Tree {
-        /// CSS selector to use as root
-        selector: Option<String>,
+    /// Abort requests by URL glob or resource type (use --list to show the
+    /// rules, --clear to remove them all).
+    ///
+    /// Globs match the whole URL: `**` matches anything, `*` anything but
+    /// `/`. Rules apply to every page in the session and can be added
+    /// before the first `open`.
+    ///
+    /// Examples:
+    ///   plwr block '**/analytics/**' '**/*.doubleclick.net/**'
+    ///   plwr block --type image,font,media
+    ///   plwr block --preset trackers --preset ads
+    ///   plwr block --preset ./my-blocklist.txt
+    ///   plwr block --list
+    ///   plwr block --clear
+    Block {
+        /// URL globs to block
+        patterns: Vec<String>,
+        /// Resource types to block (comma-separated)
+        #[arg(long, value_delimiter = ',', value_parser = [
+            "document", "stylesheet", "image", "media", "font", "script", "texttrack",
+            "xhr", "fetch", "eventsource", "websocket", "manifest", "other",
+        ])]
+        r#type: Vec<String>,
+        /// Block the domains in a bundled list (trackers, ads) or in a
+        /// filter list file: one domain per line, hosts-file lines or
+        /// adblock `||domain^` rules (repeatable)
+        #[arg(long, value_name = "NAME|FILE")]
+        preset: Vec<String>,
+        /// List the blocking rules as JSON
+        #[arg(long)]
+        list: bool,
+        /// Remove all blocking rules
+        #[arg(long)]
+        clear: bool,
     },
 
-    /// Internal: run the browser daemon (not for direct use)
-    #[command(hide = true)]
-    Daemon,
-}
+    /// Answer requests matching a URL glob with a canned response, or
+    /// with responses recorded in a HAR file.
+    ///
+    /// Mocks stay in place for the rest of the session (use --list to show
+    /// them, --remove to drop one, --clear to drop all). A newer mock for
+    /// the same glob replaces the old one.
+    ///
+    /// With --har, requests are matched by method and URL; ones the HAR has
+    /// no response for are aborted unless --not-found fallback is given.
+    /// With --update, traffic is recorded into the file instead, which is
+    /// written on `mock --clear` or `stop`. Can run before the first `open`.
+    ///
+    /// Examples:
+    ///   plwr mock '**/api/user' --body-file user.json --content-type application/json
+    ///   plwr mock '**/api/orders' --status 500 --body 'Internal error'
+    ///   plwr mock --list
+    ///   plwr mock --remove '**/api/user'
+    ///   plwr mock --har capture.har --update     # record once...
+    ///   plwr mock --har capture.har              # ...then replay offline
+    ///   plwr mock --har api.har --not-found fallback
+    ///   plwr mock --clear
+    Mock {
+        /// URL glob of the requests to answer
+        #[arg(conflicts_with = "har")]
+        pattern: Option<String>,
+        /// Response status
+        #[arg(long, default_value_t = 200)]
+        status: u16,
+        /// Response body
+        #[arg(long, conflicts_with = "body_file")]
+        body: Option<String>,
+        /// File to read the response body from
+        #[arg(long)]
+        body_file: Option<String>,
+        /// Response Content-Type
+        #[arg(long)]
+        content_type: Option<String>,
+        /// List the mocks as JSON
+        #[arg(long)]
+        list: bool,
+        /// Remove the mock for this URL glob
+        #[arg(long, value_name = "PATTERN")]
+        remove: Option<String>,
+        /// HAR file to serve responses from
+        #[arg(long)]
+        har: Option<String>,
+        /// Record the session into the HAR file instead of replaying it
+        #[arg(long, requires = "har")]
+        update: bool,
+        /// What to do with requests missing from the HAR
+        #[arg(long, value_parser = ["abort", "fallback"], default_value = "abort")]
+        not_found: String,
+        /// Remove all mocks (and write the HAR when recording with --update)
+        #[arg(long, conflicts_with = "har")]
+        clear: bool,
+    },
 
-fn find_subcommand_in_args() -> Option<String> {
+    /// Make an HTTP request with the browser's cookies and headers.
+    ///
+    /// The request is sent by the browser context, so it carries the
+    /// session's cookies and `plwr header` headers without exporting
+    /// anything to curl. Relative URLs are resolved against the current
+    /// page. Prints the body (JSON bodies as JSON) and fails on 4xx/5xx;
+    /// with --include, prints {status, headers, body} and never fails on
+    /// the status.
+    ///
+    /// Examples:
+    ///   plwr fetch GET /api/me
+    ///   plwr fetch POST /api/orders --header 'Content-Type: application/json' --body @order.json
+    ///   plwr fetch DELETE /api/orders/42 --include
+    Fetch {
+        /// HTTP method
+        method: String,
+        /// URL, absolute or relative to the current page
+        url: String,
+        /// Request header as 'Name: value' (repeatable)
+        #[arg(long = "header", value_name = "NAME: VALUE", value_parser = parse_header)]
+        headers: Vec<(String, String)>,
+        /// Request body, or @FILE to read it from a file
+        #[arg(long)]
+        body: Option<String>,
+        /// Print status and headers along with the body
+        #[arg(long, short = 'i')]
+        include: bool,
+    },
+
+    /// Turn the browser's HTTP cache off or on, or empty it (Chromium only).
+    ///
+    /// `cache disable` lasts for the rest of the session and can run before
+    /// the first `open`. Note that while any `block`, `mock` or
+    /// `header --match` rule is active, Playwright keeps the cache off
+    /// regardless.
+    ///
+    /// Examples:
+    ///   plwr cache disable
+    ///   plwr cache clear
+    ///   plwr cache enable
+    Cache {
+        #[arg(value_parser = ["disable", "enable", "clear"])]
+        action: String,
+    },
+
+    /// Clear what a site has stored in the browser, for a fresh visitor
+    /// without restarting the session (Chromium only).
+    ///
+    /// Clears everything for the current page's origin unless --origin or
+    /// the flags say otherwise. --storage covers local storage, IndexedDB
+    /// and service workers, --cache the site's Cache Storage. Use `cache
+    /// clear` for the browser's HTTP cache.
+    ///
+    /// Examples:
+    ///   plwr clear-site-data
+    ///   plwr clear-site-data --cookies --storage
+    ///   plwr clear-site-data --origin https://example.com
+    ClearSiteData {
+        /// URL or origin to clear (default: the current page's)
+        #[arg(long)]
+        origin: Option<String>,
+        /// Clear cookies
+        #[arg(long)]
+        cookies: bool,
+        /// Clear local storage, IndexedDB and service workers
+        #[arg(long)]
+        storage: bool,
+        /// Clear Cache Storage
+        #[arg(long)]
+        cache: bool,
+    },
+
+    /// List or unregister the page's service workers, or let requests
+    /// bypass them (Chromium only).
+    ///
+    /// `sw list` prints each registration's scope, script URL and state.
+    /// `sw unregister` removes every registration for the current origin.
+    /// `sw bypass on` sends requests straight to the network instead of
+    /// through service workers, for the rest of the session.
+    ///
+    /// Examples:
+    ///   plwr sw list
+    ///   plwr sw unregister
+    ///   plwr sw bypass on
+    Sw {
+        #[arg(value_parser = ["list", "unregister", "bypass"])]
+        action: String,
+        /// on or off, for `sw bypass`
+        #[arg(value_parser = ["on", "off"])]
+        state: Option<String>,
+    },
+
+    /// List the page's Cache Storage caches (what service workers precache),
+    /// or dump the entries of one as JSON.
+    ///
+    /// `cachestorage list` prints each cache's name and entry count.
+    /// `cachestorage dump` prints each cached request's URL and method with
+    /// the response's status, Content-Type and body size.
+    ///
+    /// Examples:
+    ///   plwr cachestorage list
+    ///   plwr cachestorage dump precache-v2
+    ///   plwr cachestorage dump precache-v2 | jq -r '.[].url'
+    Cachestorage {
+        #[arg(value_parser = ["list", "dump"])]
+        action: String,
+        /// Cache name, for `cachestorage dump`
+        name: Option<String>,
+    },
+
+    /// List the page's IndexedDB databases, or dump an object store's
+    /// records as JSON.
+    ///
+    /// `idb list` prints each database with its version and object stores
+    /// (key path, indexes and record count). `idb dump` prints the records
+    /// as {key, value}, in key order. Maps, Sets, Blobs and binary data are
+    /// converted to plain JSON.
+    ///
+    /// Examples:
+    ///   plwr idb list
+    ///   plwr idb dump app-db todos --limit 10
+    ///   plwr idb dump app-db todos | jq '.[].value.title'
+    Idb {
+        #[arg(value_parser = ["list", "dump"])]
+        action: String,
+        /// Database name, for `idb dump`
+        db: Option<String>,
+        /// Object store name, for `idb dump`
+        store: Option<String>,
+        /// Dump at most this many records
+        #[arg(long)]
+        limit: Option<usize>,
+    },
+
+    /// Evaluate arbitrary JavaScript in page context, print the result
+    Eval { js: String },
+
+    /// Take a screenshot (optionally of a specific element)
+    ///
+    /// --mask covers matching elements with a solid box so dynamic content
+    /// like timestamps or avatars doesn't make captures differ.
+    ///
+    /// The format follows the --path extension (.png, .jpg/.jpeg, .webp)
+    /// unless --format is given.
+    ///
+    /// Examples:
+    ///   plwr screenshot --full-page --path page.png
+    ///   plwr screenshot --path page.jpg --quality 80
+    ///   plwr screenshot --clip 0,0,800,600 --path top.webp
+    ///   plwr screenshot --selector '.chart' --path chart.png --padding 16
+    ///   plwr screenshot --mask '.timestamp' --mask '.avatar'
+    ///   plwr screenshot --annotate '#submit' --annotate '.error' --path bug.png
+    ///   plwr screenshot --compare baseline.png --max-diff-ratio 0.01
+    Screenshot {
+        #[arg(long)]
+        selector: Option<String>,
+        #[arg(long, default_value = "screenshot.png")]
+        path: String,
+        /// Cover elements matching this selector (repeatable)
+        #[arg(long)]
+        mask: Vec<String>,
+        /// Draw a labelled box around elements matching this selector (repeatable)
+        #[arg(long)]
+        annotate: Vec<String>,
+        /// Extra pixels to include around the --selector element
+        #[arg(long, requires = "selector")]
+        padding: Option<u32>,
+        /// Capture the whole scrollable page, not just the viewport
+        #[arg(long, conflicts_with = "selector")]
+        full_page: bool,
+        /// Image format (default: from the --path extension, else png)
+        #[arg(long, value_parser = ["png", "jpeg", "webp"])]
+        format: Option<String>,
+        /// JPEG/WebP quality, 0-100
+        #[arg(long, value_parser = clap::value_parser!(u8).range(0..=100))]
+        quality: Option<u8>,
+        /// Capture only this region of the viewport
+        #[arg(long, value_name = "X,Y,W,H", value_parser = parse_clip, conflicts_with = "selector")]
+        clip: Option<(f64, f64, f64, f64)>,
+        /// Compare against this baseline image and fail if they differ
+        #[arg(long, value_name = "BASELINE")]
+        compare: Option<String>,
+        /// Per-pixel color difference (0-1) to ignore when comparing
+        #[arg(long, default_value_t = 0.1, requires = "compare")]
+        threshold: f64,
+        /// Number of differing pixels to tolerate
+        #[arg(long, default_value_t = 0, requires = "compare")]
+        max_diff_pixels: u64,
+        /// Fraction of differing pixels (0-1) to tolerate
+        #[arg(long, requires = "compare")]
+        max_diff_ratio: Option<f64>,
+        /// Where to write the diff image (default: <baseline>-diff.png)
+        #[arg(long, requires = "compare")]
+        diff_path: Option<String>,
+    },
+
+    /// Dump the DOM tree as JSON (optionally rooted at a selector)
+    ///
+    /// --format outline prints one indented line per element instead
+    /// (tag#id.class [attr="value"] text), which is much easier to scan
+    /// on large pages.
+    ///
+    /// --snapshot saves the tree to a file and --diff compares the current
+    /// tree against one, listing added, removed and changed elements.
+    ///
+    /// Examples:
+    ///   plwr tree '#main'
+    ///   plwr tree --format outline --depth 3 --only id,class,href
+    ///   plwr tree 'nav' --max-text 40
+    ///   plwr tree --snapshot before.json && plwr click '#add' && plwr tree --diff before.json
+    Tree {
+        /// CSS selector to use as root
+        selector: Option<String>,
+        /// Output format
+        #[arg(long, value_parser = ["json", "outline"], default_value = "json")]
+        format: String,
+        /// Only descend this many levels below the root
+        #[arg(long)]
+        depth: Option<usize>,
+        /// Truncate each element's text to this many characters
+        #[arg(long)]
+        max_text: Option<usize>,
+        /// Only keep these attributes (id and class count as attributes)
+        #[arg(long, value_delimiter = ',')]
+        only: Option<Vec<String>>,
+        /// Save the tree as JSON to this file instead of printing it
+        #[arg(long, conflicts_with_all = ["diff", "format"])]
+        snapshot: Option<PathBuf>,
+        /// Print what changed since a snapshot saved with --snapshot
+        #[arg(long, conflicts_with = "format")]
+        diff: Option<PathBuf>,
+    },
+
+    /// Save a named DOM snapshot in the session, or diff the page against one.
+    ///
+    /// Snapshots record the element tree with text, attributes and each
+    /// element's position and size. `diff` lists added, removed and changed
+    /// elements since the snapshot was saved.
+    ///
+    /// Examples:
+    ///   plwr snapshot save home
+    ///   plwr snapshot save cart '#cart'
+    ///   plwr snapshot diff cart | jq '.changed'
+    Snapshot {
+        /// save or diff
+        #[arg(value_parser = ["save", "diff"])]
+        action: String,
+        /// Snapshot name
+        name: String,
+        /// Root element for `save` (default: the `within` scope or the whole
+        /// page)
+        selector: Option<String>,
+    },
+
+    /// Print page metadata as JSON: title, description, canonical URL,
+    /// robots, Open Graph (og) and Twitter card (twitter) tags.
+    ///
+    /// Repeated tags (e.g. several og:image) become arrays.
+    ///
+    /// Examples:
+    ///   plwr meta
+    ///   plwr meta | jq -r '.og.image'
+    Meta,
+
+    /// Print the page's JSON-LD blocks (and optionally microdata) as a JSON array.
+    ///
+    /// Blocks that aren't valid JSON show up as {"parse_error", "text"}
+    /// objects so broken markup is easy to spot.
+    ///
+    /// Examples:
+    ///   plwr structured-data
+    ///   plwr structured-data --microdata | jq '.[] | ."@type"'
+    StructuredData {
+        /// Also include microdata items (itemscope/itemprop)
+        #[arg(long)]
+        microdata: bool,
+    },
+
+    /// Run an accessibility audit with axe-core and print the violations.
+    ///
+    /// The axe-core plwr ships with is injected into the page unless the
+    /// page already has it or --axe-url says where to load it from. Each
+    /// violation lists the rule, impact, help text and the selectors of the
+    /// offending elements. Exits 1 when there are violations, so it can gate
+    /// CI.
+    ///
+    /// Examples:
+    ///   plwr audit a11y
+    ///   plwr audit a11y --tags wcag2a,wcag2aa
+    ///   plwr audit a11y | jq -r '.[].nodes[].selector'
+    Audit {
+        /// What to audit
+        #[arg(value_parser = ["a11y"])]
+        kind: String,
+        /// Only run rules with these axe tags (e.g. wcag2a,wcag2aa,best-practice)
+        #[arg(long, value_delimiter = ',')]
+        tags: Vec<String>,
+        /// Load axe-core from this URL instead of using the bundled copy
+        #[arg(long, env = "PLWR_AXE_URL")]
+        axe_url: Option<String>,
+    },
+
+    /// Convert the rendered page (or an element) to Markdown.
+    ///
+    /// Headings, paragraphs, lists, links, images, tables, quotes and code
+    /// blocks are kept; hidden elements, scripts and form controls are left out.
+    ///
+    /// Examples:
+    ///   plwr markdown
+    ///   plwr markdown 'article' > article.md
+    Markdown {
+        /// Element to convert (default: the whole body)
+        selector: Option<String>,
+    },
+
+    /// List links as JSON: text, href, absolute_url, rel and target.
+    ///
+    /// Examples:
+    ///   plwr links
+    ///   plwr links 'nav' --same-origin
+    ///   plwr links --unique | jq -r '.[].absolute_url'
+    Links {
+        /// Only list links inside this element
+        selector: Option<String>,
+        /// Skip links to other origins
+        #[arg(long)]
+        same_origin: bool,
+        /// List each absolute URL once
+        #[arg(long)]
+        unique: bool,
+    },
+
+    /// Extract an HTML table as JSON or CSV.
+    ///
+    /// The header comes from <thead> (or the first row with --header-row).
+    /// With a header, JSON rows are objects keyed by column name; without
+    /// one they are arrays of cell text.
+    ///
+    /// Examples:
+    ///   plwr table 'table.results'
+    ///   plwr table '#prices' --format csv > prices.csv
+    ///   plwr table 'table' --header-row
+    Table {
+        /// Selector for the table (the first match is used)
+        selector: String,
+        /// Output format
+        #[arg(long, value_parser = ["json", "csv"], default_value = "json")]
+        format: String,
+        /// Use the first row as the header when the table has no <thead>
+        #[arg(long)]
+        header_row: bool,
+    },
+
+    /// Find elements containing some text and suggest selectors for them.
+    ///
+    /// Prints a JSON array with each match's tag, text, and the candidate
+    /// selectors (id, test id, role and name, text, short CSS path) that
+    /// match exactly one element on the page.
+    ///
+    /// Examples:
+    ///   plwr find 'Add to cart'
+    ///   plwr find 'Email' --limit 3
+    Find {
+        /// Text to search for (case-insensitive substring)
+        text: String,
+        /// Maximum number of matches to report
+        #[arg(long, default_value_t = 10)]
+        limit: usize,
+    },
+
+    /// Print a short, stable selector for the first element matching a selector.
+    ///
+    /// Prefers a unique id, then a unique data-testid (or data-test,
+    /// data-cy), then the shortest unique CSS path.
+    ///
+    /// Examples:
+    ///   plwr selector-for 'div.main > div:nth-child(3) > button'
+    ///   plwr selector-for 'text=Add to cart'
+    SelectorFor {
+        /// Selector for the element (the first match is used)
+        selector: String,
+    },
+
+    /// Click an element in the browser window and print a selector for it.
+    ///
+    /// Needs a --headed session. Hovered elements are outlined; the click
+    /// itself is swallowed so the page doesn't react. Press Escape to cancel.
+    ///
+    /// Examples:
+    ///   plwr start --headed && plwr open https://example.com
+    ///   plwr pick
+    ///   plwr click "$(plwr pick)"
+    Pick {
+        /// How long to wait for a click, in milliseconds
+        #[arg(long, default_value_t = 60000)]
+        wait: u64,
+    },
+
+    /// Record a login in the browser window, or replay one to log in again
+    /// once a saved session has expired.
+    ///
+    /// `login record` needs a --headed session with the login page open. It
+    /// records clicks, typing, Enter presses and dropdown choices until you
+    /// press Escape in the browser or the URL matches --until. `login replay`
+    /// opens the page again and repeats the steps. Logins are saved in plwr's
+    /// config directory ($PLWR_LOGINS to override), including what was
+    /// typed, passwords too.
+    ///
+    /// Examples:
+    ///   plwr start --headed && plwr open https://example.com/login
+    ///   plwr login record example --until '**/dashboard'
+    ///   plwr login replay example && plwr state save auth.json
+    Login {
+        #[arg(value_parser = ["record", "replay"])]
+        action: String,
+        /// Name of the login, without / or \ or a leading dot
+        name: String,
+        /// Stop recording once the page's URL matches this glob
+        #[arg(long, value_name = "GLOB")]
+        until: Option<String>,
+        /// How long to record for, in milliseconds
+        #[arg(long, default_value_t = 300000)]
+        wait: u64,
+    },
+
+    /// List the running sessions with their PID, uptime, URL and title.
+    ///
+    /// A session busy with a long command (like `pick`) can't answer and
+    /// shows up without details.
+    ///
+    /// Examples:
+    ///   plwr sessions
+    ///   plwr sessions --format json | jq -r '.[].session'
+    Sessions {
+        /// Output format
+        #[arg(long, value_parser = ["table", "json"], default_value = "table")]
+        format: String,
+    },
+
+    /// Run many commands over one connection to the session, reading them
+    /// from stdin, and print each one's result as a line of JSON:
+    /// {"ok":true,"value":...} or {"ok":false,"error":"..."}.
+    ///
+    /// Commands are one per line, written like on the command line without
+    /// `plwr` (quoted like in a shell, # for comments), or a JSON array of
+    /// command lines or argument arrays. A failing command doesn't stop the
+    /// batch, but the exit status is 1 if any failed.
+    ///
+    /// Examples:
+    ///   printf '%s\n' 'open https://example.com' 'text h1' | plwr batch
+    ///   echo '["open https://example.com", ["attr", "a", "href"]]' | plwr batch
+    Batch,
+
+    /// Run a script of plwr commands over one connection to the session,
+    /// printing ✓ or ✗ and the output of each step.
+    ///
+    /// A script has one command per line, written like on the command line
+    /// without `plwr`. Lines starting with # are comments. `$NAME` and
+    /// `${NAME}` are replaced by variables, from `set`, --var or the
+    /// environment. `set NAME VALUE` sets one, `set NAME = COMMAND` sets it
+    /// to what the command prints. `if exists SELECTOR` (or `if not exists`)
+    /// ... `else` ... `end` runs steps depending on the page.
+    ///
+    /// The script stops at the first failing step unless
+    /// --continue-on-error is given, and exits with 1 if any step failed.
+    ///
+    /// Examples:
+    ///   plwr run login.plwr
+    ///   plwr run login.plwr --var user=alice --continue-on-error
+    Run {
+        /// The script
+        file: PathBuf,
+        /// Keep going after a step fails
+        #[arg(long)]
+        continue_on_error: bool,
+        /// Set a variable for the script
+        #[arg(long = "var", value_name = "NAME=VALUE")]
+        vars: Vec<String>,
+    },
+
+    /// Run a command, typically a script, whose plwr commands for this
+    /// session all go through one open connection instead of connecting
+    /// each time.
+    ///
+    /// The command gets PLWR_SESSION, PLWR_SHELL_SOCKET and
+    /// PLWR_SHELL_SESSION in its environment, which plwr uses to find the
+    /// connection. Other clients can use the session meanwhile. Exits with
+    /// the command's exit status.
+    ///
+    /// Examples:
+    ///   plwr shell -- bash scrape.sh
+    ///   plwr -S shop shell -- python3 checkout.py
+    Shell {
+        /// The command to run, after `--`
+        #[arg(trailing_var_arg = true, allow_hyphen_values = true, required = true)]
+        command: Vec<String>,
+    },
+
+    /// Show the state of this session as JSON: daemon PID, browser version,
+    /// headed or headless, URL, open tabs, extra headers, cookie count,
+    /// viewport and JS heap usage.
+    ///
+    /// Examples:
+    ///   plwr status
+    ///   plwr status | jq .tabs
+    Status,
+
+    /// Check that the session's daemon and browser answer, within the
+    /// timeout (-T). Prints how long the answer took. Fails if the session
+    /// isn't running, is busy with another command or doesn't answer.
+    ///
+    /// Examples:
+    ///   plwr ping
+    ///   plwr -T 1000 ping || plwr start
+    Ping,
+
+    /// Show the session's daemon log: commands with how long they took,
+    /// failures, dialogs, downloads and errors. The log starts over each
+    /// time the session starts; the previous one is kept.
+    ///
+    /// Examples:
+    ///   plwr logs
+    ///   plwr logs --follow
+    ///   plwr logs --level debug
+    Logs {
+        /// Keep showing new lines as they're logged, until interrupted
+        #[arg(short, long)]
+        follow: bool,
+        /// Least severe level to show
+        #[arg(long, default_value = "info", value_parser = logs::LEVELS)]
+        level: String,
+    },
+
+    /// Internal: run the browser daemon (not for direct use)
+    #[command(hide = true)]
+    Daemon,
+}
+
+impl Cmd {
+    /// The selector the command acts on, which the layout flags narrow down.
+    fn selector_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Cmd::Wait { selector, .. }
+            | Cmd::WaitNot { selector, .. }
+            | Cmd::Click { selector, .. }
+            | Cmd::Tap { selector, .. }
+            | Cmd::Fill { selector, .. }
+            | Cmd::Clear { selector, .. }
+            | Cmd::Exists { selector, .. }
+            | Cmd::IsVisible { selector, .. }
+            | Cmd::IsEnabled { selector, .. }
+            | Cmd::IsChecked { selector, .. }
+            | Cmd::IsEditable { selector, .. }
+            | Cmd::Text { selector, .. }
+            | Cmd::Attr { selector, .. }
+            | Cmd::Count { selector, .. }
+            | Cmd::InputFiles { selector, .. }
+            | Cmd::Filechooser { selector, .. }
+            | Cmd::Download { selector, .. }
+            | Cmd::Select { selector, .. }
+            | Cmd::Hover { selector, .. }
+            | Cmd::Highlight { selector, .. }
+            | Cmd::Check { selector, .. }
+            | Cmd::Uncheck { selector, .. }
+            | Cmd::Dblclick { selector, .. }
+            | Cmd::Focus { selector, .. }
+            | Cmd::Blur { selector, .. }
+            | Cmd::InnerHtml { selector, .. }
+            | Cmd::InnerText { selector, .. }
+            | Cmd::InputValue { selector, .. }
+            | Cmd::ClipboardCopy { selector, .. }
+            | Cmd::Bbox { selector, .. }
+            | Cmd::ComputedStyle { selector, .. }
+            | Cmd::Table { selector, .. }
+            | Cmd::SelectorFor { selector, .. } => Some(selector),
+            Cmd::Alias { selector, .. }
+            | Cmd::Within { selector, .. }
+            | Cmd::Scroll { selector, .. }
+            | Cmd::Screenshot { selector, .. }
+            | Cmd::Tree { selector, .. }
+            | Cmd::Snapshot { selector, .. }
+            | Cmd::Markdown { selector, .. }
+            | Cmd::Links { selector, .. } => selector.as_mut(),
+            _ => None,
+        }
+    }
+}
+
+fn subcommand_index(args: &[String]) -> Option<usize> {
     let cmd = Cli::command();
     let names: HashSet<String> = cmd
         .get_subcommands()
@@ -455,20 +1936,1549 @@ fn find_subcommand_in_args() -> Option<String> {
             names
         })
         .collect();
-    std::env::args().skip(1).find(|a| names.contains(a))
+    args.iter()
+        .skip(1)
+        .position(|a| names.contains(a))
+        .map(|i| i + 1)
 }
 
-fn socket_path(session: &str) -> PathBuf {
-    let dir = dirs::cache_dir()
-        .unwrap_or_else(|| PathBuf::from("/tmp"))
-        .join("plwr");
-    std::fs::create_dir_all(&dir).ok();
-    dir.join(format!("{}.sock", session))
+fn find_subcommand_in_args() -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    subcommand_index(&args).map(|i| args[i].clone())
+}
+
+/// `--near` and the other layout flags with their anchors, in order
+type LayoutFlags = Vec<(String, String)>;
+
+// Rewrite the getBy*-style targeting flags (`--role <role> [--name <name>]`,
+// `--label`, `--placeholder`, `--alt` (or `--alt-text`), `--title`, each with
+// an optional `--exact`, and `--text-matches <regex>`) into a Playwright
+// selector, inserted as the subcommand's first positional argument, or as its
+// --selector. This lets every selector-taking command accept them without
+// each one having an optional selector.
+//
+// The layout flags (`--right-of <selector>` etc.) are taken out and returned,
+// to narrow the command's selector once the arguments are parsed.
+fn expand_locator_flags(args: Vec<String>) -> Result<(Vec<String>, LayoutFlags), String> {
+    // `select --label` already means "match options by label", and
+    // `click --alt` and the like hold the Alt key.
+    let is_select = subcommand_index(&args).is_some_and(|i| args[i] == "select");
+    let has_alt_key = subcommand_index(&args).is_some_and(|i| {
+        Cli::command()
+            .find_subcommand(&args[i])
+            .is_some_and(|sub| sub.get_arguments().any(|a| a.get_long() == Some("alt")))
+    });
+    let mut rest = Vec::new();
+    let mut target: Option<(String, String)> = None;
+    let mut name = None;
+    let mut exact = false;
+    let mut layout = Vec::new();
+    let mut iter = args.into_iter();
+    while let Some(arg) = iter.next() {
+        if arg == "--" {
+            rest.push(arg);
+            rest.extend(iter.by_ref());
+            break;
+        }
+        let (flag, inline) = match arg.split_once('=') {
+            Some((f, v)) if f.starts_with("--") => (f.to_string(), Some(v.to_string())),
+            _ => (arg.clone(), None),
+        };
+        match flag.as_str() {
+            "--label" if is_select => rest.push(arg),
+            "--alt" if has_alt_key => rest.push(arg),
+            "--role" | "--label" | "--placeholder" | "--alt" | "--alt-text" | "--title"
+            | "--name" | "--text-matches" => {
+                let value = inline
+                    .or_else(|| iter.next())
+                    .ok_or_else(|| format!("{} requires a value", flag))?;
+                if flag == "--name" {
+                    name = Some(value);
+                } else if let Some((prev, _)) = &target {
+                    return Err(format!("{} and {} can't be used together", prev, flag));
+                } else {
+                    target = Some((flag, value));
+                }
+            }
+            "--exact" if inline.is_none() => exact = true,
+            "--near" | "--above" | "--below" | "--left-of" | "--right-of" => {
+                let anchor = inline
+                    .or_else(|| iter.next())
+                    .ok_or_else(|| format!("{} requires a selector", flag))?;
+                layout.push((flag, anchor));
+            }
+            _ => rest.push(arg),
+        }
+    }
+
+    let Some((flag, value)) = target else {
+        if name.is_some() || exact {
+            return Err(
+                "--name and --exact need --role, --label, --placeholder, --alt or --title"
+                    .to_string(),
+            );
+        }
+        return Ok((rest, layout));
+    };
+    if name.is_some() && flag != "--role" {
+        return Err("--name can only be used with --role".to_string());
+    }
+    if exact && flag == "--text-matches" {
+        return Err("--exact can't be used with --text-matches".to_string());
+    }
+    let quote = |text: &str| {
+        let suffix = if exact { "s" } else { "i" };
+        format!("{}{}", serde_json::Value::from(text), suffix)
+    };
+    let selector = match (flag.as_str(), name) {
+        ("--role", Some(name)) => format!("internal:role={}[name={}]", value, quote(&name)),
+        ("--role", None) => format!("internal:role={}", value),
+        ("--label", _) => format!("internal:label={}", quote(&value)),
+        ("--placeholder", _) => format!("internal:attr=[placeholder={}]", quote(&value)),
+        ("--alt" | "--alt-text", _) => format!("internal:attr=[alt={}]", quote(&value)),
+        ("--text-matches", _) => format!("internal:text={}", regex_literal(&value)),
+        _ => format!("internal:attr=[title={}]", quote(&value)),
+    };
+    let idx =
+        subcommand_index(&rest).ok_or_else(|| format!("{} must be used with a command", flag))?;
+    // Where the command takes its selector: its first positional argument,
+    // or --selector for commands like screenshot.
+    let cmd = Cli::command();
+    let sub = cmd
+        .find_subcommand(&rest[idx])
+        .ok_or_else(|| format!("{} must be used with a command", flag))?;
+    let is_selector = |arg: &clap::Arg| arg.get_id() == "selector";
+    if sub.get_positionals().next().is_some_and(is_selector) {
+        rest.insert(idx + 1, selector);
+    } else if sub.get_opts().any(is_selector) {
+        rest.splice(idx + 1..idx + 1, ["--selector".to_string(), selector]);
+    } else {
+        return Err(format!("{} can't be used with {}", flag, sub.get_name()));
+    }
+    Ok((rest, layout))
+}
+
+// Accepts `pattern` or `/pattern/flags`. A literal `>>` would end the
+// selector, so it's escaped the way Playwright's getByText does.
+fn regex_literal(value: &str) -> String {
+    let is_literal = value.starts_with('/')
+        && value
+            .rfind('/')
+            .is_some_and(|end| end > 0 && value[end + 1..].chars().all(|c| "dgimsuvy".contains(c)));
+    let literal = if is_literal {
+        value.to_string()
+    } else {
+        format!("/{}/", value)
+    };
+    literal.replace(">>", "\\>\\>")
+}
+
+// `input --right-of 'text=Quantity'` becomes
+// `input:right-of(text=Quantity) >> nth=0`. Playwright orders layout matches
+// by distance to the anchor, so nth=0 is the closest one.
+fn apply_layout_flags(cmd: &mut Cmd, layout: LayoutFlags) -> Result<(), String> {
+    let Some((flag, _)) = layout.first() else {
+        return Ok(());
+    };
+    let selector = cmd
+        .selector_mut()
+        .ok_or_else(|| format!("{} needs a selector to narrow down", flag))?;
+    // Layout pseudo-classes belong to the CSS engine, so the last segment of
+    // the chain has to be CSS.
+    let last = selector.rsplit(">>").next().unwrap_or_default().trim();
+    let engine = last.split_once('=').map(|(e, _)| e).unwrap_or_default();
+    let is_engine = !engine.is_empty()
+        && engine
+            .chars()
+            .all(|c| c.is_ascii_alphabetic() || c == ':' || c == '-');
+    if is_engine && engine != "css" {
+        return Err(format!("{} only works with CSS selectors", flag));
+    }
+    for (flag, anchor) in layout {
+        selector.push_str(&format!(":{}({})", flag.trim_start_matches('-'), anchor));
+    }
+    selector.push_str(" >> nth=0");
+    Ok(())
+}
+
+fn parse_xy(s: &str) -> Result<(f64, f64), String> {
+    let (x, y) = s
+        .split_once(',')
+        .ok_or_else(|| format!("expected X,Y but got '{}'", s))?;
+    let x = x
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid X in '{}'", s))?;
+    let y = y
+        .trim()
+        .parse()
+        .map_err(|_| format!("invalid Y in '{}'", s))?;
+    Ok((x, y))
+}
+
+fn parse_clip(s: &str) -> Result<(f64, f64, f64, f64), String> {
+    let parts: Vec<f64> = s
+        .split(',')
+        .map(|p| p.trim().parse())
+        .collect::<Result<_, _>>()
+        .map_err(|_| format!("expected X,Y,W,H but got '{}'", s))?;
+    match parts[..] {
+        [x, y, w, h] => Ok((x, y, w, h)),
+        _ => Err(format!("expected X,Y,W,H but got '{}'", s)),
+    }
+}
+
+fn parse_status_filter(s: &str) -> Result<String, String> {
+    let mut chars = s.chars();
+    let valid = s.len() == 3
+        && chars.next().is_some_and(|c| ('1'..='5').contains(&c))
+        && chars.all(|c| c.is_ascii_digit() || c == 'x');
+    if valid {
+        Ok(s.to_string())
+    } else {
+        Err(format!("expected a status like 200 or 2xx but got '{}'", s))
+    }
+}
+
+fn parse_credentials(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((user, password)) => Ok((user.to_string(), password.to_string())),
+        None => Err("expected user:password".to_string()),
+    }
+}
+
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.split_once(':') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.trim().to_string()))
+        }
+        _ => Err(format!("expected 'Name: value', got '{}'", s)),
+    }
+}
+
+fn resolve_files(paths: Vec<String>) -> Result<Vec<String>, String> {
+    let mut resolved = Vec::new();
+    for path in paths {
+        match std::fs::canonicalize(resolve_client_path(&path)) {
+            Ok(p) if p.is_file() => resolved.push(p.to_string_lossy().to_string()),
+            Ok(_) => return Err(format!("Not a file: {}", path)),
+            Err(_) => return Err(format!("File not found: {}", path)),
+        }
+    }
+    Ok(resolved)
+}
+
+// serde_json::Map sorts its keys, but fill-form has to fill fields in file
+// order (e.g. a country select that populates the state select). Input
+// starting with `{` is JSON, anything else YAML.
+fn parse_form_fields(text: &str) -> Result<Vec<(String, serde_json::Value)>, String> {
+    struct Fields(Vec<(String, serde_json::Value)>);
+
+    impl<'de> serde::Deserialize<'de> for Fields {
+        fn deserialize<D: serde::Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+            struct FieldsVisitor;
+            impl<'de> serde::de::Visitor<'de> for FieldsVisitor {
+                type Value = Fields;
+                fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                    f.write_str("an object mapping selectors to values")
+                }
+                fn visit_map<A: serde::de::MapAccess<'de>>(
+                    self,
+                    mut map: A,
+                ) -> Result<Fields, A::Error> {
+                    let mut fields = Vec::new();
+                    while let Some(entry) = map.next_entry()? {
+                        fields.push(entry);
+                    }
+                    Ok(Fields(fields))
+                }
+            }
+            d.deserialize_map(FieldsVisitor)
+        }
+    }
+
+    let parsed = if text.trim_start().starts_with('{') {
+        serde_json::from_str(text).map_err(|e| e.to_string())
+    } else {
+        serde_norway::from_str(text).map_err(|e| e.to_string())
+    };
+    let Fields(fields) = parsed.map_err(|e| format!("Invalid form data: {}", e))?;
+    for (selector, value) in &fields {
+        let ok = match value {
+            serde_json::Value::String(_)
+            | serde_json::Value::Number(_)
+            | serde_json::Value::Bool(_) => true,
+            serde_json::Value::Array(items) => items.iter().all(|v| v.is_string() || v.is_number()),
+            _ => false,
+        };
+        if !ok {
+            return Err(format!(
+                "Unsupported value for '{}'. Use a string, number, boolean, or array of strings.",
+                selector
+            ));
+        }
+    }
+    Ok(fields)
+}
+
+fn socket_path(session: &str) -> std::io::Result<PathBuf> {
+    Ok(socket::private_dir()?.join(format!("{}.sock", session)))
+}
+
+/// A path from the command line, made absolute against our working
+/// directory since the daemon's is wherever `plwr start` was run.
+fn resolve_client_path(path: impl AsRef<Path>) -> PathBuf {
+    match std::env::current_dir() {
+        Ok(cwd) => cwd.join(path),
+        Err(_) => path.as_ref().to_path_buf(),
+    }
+}
+
+/// Every session with a socket, by name. The daemon behind a socket may
+/// have died without removing it.
+fn session_sockets() -> Vec<(String, PathBuf)> {
+    let Ok(entries) = std::fs::read_dir(socket::dir()) else {
+        return Vec::new();
+    };
+    let mut sockets: Vec<(String, PathBuf)> = entries
+        .filter_map(|entry| {
+            let path = entry.ok()?.path();
+            if path.extension()? != "sock" {
+                return None;
+            }
+            Some((path.file_stem()?.to_str()?.to_string(), path))
+        })
+        .collect();
+    sockets.sort();
+    sockets
+}
+
+/// Seconds as e.g. "42s", "5m12s" or "3h05m".
+fn format_uptime(secs: u64) -> String {
+    match secs {
+        s if s < 60 => format!("{}s", s),
+        s if s < 3600 => format!("{}m{:02}s", s / 60, s % 60),
+        s => format!("{}h{:02}m", s / 3600, s % 3600 / 60),
+    }
+}
+
+/// Where `start --persist` keeps a session's state between runs. The state
+/// has the session's cookies, so there's no falling back to a shared
+/// directory like /tmp when there's no data directory.
+fn persist_path(session: &str) -> Option<PathBuf> {
+    Some(
+        dirs::data_dir()?
+            .join("plwr")
+            .join("sessions")
+            .join(format!("{}.json", session)),
+    )
+}
+
+/// The daemon command for a CLI subcommand, or a usage error. Subcommands
+/// that don't map to a single daemon command (`start`, `stop`, ...) are
+/// handled before this.
+fn build_command(cmd: Cmd, timeout: u64) -> Result<Command, String> {
+    Ok(match cmd {
+        Cmd::Daemon
+        | Cmd::Stop { .. }
+        | Cmd::Start { .. }
+        | Cmd::Sessions { .. }
+        | Cmd::Logs { .. }
+        | Cmd::Batch
+        | Cmd::Run { .. }
+        | Cmd::Shell { .. } => unreachable!(),
+        Cmd::Open {
+            url,
+            show_redirects,
+        } => Command::Open {
+            url,
+            timeout,
+            show_redirects,
+        },
+        Cmd::Reload => Command::Reload,
+        Cmd::Url => Command::Url,
+        Cmd::Status => Command::Status,
+        Cmd::Ping => Command::Ping,
+        Cmd::Wait { selector } => Command::Wait { selector, timeout },
+        Cmd::WaitNot { selector } => Command::WaitNot { selector, timeout },
+        Cmd::WaitAny { selectors } => Command::WaitAny { selectors, timeout },
+        Cmd::WaitAll { selectors } => Command::WaitAll { selectors, timeout },
+        Cmd::WaitResponse {
+            pattern,
+            status,
+            print_body,
+            then,
+        } => Command::WaitResponse {
+            pattern,
+            status,
+            print_body,
+            selector: then,
+            timeout,
+        },
+        Cmd::WaitRequest {
+            pattern,
+            print,
+            then,
+        } => Command::WaitRequest {
+            pattern,
+            print,
+            selector: then,
+            timeout,
+        },
+        Cmd::Click {
+            selector,
+            right,
+            middle,
+            alt,
+            control,
+            meta,
+            shift,
+            button,
+            modifiers: extra_modifiers,
+            position,
+            count,
+            force,
+            trial,
+        } => {
+            let mut modifiers = Vec::new();
+            if alt {
+                modifiers.push("Alt".to_string());
+            }
+            if control {
+                modifiers.push("Control".to_string());
+            }
+            if meta {
+                modifiers.push("Meta".to_string());
+            }
+            if shift {
+                modifiers.push("Shift".to_string());
+            }
+            for m in extra_modifiers {
+                if !modifiers.contains(&m) {
+                    modifiers.push(m);
+                }
+            }
+            let button = if right {
+                Some("right".to_string())
+            } else if middle {
+                Some("middle".to_string())
+            } else {
+                button
+            };
+            Command::Click {
+                selector,
+                timeout,
+                modifiers,
+                button,
+                position,
+                click_count: count,
+                force,
+                trial,
+            }
+        }
+        Cmd::Tap { selector } => Command::Tap { selector, timeout },
+        Cmd::Fill { selector, text } => Command::Fill {
+            selector,
+            text,
+            timeout,
+        },
+        Cmd::FillForm { file } => {
+            let text = if file == "-" {
+                std::io::read_to_string(std::io::stdin())
+            } else {
+                std::fs::read_to_string(&file)
+            };
+            let text = match text {
+                Ok(text) => text,
+                Err(e) => {
+                    return Err(format!("Cannot read {}: {}", file, e));
+                }
+            };
+            match parse_form_fields(&text) {
+                Ok(fields) => Command::FillForm { fields, timeout },
+                Err(e) => {
+                    return Err(e.to_string());
+                }
+            }
+        }
+        Cmd::Clear { selector } => Command::Clear { selector, timeout },
+        Cmd::Press { keys, repeat } => Command::Press {
+            keys: std::iter::repeat_n(keys, repeat).flatten().collect(),
+        },
+        Cmd::Key { action, key } => match action.as_str() {
+            "down" => Command::KeyDown { key },
+            "up" => Command::KeyUp { key },
+            other => {
+                return Err(format!(
+                    "Unknown key action '{}'. Use 'down' or 'up'.",
+                    other
+                ));
+            }
+        },
+        Cmd::Type { text, delay } => Command::Type { text, delay },
+        Cmd::InsertText { text } => Command::InsertText { text },
+        Cmd::Exists { selector } => Command::Exists { selector },
+        Cmd::Cookie { list: true, .. } => Command::CookieList,
+        Cmd::Cookie { clear: true, .. } => Command::CookieClear,
+        Cmd::Cookie {
+            name: Some(name),
+            value: Some(value),
+            url,
+            domain,
+            path,
+            expires,
+            http_only,
+            secure,
+            same_site,
+            ..
+        } => {
+            let same_site = same_site.map(|s| match s.to_ascii_lowercase().as_str() {
+                "strict" => "Strict".to_string(),
+                "lax" => "Lax".to_string(),
+                _ => "None".to_string(),
+            });
+            if same_site.as_deref() == Some("None") && !secure {
+                return Err(
+                    "--same-site none needs --secure, or browsers reject the cookie".to_string(),
+                );
+            }
+            Command::Cookie {
+                name,
+                value,
+                url: url.unwrap_or_default(),
+                domain,
+                path,
+                expires,
+                http_only,
+                secure,
+                same_site,
+            }
+        }
+        Cmd::Cookie {
+            name: Some(name),
+            value: None,
+            ..
+        } => {
+            return Err(format!(
+                "Usage: plwr cookie <name> <value> [--url <url> | --domain <domain>] [attributes], plwr cookie --list, or plwr cookie --clear\nMissing value for cookie '{}'",
+                name
+            ));
+        }
+        Cmd::Cookie { .. } => {
+            return Err("Usage: plwr cookie <name> <value> [--url <url> | --domain <domain>] [attributes], plwr cookie --list, or plwr cookie --clear".to_string());
+        }
+        Cmd::Alias { list: true, .. } => Command::AliasList,
+        Cmd::Alias { clear: true, .. } => Command::AliasClear,
+        Cmd::Alias {
+            name: Some(name),
+            selector: Some(selector),
+            ..
+        } => Command::Alias {
+            name: name.trim_start_matches('@').to_string(),
+            selector,
+        },
+        Cmd::Alias {
+            name: Some(name),
+            selector: None,
+            ..
+        } => {
+            return Err(format!(
+                "Usage: plwr alias <name> <selector>, plwr alias --list, or plwr alias --clear\nMissing selector for alias '{}'",
+                name
+            ));
+        }
+        Cmd::Alias { .. } => {
+            return Err(
+                "Usage: plwr alias <name> <selector>, plwr alias --list, or plwr alias --clear"
+                    .to_string(),
+            );
+        }
+        Cmd::Within {
+            selector: Some(selector),
+            ..
+        } => Command::Within { selector },
+        Cmd::Within { .. } => Command::WithinClear,
+        Cmd::Viewport {
+            width,
+            height,
+            preset,
+            scale,
+            mobile,
+            touch,
+        } => {
+            // Flags given along with a preset take precedence.
+            let (width, height, scale, mobile, touch) = match preset {
+                Some(name) => match viewport::preset(&name) {
+                    Ok(p) => (
+                        p.width,
+                        p.height,
+                        scale.or(Some(p.scale)),
+                        mobile.or(Some(p.mobile)),
+                        touch.or(Some(p.touch)),
+                    ),
+                    Err(e) => {
+                        return Err(e.to_string());
+                    }
+                },
+                None => (
+                    width.unwrap_or_default(),
+                    height.unwrap_or_default(),
+                    scale,
+                    mobile,
+                    touch,
+                ),
+            };
+            if scale.is_some_and(|s| s <= 0.0) {
+                return Err("--scale must be positive".to_string());
+            }
+            Command::Viewport {
+                width,
+                height,
+                scale,
+                mobile,
+                touch,
+                timeout,
+            }
+        }
+        Cmd::Header { clear: true, .. } => Command::HeaderClear,
+        Cmd::Header {
+            name: Some(name),
+            value: Some(value),
+            url_match,
+            ..
+        } => Command::Header {
+            name,
+            value,
+            url_match,
+        },
+        Cmd::Header {
+            name: Some(name),
+            value: None,
+            ..
+        } => {
+            return Err(format!(
+                "Usage: plwr header <name> <value> or plwr header --clear\nMissing value for header '{}'",
+                name
+            ));
+        }
+        Cmd::Header { name: None, .. } => {
+            return Err("Usage: plwr header <name> <value> or plwr header --clear".to_string());
+        }
+        Cmd::Auth {
+            credentials: Some((username, password)),
+            origin,
+            ..
+        } => Command::Auth {
+            username,
+            password,
+            origin,
+        },
+        Cmd::Auth { .. } => Command::AuthClear,
+        Cmd::Lang {
+            languages: Some(languages),
+            ..
+        } => {
+            let valid = languages.split(',').all(|lang| {
+                let tag = lang.split(';').next().unwrap_or("").trim();
+                tag == "*"
+                    || (!tag.is_empty()
+                        && tag.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'))
+            });
+            if !valid {
+                return Err(format!(
+                    "Invalid language list '{}'. Use e.g. 'sv-SE,en;q=0.8'",
+                    languages
+                ));
+            }
+            Command::Lang { languages }
+        }
+        Cmd::Lang { .. } => Command::LangClear,
+        Cmd::Geo {
+            latitude: Some(latitude),
+            longitude: Some(longitude),
+            accuracy,
+            ..
+        } => {
+            if !(-90.0..=90.0).contains(&latitude) || !(-180.0..=180.0).contains(&longitude) {
+                return Err("Latitude must be -90 to 90 and longitude -180 to 180".to_string());
+            }
+            if accuracy.is_some_and(|a| a < 0.0) {
+                return Err("--accuracy can't be negative".to_string());
+            }
+            Command::Geo {
+                latitude,
+                longitude,
+                accuracy,
+            }
+        }
+        Cmd::Geo { .. } => Command::GeoClear,
+        Cmd::Emulate { reset: true, .. } => Command::Emulate {
+            media: Some("no-override".into()),
+            color_scheme: Some("no-override".into()),
+            reduced_motion: Some("no-override".into()),
+            forced_colors: Some("no-override".into()),
+        },
+        Cmd::Emulate {
+            media: None,
+            color_scheme: None,
+            reduced_motion: None,
+            forced_colors: None,
+            ..
+        } => {
+            return Err("Usage: plwr emulate --media <type>, plwr emulate --color-scheme <scheme>, plwr emulate --reduced-motion <value>, plwr emulate --forced-colors <value>, or plwr emulate --reset".to_string());
+        }
+        Cmd::Emulate {
+            media,
+            color_scheme,
+            reduced_motion,
+            forced_colors,
+            ..
+        } => Command::Emulate {
+            media,
+            color_scheme,
+            reduced_motion,
+            forced_colors,
+        },
+        Cmd::Text {
+            selector,
+            all,
+            trim,
+        } => Command::Text {
+            selector,
+            timeout,
+            all,
+            trim,
+        },
+        Cmd::Attr {
+            selector,
+            name,
+            all,
+        } => Command::Attr {
+            selector,
+            name,
+            timeout,
+            all,
+        },
+        Cmd::Count {
+            selector,
+            visible,
+            hidden,
+            enabled,
+            disabled,
+            matching,
+        } => Command::Count {
+            selector,
+            visible: (visible || hidden).then_some(visible),
+            enabled: (enabled || disabled).then_some(enabled),
+            matching: matching.as_deref().map(regex_literal),
+        },
+        Cmd::IsVisible { selector } => Command::IsVisible { selector },
+        Cmd::IsEnabled { selector } => Command::IsEnabled { selector, timeout },
+        Cmd::IsChecked { selector } => Command::IsChecked { selector, timeout },
+        Cmd::IsEditable { selector } => Command::IsEditable { selector, timeout },
+        Cmd::InputFiles {
+            selector,
+            paths,
+            clear,
+        } => match resolve_files(if clear { Vec::new() } else { paths }) {
+            Ok(paths) => Command::InputFiles {
+                selector,
+                paths,
+                timeout,
+            },
+            Err(e) => {
+                return Err(e.to_string());
+            }
+        },
+        Cmd::Download { selector, path } => {
+            let path = resolve_client_path(path.unwrap_or_else(|| ".".to_string()));
+            Command::Download {
+                selector,
+                path: path.to_string_lossy().to_string(),
+                timeout,
+            }
+        }
+        Cmd::Filechooser { selector, paths } => match resolve_files(paths) {
+            Ok(paths) => Command::FileChooser {
+                selector,
+                paths,
+                timeout,
+            },
+            Err(e) => {
+                return Err(e.to_string());
+            }
+        },
+        Cmd::Select {
+            selector,
+            values,
+            label,
+            index,
+        } => Command::Select {
+            selector,
+            values,
+            by_label: label,
+            by_index: index,
+            timeout,
+        },
+        Cmd::Hover {
+            selector,
+            force,
+            trial,
+        } => Command::Hover {
+            selector,
+            timeout,
+            force,
+            trial,
+        },
+        Cmd::Highlight { selector, duration } => Command::Highlight { selector, duration },
+        Cmd::Check {
+            selector,
+            force,
+            trial,
+        } => Command::Check {
+            selector,
+            timeout,
+            force,
+            trial,
+        },
+        Cmd::Uncheck {
+            selector,
+            force,
+            trial,
+        } => Command::Uncheck {
+            selector,
+            timeout,
+            force,
+            trial,
+        },
+        Cmd::Dblclick {
+            selector,
+            right,
+            middle,
+            alt,
+            control,
+            meta,
+            shift,
+            force,
+            trial,
+        } => {
+            let mut modifiers = Vec::new();
+            if alt {
+                modifiers.push("Alt".to_string());
+            }
+            if control {
+                modifiers.push("Control".to_string());
+            }
+            if meta {
+                modifiers.push("Meta".to_string());
+            }
+            if shift {
+                modifiers.push("Shift".to_string());
+            }
+            let button = if right {
+                Some("right".to_string())
+            } else if middle {
+                Some("middle".to_string())
+            } else {
+                None
+            };
+            Command::Dblclick {
+                selector,
+                timeout,
+                modifiers,
+                button,
+                force,
+                trial,
+            }
+        }
+        Cmd::Focus { selector } => Command::Focus { selector, timeout },
+        Cmd::Blur { selector } => Command::Blur { selector, timeout },
+        Cmd::InnerHtml { selector } => Command::InnerHtml { selector, timeout },
+        Cmd::InnerText { selector, all } => Command::InnerText {
+            selector,
+            timeout,
+            all,
+        },
+        Cmd::InputValue { selector } => Command::InputValue { selector, timeout },
+        Cmd::Scroll {
+            selector: Some(selector),
+            by: None,
+            to: None,
+        } => Command::ScrollIntoView { selector, timeout },
+        Cmd::Scroll {
+            selector: None,
+            by: None,
+            to: None,
+        } => {
+            return Err("Usage: plwr scroll <selector>, plwr scroll [selector] --by X,Y, or plwr scroll [selector] --to <edge>".to_string());
+        }
+        Cmd::Scroll { selector, by, to } => Command::Scroll {
+            selector,
+            by,
+            to,
+            timeout,
+        },
+        Cmd::NextDialog { action, text } => match action.as_str() {
+            "accept" => Command::DialogAccept {
+                prompt_text: text,
+                persist: false,
+            },
+            "dismiss" => Command::DialogDismiss { persist: false },
+            other => {
+                return Err(format!(
+                    "Unknown dialog action '{}'. Use 'accept' or 'dismiss'.",
+                    other
+                ));
+            }
+        },
+        Cmd::Dialog {
+            action,
+            text,
+            persist,
+        } => {
+            if text.is_some() && action != "accept" {
+                return Err("--text can only be used with 'dialog accept'".to_string());
+            }
+            match action.as_str() {
+                "accept" => Command::DialogAccept {
+                    prompt_text: text,
+                    persist,
+                },
+                "dismiss" => Command::DialogDismiss { persist },
+                "last" if persist => {
+                    return Err(
+                        "--persist can only be used with 'dialog accept' or 'dialog dismiss'"
+                            .to_string(),
+                    );
+                }
+                "last" => Command::DialogLast,
+                other => {
+                    return Err(format!(
+                        "Unknown dialog action '{}'. Use 'accept', 'dismiss' or 'last'.",
+                        other
+                    ));
+                }
+            }
+        }
+        Cmd::Console { clear: true } => Command::ConsoleClear,
+        Cmd::Console { clear: false } => Command::Console,
+        Cmd::Network { clear: true, .. } => Command::NetworkClear,
+        Cmd::Network {
+            clear: false,
+            r#type,
+            url,
+            include_ws_messages,
+        } => Command::Network {
+            types: r#type,
+            url_pattern: url,
+            include_ws_messages,
+        },
+        Cmd::Net {
+            action,
+            pattern,
+            filter,
+            since,
+            fail,
+            dir,
+            stop,
+        } => {
+            match (action.as_str(), pattern) {
+                ("save", None) if stop => Command::NetSaveStop,
+                ("body" | "timing" | "save", None) => {
+                    return Err(format!("Usage: plwr net {} <URL glob>", action));
+                }
+                (_, Some(_)) if !matches!(action.as_str(), "body" | "timing" | "save") => {
+                    return Err("Only 'plwr net body', 'plwr net timing' and 'plwr net save' take a URL glob".to_string());
+                }
+                (_, _) if stop && action != "save" => {
+                    return Err("--stop only applies to 'plwr net save'".to_string());
+                }
+                (_, _)
+                    if !matches!(action.as_str(), "log" | "failures")
+                        && (filter.is_some() || since.is_some()) =>
+                {
+                    return Err(
+                        "--filter and --since only apply to 'plwr net log' and 'plwr net failures'"
+                            .to_string(),
+                    );
+                }
+                (_, _) if fail && action != "failures" => {
+                    return Err("--fail only applies to 'plwr net failures'".to_string());
+                }
+                ("body", Some(pattern)) => Command::NetBody { pattern },
+                ("timing", Some(pattern)) => Command::NetTiming { pattern },
+                ("save", Some(_)) if stop => {
+                    return Err(
+                        "Usage: plwr net save <URL glob> --dir DIR, or plwr net save --stop"
+                            .to_string(),
+                    );
+                }
+                ("save", Some(pattern)) => {
+                    let dir = resolve_client_path(dir);
+                    Command::NetSave {
+                        pattern,
+                        dir: dir.to_string_lossy().to_string(),
+                    }
+                }
+                ("failures", _) => Command::NetFailures { filter, since },
+                ("capture", _) => Command::NetCapture,
+                ("clear", _) => Command::NetClear,
+                _ => Command::NetLog { filter, since },
+            }
+        }
+        Cmd::HarStart { omit_content } => Command::HarStart { omit_content },
+        Cmd::State { action, path } if action == "load" => match resolve_files(vec![path]) {
+            Ok(files) => Command::StateLoad {
+                path: files[0].clone(),
+                timeout,
+            },
+            Err(e) => {
+                return Err(e.to_string());
+            }
+        },
+        Cmd::SnapshotState { action, name } => {
+            if action == "save" {
+                Command::StateSnapshotSave { name }
+            } else {
+                Command::StateSnapshotRestore { name, timeout }
+            }
+        }
+        Cmd::Login { name, .. } => match login::load(&name) {
+            Ok(steps) => Command::LoginReplay { steps, timeout },
+            Err(e) => {
+                return Err(e.to_string());
+            }
+        },
+        Cmd::ImportProfile {
+            from_chrome,
+            origin,
+        } => {
+            let profile = from_chrome.unwrap_or_else(|| "Default".to_string());
+            let domain = origin.map(|o| {
+                let host = o.split_once("://").map_or(o.as_str(), |(_, rest)| rest);
+                let host = host.split(['/', '?', '#', ':']).next().unwrap_or("");
+                host.to_ascii_lowercase()
+            });
+            let user_data_dir = daemon::chrome_user_data_dir("stable");
+            match chrome_cookies::read(&user_data_dir, &profile, domain.as_deref()) {
+                Ok((cookies, skipped)) => {
+                    if skipped > 0 {
+                        eprintln!(
+                            "Skipped {} cookie{} that couldn't be decrypted",
+                            skipped,
+                            if skipped == 1 { "" } else { "s" }
+                        );
+                    }
+                    Command::CookieImport { cookies }
+                }
+                Err(e) => {
+                    return Err(e.to_string());
+                }
+            }
+        }
+        Cmd::State { path, .. } => {
+            let path = resolve_client_path(path);
+            Command::StateSave {
+                path: path.to_string_lossy().to_string(),
+            }
+        }
+        Cmd::HarStop { path } => {
+            let path = resolve_client_path(path);
+            Command::HarStop {
+                path: path.to_string_lossy().to_string(),
+            }
+        }
+        Cmd::Block { list: true, .. } => Command::BlockList,
+        Cmd::Block { clear: true, .. } => Command::BlockClear,
+        Cmd::Block {
+            patterns,
+            r#type,
+            preset,
+            ..
+        } if patterns.is_empty() && r#type.is_empty() && preset.is_empty() => {
+            return Err("Usage: plwr block <url-glob>..., plwr block --type <types>, plwr block --preset <name|file>, plwr block --list, or plwr block --clear".to_string());
+        }
+        Cmd::Block {
+            patterns,
+            r#type,
+            preset,
+            ..
+        } => {
+            let mut presets = Vec::new();
+            for name in preset {
+                if blocklist::PRESETS.contains(&name.as_str()) {
+                    presets.push(name);
+                    continue;
+                }
+                match resolve_files(vec![name.clone()]) {
+                    Ok(files) => presets.extend(files),
+                    Err(_) => {
+                        return Err(format!(
+                            "Unknown preset '{}'. Use {} or a filter list file.",
+                            name,
+                            blocklist::PRESETS.join(", ")
+                        ));
+                    }
+                }
+            }
+            Command::Block {
+                patterns,
+                types: r#type,
+                presets,
+            }
+        }
+        Cmd::Mock { clear: true, .. } => Command::MockClear,
+        Cmd::Mock { list: true, .. } => Command::MockList,
+        Cmd::Mock {
+            remove: Some(pattern),
+            ..
+        } => Command::MockRemove { pattern },
+        Cmd::Mock {
+            pattern: Some(pattern),
+            status,
+            body,
+            body_file,
+            content_type,
+            ..
+        } => {
+            let body_file = match body_file.map(|f| resolve_files(vec![f])).transpose() {
+                Ok(files) => files.and_then(|f| f.into_iter().next()),
+                Err(e) => {
+                    return Err(e.to_string());
+                }
+            };
+            Command::Mock {
+                pattern,
+                status,
+                body,
+                body_file,
+                content_type,
+            }
+        }
+        Cmd::Mock { har: None, .. } => {
+            return Err("Usage: plwr mock <url-glob> [--status N] [--body TEXT | --body-file FILE], plwr mock --har FILE, plwr mock --list, or plwr mock --clear".to_string());
+        }
+        Cmd::Mock {
+            har,
+            update,
+            not_found,
+            ..
+        } => {
+            let har = PathBuf::from(har.unwrap_or_default());
+            if !update && !har.is_file() {
+                return Err(format!("File not found: {}", har.display()));
+            }
+            let path = resolve_client_path(har);
+            Command::MockHar {
+                path: path.to_string_lossy().to_string(),
+                update,
+                not_found_fallback: not_found == "fallback",
+            }
+        }
+        Cmd::Fetch {
+            method,
+            url,
+            headers,
+            body,
+            include,
+        } => {
+            let (body, body_file) = match body {
+                Some(b) if b.starts_with('@') => match resolve_files(vec![b[1..].to_string()]) {
+                    Ok(files) => (None, files.into_iter().next()),
+                    Err(e) => {
+                        return Err(e.to_string());
+                    }
+                },
+                body => (body, None),
+            };
+            Command::Fetch {
+                method: method.to_ascii_uppercase(),
+                url,
+                headers,
+                body,
+                body_file,
+                include,
+                timeout,
+            }
+        }
+        Cmd::Cache { action } => match action.as_str() {
+            "disable" => Command::CacheDisable,
+            "enable" => Command::CacheEnable,
+            _ => Command::CacheClear,
+        },
+        Cmd::ClearSiteData {
+            origin,
+            cookies,
+            storage,
+            cache,
+        } => Command::ClearSiteData {
+            origin,
+            cookies,
+            storage,
+            cache,
+        },
+        Cmd::Sw { action, state } => match (action.as_str(), state) {
+            ("bypass", Some(state)) => Command::SwBypass {
+                enabled: state == "on",
+            },
+            ("bypass", None) => {
+                return Err("Usage: plwr sw bypass on|off".to_string());
+            }
+            (_, Some(_)) => {
+                return Err("Only 'plwr sw bypass' takes on or off".to_string());
+            }
+            ("list", None) => Command::SwList,
+            _ => Command::SwUnregister,
+        },
+        Cmd::Cachestorage { action, name } => match (action.as_str(), name) {
+            ("dump", Some(name)) => Command::CacheStorageDump { name },
+            ("dump", None) => {
+                return Err("Usage: plwr cachestorage dump <cache-name>".to_string());
+            }
+            (_, None) => Command::CacheStorageList,
+            (_, Some(_)) => {
+                return Err("Only 'plwr cachestorage dump' takes a cache name".to_string());
+            }
+        },
+        Cmd::Idb {
+            action,
+            db,
+            store,
+            limit,
+        } => match (action.as_str(), db, store) {
+            ("dump", Some(db), Some(store)) => Command::IdbDump { db, store, limit },
+            ("dump", _, _) => {
+                return Err("Usage: plwr idb dump <db> <store> [--limit N]".to_string());
+            }
+            (_, None, None) => Command::IdbList,
+            _ => {
+                return Err("Only 'plwr idb dump' takes a database and store".to_string());
+            }
+        },
+        Cmd::ClipboardCopy { selector } => Command::ClipboardCopy { selector, timeout },
+        Cmd::ClipboardPaste => Command::ClipboardPaste,
+        Cmd::Clipboard { action, text } => match (action.as_str(), text) {
+            ("get", None) => Command::ClipboardGet,
+            ("set", Some(text)) => Command::ClipboardSet { text },
+            ("get", Some(_)) | ("set", None) => {
+                return Err("Usage: plwr clipboard get or plwr clipboard set <text>".to_string());
+            }
+            (other, _) => {
+                return Err(format!(
+                    "Unknown clipboard action '{}'. Use 'get' or 'set'.",
+                    other
+                ));
+            }
+        },
+        Cmd::Bbox { selector } => Command::Bbox { selector, timeout },
+        Cmd::ComputedStyle {
+            selector,
+            properties,
+        } => Command::ComputedStyle {
+            selector,
+            properties,
+            timeout,
+        },
+        Cmd::Eval { js } => Command::Eval { js },
+        Cmd::Screenshot {
+            selector,
+            path,
+            mask,
+            annotate,
+            padding,
+            full_page,
+            format,
+            quality,
+            clip,
+            compare,
+            threshold,
+            max_diff_pixels,
+            max_diff_ratio,
+            diff_path,
+        } => {
+            let format = format.unwrap_or_else(|| {
+                match Path::new(&path)
+                    .extension()
+                    .and_then(|e| e.to_str())
+                    .map(|e| e.to_ascii_lowercase())
+                    .as_deref()
+                {
+                    Some("jpg" | "jpeg") => "jpeg",
+                    Some("webp") => "webp",
+                    _ => "png",
+                }
+                .to_string()
+            });
+            if quality.is_some() && format == "png" {
+                return Err("--quality only applies to jpeg and webp screenshots".to_string());
+            }
+            Command::Screenshot {
+                selector,
+                path: resolve_client_path(path).to_string_lossy().to_string(),
+                timeout,
+                mask,
+                annotate,
+                padding,
+                full_page,
+                format: Some(format),
+                quality,
+                clip,
+                compare: compare.map(|baseline| {
+                    let baseline = resolve_client_path(baseline);
+                    let diff_path = match diff_path {
+                        Some(diff_path) => resolve_client_path(diff_path),
+                        None => {
+                            let stem = baseline.file_stem().unwrap_or_default().to_string_lossy();
+                            baseline.with_file_name(format!("{}-diff.png", stem))
+                        }
+                    };
+                    ScreenshotCompare {
+                        baseline: baseline.to_string_lossy().to_string(),
+                        diff_path: diff_path.to_string_lossy().to_string(),
+                        threshold,
+                        max_diff_pixels,
+                        max_diff_ratio,
+                    }
+                }),
+            }
+        }
+        Cmd::Find { text, limit } => Command::Find { text, limit },
+        Cmd::Pick { wait } => Command::Pick { timeout: wait },
+        Cmd::SelectorFor { selector } => Command::SelectorFor { selector, timeout },
+        Cmd::Tree {
+            selector,
+            format,
+            depth,
+            max_text,
+            only,
+            ..
+        } => Command::Tree {
+            selector,
+            timeout,
+            outline: format == "outline",
+            depth,
+            max_text,
+            only,
+        },
+        Cmd::Snapshot {
+            action,
+            name,
+            selector,
+        } => {
+            if action == "save" {
+                Command::SnapshotSave {
+                    name,
+                    selector,
+                    timeout,
+                }
+            } else if selector.is_some() {
+                return Err(
+                    "snapshot diff uses the selector the snapshot was saved with".to_string(),
+                );
+            } else {
+                Command::SnapshotDiff { name }
+            }
+        }
+        Cmd::Meta => Command::Meta,
+        Cmd::StructuredData { microdata } => Command::StructuredData { microdata },
+        Cmd::Audit { tags, axe_url, .. } => Command::AuditA11y {
+            tags,
+            axe_url,
+            timeout,
+        },
+        Cmd::Markdown { selector } => Command::Markdown { selector, timeout },
+        Cmd::Links {
+            selector,
+            same_origin,
+            unique,
+        } => Command::Links {
+            selector,
+            same_origin,
+            unique,
+            timeout,
+        },
+        Cmd::Table {
+            selector,
+            format,
+            header_row,
+        } => Command::Table {
+            selector,
+            csv: format == "csv",
+            header_row,
+            timeout,
+        },
+    })
+}
+
+/// The daemon command for one command of a batch or script, parsed like a
+/// command line. The batch's timeout applies unless the command sets its
+/// own.
+fn batch_command(args: Vec<String>, timeout: u64) -> Result<Command, String> {
+    let (args, layout) =
+        expand_locator_flags(std::iter::once("plwr".to_string()).chain(args).collect())?;
+    let matches = Cli::command().try_get_matches_from(args).map_err(|e| {
+        let rendered = e.render().to_string();
+        let first = rendered.lines().next().unwrap_or_default();
+        first.strip_prefix("error: ").unwrap_or(first).to_string()
+    })?;
+    let mut cli = Cli::from_arg_matches(&matches).map_err(|e| e.to_string())?;
+    apply_layout_flags(&mut cli.command, layout)?;
+    let timeout = match matches.value_source("timeout") {
+        Some(clap::parser::ValueSource::CommandLine) => cli.timeout,
+        _ => timeout,
+    };
+    let name = matches.subcommand_name().unwrap_or_default();
+    match cli.command {
+        Cmd::Stop { all: false } => Ok(Command::Stop),
+        Cmd::Daemon
+        | Cmd::Start { .. }
+        | Cmd::Stop { .. }
+        | Cmd::Sessions { .. }
+        | Cmd::Logs { .. }
+        | Cmd::Batch
+        | Cmd::Run { .. }
+        | Cmd::Shell { .. } => Err(format!("'{}' can't be used in a batch or script", name)),
+        Cmd::Tree { snapshot, diff, .. } if snapshot.is_some() || diff.is_some() => Err(
+            "'tree --snapshot' and 'tree --diff' can't be used in a batch or script".to_string(),
+        ),
+        Cmd::Login { action, .. } if action == "record" => {
+            Err("'login record' can't be used in a batch or script".to_string())
+        }
+        cmd => build_command(cmd, timeout),
+    }
+}
+
+/// Runs the steps of a `plwr run` script over one connection to the session.
+async fn run_script(
+    sock: &Path,
+    steps: &[script::Step],
+    mut vars: HashMap<String, String>,
+    timeout: u64,
+    continue_on_error: bool,
+) -> ExitCode {
+    let mut conn = match client::Connection::open(sock).await {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut ran = 0;
+    let mut failed = 0;
+    let mut next = 0;
+    while let Some(step) = steps.get(next) {
+        next += 1;
+        let lookup = |name: &str| vars.get(name).cloned().or_else(|| std::env::var(name).ok());
+        let line = match &step.kind {
+            script::StepKind::Jump(target) => {
+                next = *target;
+                continue;
+            }
+            script::StepKind::Command(line) | script::StepKind::Set { value: line, .. } => {
+                line.clone()
+            }
+            script::StepKind::IfExists { args, .. } => format!("exists {}", args),
+        };
+        let mut is_stop = false;
+        let result = match (&step.kind, batch::split_expanding(&line, &lookup)) {
+            (_, Err(e)) => Err(e),
+            (script::StepKind::Set { capture: false, .. }, Ok(words)) => {
+                Ok(serde_json::Value::String(words.join(" ")))
+            }
+            (_, Ok(args)) => match batch_command(args, timeout) {
+                Ok(command) => {
+                    is_stop = matches!(command, Command::Stop);
+                    match conn.send(command).await {
+                        Ok(resp) => {
+                            if let Some(warning) = &resp.warning {
+                                eprintln!("{}", warning);
+                            }
+                            if resp.ok {
+                                Ok(resp.value.unwrap_or_default())
+                            } else {
+                                Err(resp.error.unwrap_or_else(|| "Unknown error".into()))
+                            }
+                        }
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            return ExitCode::FAILURE;
+                        }
+                    }
+                }
+                Err(e) => Err(e),
+            },
+        };
+        ran += 1;
+
+        let result = match (&step.kind, result) {
+            (
+                script::StepKind::IfExists {
+                    negate, otherwise, ..
+                },
+                result,
+            ) => {
+                let holds = result
+                    .as_ref()
+                    .is_ok_and(|exists| exists.as_bool() != Some(*negate));
+                if !holds {
+                    next = *otherwise;
+                }
+                result.map(|_| serde_json::json!(holds.to_string()))
+            }
+            (script::StepKind::Set { name, .. }, Ok(value)) => {
+                let value = match value {
+                    serde_json::Value::String(s) => s,
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                };
+                vars.insert(name.clone(), value);
+                Ok(serde_json::Value::Null)
+            }
+            // Like `plwr exists` failing when there's nothing
+            (_, Ok(serde_json::Value::Bool(false))) => Err(String::new()),
+            (_, result) => result,
+        };
+        let (ok, output) = match result {
+            Ok(serde_json::Value::String(s)) => (true, s),
+            Ok(serde_json::Value::Null | serde_json::Value::Bool(_)) => (true, String::new()),
+            Ok(other) => (true, serde_json::to_string_pretty(&other).unwrap()),
+            Err(e) => (false, e),
+        };
+        if ok {
+            println!("✓ {}", step.text);
+        } else {
+            failed += 1;
+            println!("✗ {} (line {})", step.text, step.line);
+        }
+        for line in output.lines() {
+            println!("  {}", line);
+        }
+        if is_stop || (!ok && !continue_on_error) {
+            break;
+        }
+    }
+
+    match failed {
+        0 => println!("{} steps passed", ran),
+        n => println!("{} of {} steps failed", n, ran),
+    }
+    if failed > 0 {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
 }
 
 #[tokio::main]
 async fn main() -> ExitCode {
-    let cli = match Cli::try_parse() {
+    let (args, layout) = match expand_locator_flags(std::env::args().collect()) {
+        Ok(expanded) => expanded,
+        Err(e) => {
+            eprintln!("{}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut cli = match Cli::try_parse_from(args) {
         Ok(cli) => cli,
         Err(e) => {
             match e.kind() {
@@ -505,7 +3515,27 @@ async fn main() -> ExitCode {
             }
         }
     };
-    let sock = socket_path(&cli.session);
+    if let Err(e) = apply_layout_flags(&mut cli.command, layout) {
+        eprintln!("{}", e);
+        return ExitCode::FAILURE;
+    }
+    let mut sock = match socket_path(&cli.session) {
+        Ok(sock) => sock,
+        Err(e) => {
+            eprintln!("Cannot use the socket directory: {}", e);
+            return ExitCode::FAILURE;
+        }
+    };
+    // Inside `plwr shell`, commands for its session go through the shell's
+    // connection. `start` still looks at the session itself.
+    if let (Ok(relay), Ok(session)) = (
+        std::env::var("PLWR_SHELL_SOCKET"),
+        std::env::var("PLWR_SHELL_SESSION"),
+    ) {
+        if session == cli.session && !matches!(cli.command, Cmd::Start { .. } | Cmd::Daemon) {
+            sock = PathBuf::from(relay);
+        }
+    }
 
     match cli.command {
         Cmd::Daemon => {
@@ -515,8 +3545,8 @@ async fn main() -> ExitCode {
             match daemon::run(&sock, headed, ignore_cert_errors).await {
                 Ok(()) => ExitCode::SUCCESS,
                 Err(e) => {
-                    std::fs::remove_file(&sock).ok();
-                    eprintln!("{}", e);
+                    socket::remove(&sock);
+                    logs::error(e.to_string());
                     ExitCode::FAILURE
                 }
             }
@@ -525,8 +3555,15 @@ async fn main() -> ExitCode {
         Cmd::Start {
             headed,
             video,
-            ignore_cert_errors,
+            ignore_https_errors,
             cdp,
+            touch,
+            fake_media,
+            fake_video,
+            fake_audio,
+            profile,
+            persist,
+            idle_timeout,
         } => {
             let headed = headed || std::env::var("PLAYWRIGHT_HEADED").is_ok_and(|v| !v.is_empty());
             if cdp.is_some() && headed {
@@ -539,17 +3576,66 @@ async fn main() -> ExitCode {
                 eprintln!("--cdp and --video are mutually exclusive (video recording requires a launched browser)");
                 return ExitCode::FAILURE;
             }
-            let ignore_cert_errors = ignore_cert_errors
-                || std::env::var("PLWR_IGNORE_CERT_ERRORS").is_ok_and(|v| !v.is_empty());
-            match client::ensure_started(
-                &sock,
+            if cdp.is_some() && touch {
+                eprintln!("--cdp and --touch are mutually exclusive (touch emulation requires a launched browser)");
+                return ExitCode::FAILURE;
+            }
+            if cdp.is_some() && fake_media {
+                eprintln!("--cdp and --fake-media are mutually exclusive (fake devices require a launched browser)");
+                return ExitCode::FAILURE;
+            }
+            if cdp.is_some() && profile.is_some() {
+                eprintln!("--cdp and --profile are mutually exclusive (connect with --cdp <user-data-dir> to use a running Chrome's profile)");
+                return ExitCode::FAILURE;
+            }
+            if cdp.is_some() && persist {
+                eprintln!(
+                    "--cdp and --persist are mutually exclusive (your Chrome keeps its own state)"
+                );
+                return ExitCode::FAILURE;
+            }
+            if idle_timeout.is_some_and(|minutes| minutes <= 0.0 || !minutes.is_finite()) {
+                eprintln!("--idle-timeout must be a positive number of minutes");
+                return ExitCode::FAILURE;
+            }
+            // The browser runs in the daemon's directory, so the files need
+            // absolute paths.
+            let resolve = |path: Option<String>| {
+                path.map(|p| resolve_files(vec![p]).map(|files| files[0].clone()))
+                    .transpose()
+            };
+            let fake_media = match (fake_media, resolve(fake_video), resolve(fake_audio)) {
+                (false, _, _) => None,
+                (true, Ok(video), Ok(audio)) => Some(client::FakeMedia { video, audio }),
+                (true, Err(e), _) | (true, _, Err(e)) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let ignore_cert_errors = ignore_https_errors
+                || ["PLWR_IGNORE_HTTPS_ERRORS", "PLWR_IGNORE_CERT_ERRORS"]
+                    .iter()
+                    .any(|var| std::env::var(var).is_ok_and(|v| !v.is_empty()));
+            let profile = profile.map(|dir| resolve_client_path(dir).to_string_lossy().to_string());
+            let persist = match persist.then(|| persist_path(&cli.session)) {
+                Some(None) => {
+                    eprintln!("--persist needs a data directory to keep the session in, set XDG_DATA_HOME");
+                    return ExitCode::FAILURE;
+                }
+                path => path.flatten().map(|p| p.to_string_lossy().to_string()),
+            };
+            let options = client::StartOptions {
                 headed,
-                video.as_deref(),
+                video,
                 ignore_cert_errors,
-                cdp.as_deref(),
-            )
-            .await
-            {
+                cdp,
+                touch,
+                fake_media,
+                profile,
+                persist,
+                idle_timeout,
+            };
+            match client::ensure_started(&sock, &options).await {
                 Ok(()) => {
                     println!("Started session '{}'", cli.session);
                     ExitCode::SUCCESS
@@ -561,280 +3647,449 @@ async fn main() -> ExitCode {
             }
         }
 
-        Cmd::Stop => match client::send_if_running(&sock, Command::Stop).await {
-            Ok(Some(_)) => {
-                println!("Stopped session '{}'", cli.session);
-                ExitCode::SUCCESS
+        Cmd::Logs { follow, level } => {
+            let path = logs::path(&cli.session);
+            let Ok(file) = std::fs::File::open(&path) else {
+                eprintln!("No logs for session '{}'", cli.session);
+                return ExitCode::FAILURE;
+            };
+            match logs::show(file, &path, &level, follow).await {
+                Ok(()) => ExitCode::SUCCESS,
+                Err(e) => {
+                    eprintln!("Cannot read {}: {}", path.display(), e);
+                    ExitCode::FAILURE
+                }
             }
-            Ok(None) => {
-                println!("No session '{}' running", cli.session);
-                ExitCode::SUCCESS
+        }
+
+        Cmd::Sessions { format } => {
+            let mut sessions = Vec::new();
+            for (name, path) in session_sockets() {
+                let info = tokio::time::timeout(
+                    std::time::Duration::from_secs(2),
+                    client::send_if_running(&path, Command::SessionInfo),
+                )
+                .await;
+                let info = match info {
+                    Ok(Ok(Some(resp))) if resp.ok => resp.value.unwrap_or_default(),
+                    // Nothing listening: a daemon that died
+                    Ok(_) => continue,
+                    Err(_) => serde_json::Value::Null,
+                };
+                sessions.push(serde_json::json!({
+                    "session": name,
+                    "pid": info["pid"],
+                    "url": info["url"],
+                    "title": info["title"],
+                    "uptime": info["uptime"],
+                }));
             }
-            Err(e) => {
-                eprintln!("{}", e);
-                ExitCode::FAILURE
+            if format == "json" {
+                println!("{}", serde_json::to_string_pretty(&sessions).unwrap());
+                return ExitCode::SUCCESS;
             }
-        },
+            if sessions.is_empty() {
+                println!("No sessions running");
+                return ExitCode::SUCCESS;
+            }
+            let rows: Vec<[String; 5]> = sessions
+                .iter()
+                .map(|s| {
+                    let text = |v: &serde_json::Value| v.as_str().unwrap_or("-").to_string();
+                    [
+                        text(&s["session"]),
+                        s["pid"].as_u64().map_or("-".into(), |p| p.to_string()),
+                        s["uptime"].as_u64().map_or("-".into(), format_uptime),
+                        match &s["url"] {
+                            serde_json::Value::Null if s["pid"].is_null() => "(busy)".into(),
+                            url => text(url),
+                        },
+                        text(&s["title"]),
+                    ]
+                })
+                .collect();
+            let header = ["SESSION", "PID", "UPTIME", "URL", "TITLE"].map(String::from);
+            let widths: Vec<usize> = (0..4)
+                .map(|i| {
+                    std::iter::once(&header)
+                        .chain(&rows)
+                        .map(|r| r[i].chars().count())
+                        .max()
+                        .unwrap_or(0)
+                })
+                .collect();
+            for row in std::iter::once(&header).chain(&rows) {
+                let mut line = String::new();
+                for (i, width) in widths.iter().enumerate() {
+                    line.push_str(&format!("{:<w$}  ", row[i], w = width));
+                }
+                line.push_str(&row[4]);
+                println!("{}", line.trim_end());
+            }
+            ExitCode::SUCCESS
+        }
 
-        cmd => {
-            let command = match cmd {
-                Cmd::Daemon | Cmd::Stop | Cmd::Start { .. } => unreachable!(),
-                Cmd::Open { url } => Command::Open {
-                    url,
-                    timeout: cli.timeout,
-                },
-                Cmd::Reload => Command::Reload,
-                Cmd::Url => Command::Url,
-                Cmd::Wait { selector } => Command::Wait {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::WaitNot { selector } => Command::WaitNot {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::WaitAny { selectors } => Command::WaitAny {
-                    selectors,
-                    timeout: cli.timeout,
-                },
-                Cmd::WaitAll { selectors } => Command::WaitAll {
-                    selectors,
-                    timeout: cli.timeout,
-                },
-                Cmd::Click {
-                    selector,
-                    right,
-                    middle,
-                    alt,
-                    control,
-                    meta,
-                    shift,
-                } => {
-                    let mut modifiers = Vec::new();
-                    if alt {
-                        modifiers.push("Alt".to_string());
-                    }
-                    if control {
-                        modifiers.push("Control".to_string());
+        Cmd::Batch => {
+            use tokio::io::AsyncBufReadExt;
+
+            let mut conn = match client::Connection::open(&sock).await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut lines = tokio::io::BufReader::new(tokio::io::stdin()).lines();
+            let mut failed = false;
+            'lines: loop {
+                let line = match lines.next_line().await {
+                    Ok(Some(line)) => line,
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("{}", e);
+                        return ExitCode::FAILURE;
                     }
-                    if meta {
-                        modifiers.push("Meta".to_string());
+                };
+                // A JSON array is the whole batch, so it's read to the end
+                // before anything runs.
+                let commands = if line.trim_start().starts_with('[') {
+                    let mut text = line;
+                    while let Ok(Some(line)) = lines.next_line().await {
+                        text.push('\n');
+                        text.push_str(&line);
                     }
-                    if shift {
-                        modifiers.push("Shift".to_string());
+                    match batch::parse_json(&text) {
+                        Ok(commands) => commands.into_iter().map(Ok).collect(),
+                        Err(e) => {
+                            eprintln!("{}", e);
+                            return ExitCode::FAILURE;
+                        }
                     }
-                    let button = if right {
-                        Some("right".to_string())
-                    } else if middle {
-                        Some("middle".to_string())
-                    } else {
-                        None
+                } else {
+                    vec![batch::split(&line)]
+                };
+                for args in commands {
+                    let command = match args {
+                        Ok(args) if args.is_empty() => continue,
+                        Ok(args) => batch_command(args, cli.timeout),
+                        Err(e) => Err(e),
+                    };
+                    let is_stop = matches!(command, Ok(Command::Stop));
+                    let resp = match command {
+                        Ok(command) => match conn.send(command).await {
+                            Ok(resp) => resp,
+                            Err(e) => {
+                                eprintln!("{}", e);
+                                return ExitCode::FAILURE;
+                            }
+                        },
+                        Err(e) => Response::err(e),
                     };
-                    Command::Click {
-                        selector,
-                        timeout: cli.timeout,
-                        modifiers,
-                        button,
+                    failed |= !resp.ok;
+                    println!("{}", serde_json::to_string(&resp).unwrap());
+                    // The daemon is gone after a stop.
+                    if is_stop {
+                        break 'lines;
                     }
                 }
-                Cmd::Fill { selector, text } => Command::Fill {
-                    selector,
-                    text,
-                    timeout: cli.timeout,
-                },
-                Cmd::Press { key } => Command::Press { key },
-                Cmd::Type { text, delay } => Command::Type { text, delay },
-                Cmd::Exists { selector } => Command::Exists { selector },
-                Cmd::Cookie { list: true, .. } => Command::CookieList,
-                Cmd::Cookie { clear: true, .. } => Command::CookieClear,
-                Cmd::Cookie {
-                    name: Some(name),
-                    value: Some(value),
-                    url,
-                    ..
-                } => {
-                    let url = url.unwrap_or_default();
-                    Command::Cookie { name, value, url }
-                }
-                Cmd::Cookie {
-                    name: Some(name),
-                    value: None,
-                    ..
-                } => {
-                    eprintln!("Usage: plwr cookie <name> <value> [--url <url>], plwr cookie --list, or plwr cookie --clear");
-                    eprintln!("Missing value for cookie '{}'", name);
+            }
+            if failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+
+        Cmd::Run {
+            file,
+            continue_on_error,
+            vars,
+        } => {
+            let text = match std::fs::read_to_string(&file) {
+                Ok(text) => text,
+                Err(e) => {
+                    eprintln!("Cannot read {}: {}", file.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let steps = match script::parse(&text) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    eprintln!("{}: {}", file.display(), e);
                     return ExitCode::FAILURE;
                 }
-                Cmd::Cookie { .. } => {
-                    eprintln!("Usage: plwr cookie <name> <value> [--url <url>], plwr cookie --list, or plwr cookie --clear");
+            };
+            let mut variables = HashMap::new();
+            for var in vars {
+                let Some((name, value)) = var.split_once('=') else {
+                    eprintln!("--var takes NAME=VALUE, got '{}'", var);
+                    return ExitCode::FAILURE;
+                };
+                variables.insert(name.to_string(), value.to_string());
+            }
+            run_script(&sock, &steps, variables, cli.timeout, continue_on_error).await
+        }
+
+        Cmd::Shell { command } => {
+            let conn = match client::Connection::open(&sock).await {
+                Ok(conn) => conn,
+                Err(e) => {
+                    eprintln!("{}", e);
                     return ExitCode::FAILURE;
                 }
-                Cmd::Viewport { width, height } => Command::Viewport { width, height },
-                Cmd::Header { clear: true, .. } => Command::HeaderClear,
-                Cmd::Header {
-                    name: Some(name),
-                    value: Some(value),
-                    ..
-                } => Command::Header { name, value },
-                Cmd::Header {
-                    name: Some(name),
-                    value: None,
-                    ..
-                } => {
-                    eprintln!("Usage: plwr header <name> <value> or plwr header --clear");
-                    eprintln!("Missing value for header '{}'", name);
+            };
+            let dir = match tempfile::tempdir() {
+                Ok(dir) => dir,
+                Err(e) => {
+                    eprintln!("{}", e);
                     return ExitCode::FAILURE;
                 }
-                Cmd::Header { name: None, .. } => {
-                    eprintln!("Usage: plwr header <name> <value> or plwr header --clear");
+            };
+            let relay_path = dir.path().join("plwr.sock");
+            let listener = match tokio::net::UnixListener::bind(&relay_path) {
+                Ok(listener) if socket::restrict(&relay_path).is_ok() => listener,
+                Ok(_) => {
+                    eprintln!("Cannot restrict access to {}", relay_path.display());
                     return ExitCode::FAILURE;
                 }
-                Cmd::Text { selector } => Command::Text {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::Attr { selector, name } => Command::Attr {
-                    selector,
-                    name,
-                    timeout: cli.timeout,
-                },
-                Cmd::Count { selector } => Command::Count { selector },
-                Cmd::InputFiles { selector, paths } => Command::InputFiles {
-                    selector,
-                    paths,
-                    timeout: cli.timeout,
-                },
-                Cmd::Select {
-                    selector,
-                    values,
-                    label,
-                } => Command::Select {
-                    selector,
-                    values,
-                    by_label: label,
-                    timeout: cli.timeout,
-                },
-                Cmd::Hover { selector } => Command::Hover {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::Check { selector } => Command::Check {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::Uncheck { selector } => Command::Uncheck {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::Dblclick {
-                    selector,
-                    right,
-                    middle,
-                    alt,
-                    control,
-                    meta,
-                    shift,
-                } => {
-                    let mut modifiers = Vec::new();
-                    if alt {
-                        modifiers.push("Alt".to_string());
-                    }
-                    if control {
-                        modifiers.push("Control".to_string());
-                    }
-                    if meta {
-                        modifiers.push("Meta".to_string());
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let mut child = match tokio::process::Command::new(&command[0])
+                .args(&command[1..])
+                .env("PLWR_SHELL_SOCKET", &relay_path)
+                .env("PLWR_SHELL_SESSION", &cli.session)
+                .env("PLWR_SESSION", &cli.session)
+                .spawn()
+            {
+                Ok(child) => child,
+                Err(e) => {
+                    eprintln!("Cannot run {}: {}", command[0], e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let status = tokio::select! {
+                status = child.wait() => status,
+                Err(e) = client::relay(listener, &sock, Some(conn)) => {
+                    eprintln!("{}", e);
+                    child.wait().await
+                }
+            };
+            match status.map(|s| s.code()) {
+                Ok(Some(code)) => ExitCode::from(code as u8),
+                // Killed by a signal
+                Ok(None) => ExitCode::FAILURE,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+
+        Cmd::Stop { all: true } => {
+            let mut stopped = 0;
+            let mut failed = false;
+            for (name, path) in session_sockets() {
+                match client::send_if_running(&path, Command::Stop).await {
+                    Ok(Some(_)) => {
+                        println!("Stopped session '{}'", name);
+                        stopped += 1;
                     }
-                    if shift {
-                        modifiers.push("Shift".to_string());
+                    // Left behind by a daemon that died
+                    Ok(None) => {
+                        socket::remove(&path);
                     }
-                    let button = if right {
-                        Some("right".to_string())
-                    } else if middle {
-                        Some("middle".to_string())
-                    } else {
-                        None
-                    };
-                    Command::Dblclick {
-                        selector,
-                        timeout: cli.timeout,
-                        modifiers,
-                        button,
+                    Err(e) => {
+                        eprintln!("Cannot stop session '{}': {}", name, e);
+                        failed = true;
                     }
                 }
-                Cmd::Focus { selector } => Command::Focus {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::Blur { selector } => Command::Blur {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::InnerHtml { selector } => Command::InnerHtml {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::InputValue { selector } => Command::InputValue {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::Scroll { selector } => Command::ScrollIntoView {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::NextDialog { action, text } => match action.as_str() {
-                    "accept" => Command::DialogAccept { prompt_text: text },
-                    "dismiss" => Command::DialogDismiss,
-                    other => {
-                        eprintln!(
-                            "Unknown dialog action '{}'. Use 'accept' or 'dismiss'.",
-                            other
-                        );
+            }
+            match stopped {
+                0 if !failed => println!("No sessions running"),
+                1 => println!("Stopped 1 session"),
+                n => println!("Stopped {} sessions", n),
+            }
+            if failed {
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            }
+        }
+
+        Cmd::Ping => {
+            let started = std::time::Instant::now();
+            let answer = tokio::time::timeout(
+                std::time::Duration::from_millis(cli.timeout),
+                client::send_if_running(&sock, Command::Ping),
+            )
+            .await;
+            match answer {
+                Ok(Ok(Some(resp))) if resp.ok => {
+                    println!("pong ({}ms)", started.elapsed().as_millis());
+                    ExitCode::SUCCESS
+                }
+                Ok(Ok(Some(resp))) => {
+                    eprintln!("{}", resp.error.unwrap_or_else(|| "Unknown error".into()));
+                    ExitCode::FAILURE
+                }
+                Ok(Ok(None)) => {
+                    eprintln!("No session '{}' running", cli.session);
+                    ExitCode::FAILURE
+                }
+                Ok(Err(e)) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+                Err(_) => {
+                    eprintln!(
+                        "Session '{}' didn't answer within {}ms",
+                        cli.session, cli.timeout
+                    );
+                    ExitCode::FAILURE
+                }
+            }
+        }
+
+        Cmd::Stop { .. } => match client::send_if_running(&sock, Command::Stop).await {
+            Ok(Some(_)) => {
+                println!("Stopped session '{}'", cli.session);
+                ExitCode::SUCCESS
+            }
+            Ok(None) => {
+                println!("No session '{}' running", cli.session);
+                ExitCode::SUCCESS
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                ExitCode::FAILURE
+            }
+        },
+
+        Cmd::Tree {
+            selector,
+            depth,
+            max_text,
+            only,
+            snapshot,
+            diff,
+            ..
+        } if snapshot.is_some() || diff.is_some() => {
+            let before = match &diff {
+                Some(file) => match std::fs::read_to_string(file)
+                    .map_err(|e| e.to_string())
+                    .and_then(|s| serde_json::from_str(&s).map_err(|e| e.to_string()))
+                {
+                    Ok(tree) => Some(tree),
+                    Err(e) => {
+                        eprintln!("Cannot read snapshot {}: {}", file.display(), e);
                         return ExitCode::FAILURE;
                     }
                 },
-                Cmd::Console { clear: true } => Command::ConsoleClear,
-                Cmd::Console { clear: false } => Command::Console,
-                Cmd::Network { clear: true, .. } => Command::NetworkClear,
-                Cmd::Network {
-                    clear: false,
-                    r#type,
-                    url,
-                    include_ws_messages,
-                } => Command::Network {
-                    types: r#type,
-                    url_pattern: url,
-                    include_ws_messages,
-                },
-                Cmd::ClipboardCopy { selector } => Command::ClipboardCopy {
-                    selector,
-                    timeout: cli.timeout,
-                },
-                Cmd::ClipboardPaste => Command::ClipboardPaste,
-                Cmd::ComputedStyle {
-                    selector,
-                    properties,
-                } => Command::ComputedStyle {
-                    selector,
-                    properties,
-                    timeout: cli.timeout,
-                },
-                Cmd::Eval { js } => Command::Eval { js },
-                Cmd::Screenshot { selector, path } => Command::Screenshot {
-                    selector,
-                    path,
-                    timeout: cli.timeout,
-                },
-                Cmd::Tree { selector } => Command::Tree {
-                    selector,
-                    timeout: cli.timeout,
-                },
+                None => None,
+            };
+            let command = Command::Tree {
+                selector,
+                timeout: cli.timeout,
+                outline: false,
+                depth,
+                max_text,
+                only,
+            };
+            let tree = match client::send(&sock, command).await {
+                Ok(resp) if resp.ok => resp.value.unwrap_or_default(),
+                Ok(resp) => {
+                    eprintln!("{}", resp.error.unwrap_or_else(|| "Unknown error".into()));
+                    return ExitCode::FAILURE;
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            if let Some(before) = before {
+                let changes = tree::diff(&before, &tree);
+                println!("{}", serde_json::to_string_pretty(&changes).unwrap());
+            }
+            if let Some(file) = snapshot {
+                let json = serde_json::to_string_pretty(&tree).unwrap();
+                if let Err(e) = std::fs::write(&file, json + "\n") {
+                    eprintln!("Cannot write snapshot {}: {}", file.display(), e);
+                    return ExitCode::FAILURE;
+                }
+            }
+            ExitCode::SUCCESS
+        }
+
+        Cmd::Login {
+            action,
+            name,
+            until,
+            wait,
+        } if action == "record" => {
+            // Before recording, not after the user has logged in
+            if let Err(e) = login::check_name(&name) {
+                eprintln!("{}", e);
+                return ExitCode::FAILURE;
+            }
+            let command = Command::LoginRecord {
+                until,
+                timeout: wait,
+            };
+            let steps = match client::send(&sock, command).await {
+                Ok(resp) if resp.ok => resp.value.unwrap_or_default(),
+                Ok(resp) => {
+                    eprintln!("{}", resp.error.unwrap_or_else(|| "Unknown error".into()));
+                    return ExitCode::FAILURE;
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            let steps: Vec<LoginStep> = match serde_json::from_value(steps) {
+                Ok(steps) => steps,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
+            };
+            match login::save(&name, &steps) {
+                Ok(path) => {
+                    println!("Recorded {} steps to {}", steps.len(), path.display());
+                    ExitCode::SUCCESS
+                }
+                Err(e) => {
+                    eprintln!("{}", e);
+                    ExitCode::FAILURE
+                }
+            }
+        }
+
+        cmd => {
+            // Audits print their findings and still fail when there are any.
+            let fail_on_findings = matches!(cmd, Cmd::Audit { .. } | Cmd::Net { fail: true, .. });
+            let command = match build_command(cmd, cli.timeout) {
+                Ok(command) => command,
+                Err(e) => {
+                    eprintln!("{}", e);
+                    return ExitCode::FAILURE;
+                }
             };
 
             match client::send(&sock, command).await {
                 Ok(resp) => {
+                    if let Some(warning) = &resp.warning {
+                        eprintln!("{}", warning);
+                    }
                     if resp.ok {
                         if let Some(value) = resp.value {
+                            if fail_on_findings && value.as_array().is_some_and(|a| !a.is_empty()) {
+                                println!("{}", serde_json::to_string_pretty(&value).unwrap());
+                                return ExitCode::FAILURE;
+                            }
                             match value {
                                 serde_json::Value::String(s) => println!("{}", s),
                                 serde_json::Value::Bool(b) => {
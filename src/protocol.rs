@@ -11,6 +11,8 @@ pub enum Command {
     Open {
         url: String,
         timeout: u64,
+        #[serde(default)]
+        show_redirects: bool,
     },
     Reload,
     Url,
@@ -30,6 +32,24 @@ pub enum Command {
         selectors: Vec<String>,
         timeout: u64,
     },
+    WaitResponse {
+        /// URL glob
+        pattern: String,
+        /// Status filter like `200` or `2xx`
+        status: Option<String>,
+        print_body: bool,
+        /// Element to click once waiting has started
+        selector: Option<String>,
+        timeout: u64,
+    },
+    WaitRequest {
+        /// URL glob
+        pattern: String,
+        print: bool,
+        /// Element to click once waiting has started
+        selector: Option<String>,
+        timeout: u64,
+    },
     Click {
         selector: String,
         timeout: u64,
@@ -37,33 +57,92 @@ pub enum Command {
         modifiers: Vec<String>,
         #[serde(default)]
         button: Option<String>,
+        #[serde(default)]
+        position: Option<(f64, f64)>,
+        #[serde(default)]
+        click_count: Option<u32>,
+        #[serde(default)]
+        force: bool,
+        #[serde(default)]
+        trial: bool,
+    },
+    Tap {
+        selector: String,
+        timeout: u64,
     },
     Fill {
         selector: String,
         text: String,
         timeout: u64,
     },
+    FillForm {
+        fields: Vec<(String, serde_json::Value)>,
+        timeout: u64,
+    },
+    Clear {
+        selector: String,
+        timeout: u64,
+    },
     Press {
+        /// Older clients send a single `key`
+        #[serde(alias = "key", deserialize_with = "one_or_many")]
+        keys: Vec<String>,
+    },
+    KeyDown {
+        key: String,
+    },
+    KeyUp {
         key: String,
     },
     Type {
         text: String,
         delay: Option<f64>,
     },
+    InsertText {
+        text: String,
+    },
     Exists {
         selector: String,
     },
+    IsVisible {
+        selector: String,
+    },
+    IsEnabled {
+        selector: String,
+        timeout: u64,
+    },
+    IsChecked {
+        selector: String,
+        timeout: u64,
+    },
+    IsEditable {
+        selector: String,
+        timeout: u64,
+    },
     Text {
         selector: String,
         timeout: u64,
+        #[serde(default)]
+        all: bool,
+        #[serde(default)]
+        trim: bool,
     },
     Attr {
         selector: String,
         name: String,
         timeout: u64,
+        #[serde(default)]
+        all: bool,
     },
     Count {
         selector: String,
+        #[serde(default)]
+        visible: Option<bool>,
+        #[serde(default)]
+        enabled: Option<bool>,
+        /// Regex literal (`/re/flags`) the element's text must match
+        #[serde(default)]
+        matching: Option<String>,
     },
     Eval {
         js: String,
@@ -72,49 +151,237 @@ pub enum Command {
         selector: Option<String>,
         path: String,
         timeout: u64,
+        #[serde(default)]
+        mask: Vec<String>,
+        #[serde(default)]
+        padding: Option<u32>,
+        #[serde(default)]
+        full_page: bool,
+        #[serde(default)]
+        format: Option<String>,
+        #[serde(default)]
+        quality: Option<u8>,
+        #[serde(default)]
+        clip: Option<(f64, f64, f64, f64)>,
+        #[serde(default)]
+        compare: Option<ScreenshotCompare>,
+        #[serde(default)]
+        annotate: Vec<String>,
     },
     Tree {
         selector: Option<String>,
         timeout: u64,
+        #[serde(default)]
+        outline: bool,
+        #[serde(default)]
+        depth: Option<usize>,
+        #[serde(default)]
+        max_text: Option<usize>,
+        #[serde(default)]
+        only: Option<Vec<String>>,
+    },
+    SnapshotSave {
+        name: String,
+        /// Defaults to the `within` scope, or the whole page
+        selector: Option<String>,
+        timeout: u64,
+    },
+    SnapshotDiff {
+        name: String,
+    },
+    Meta,
+    StructuredData {
+        microdata: bool,
+    },
+    AuditA11y {
+        tags: Vec<String>,
+        axe_url: Option<String>,
+        timeout: u64,
+    },
+    Markdown {
+        selector: Option<String>,
+        timeout: u64,
+    },
+    Links {
+        selector: Option<String>,
+        same_origin: bool,
+        unique: bool,
+        timeout: u64,
+    },
+    Table {
+        selector: String,
+        csv: bool,
+        header_row: bool,
+        timeout: u64,
+    },
+    Find {
+        text: String,
+        limit: usize,
+    },
+    Pick {
+        timeout: u64,
+    },
+    SelectorFor {
+        selector: String,
+        timeout: u64,
     },
     Header {
         name: String,
         value: String,
+        /// Only for requests whose URL matches this glob
+        #[serde(default)]
+        url_match: Option<String>,
     },
     HeaderClear,
+    Lang {
+        /// Accept-Language value, e.g. "sv-SE,en;q=0.8"
+        languages: String,
+    },
+    LangClear,
+    Auth {
+        username: String,
+        password: String,
+        origin: Option<String>,
+    },
+    AuthClear,
+    Geo {
+        latitude: f64,
+        longitude: f64,
+        /// Meters
+        accuracy: Option<f64>,
+    },
+    GeoClear,
     Cookie {
         name: String,
         value: String,
         url: String,
+        /// Used instead of the URL's host when given
+        #[serde(default)]
+        domain: Option<String>,
+        #[serde(default)]
+        path: Option<String>,
+        /// Unix time in seconds
+        #[serde(default)]
+        expires: Option<f64>,
+        #[serde(default)]
+        http_only: bool,
+        #[serde(default)]
+        secure: bool,
+        /// Strict, Lax or None
+        #[serde(default)]
+        same_site: Option<String>,
     },
     CookieList,
     CookieClear,
+    CookieImport {
+        /// Cookies in the driver's addCookies format
+        cookies: Vec<serde_json::Value>,
+    },
+    Alias {
+        name: String,
+        selector: String,
+    },
+    AliasList,
+    AliasClear,
+    Within {
+        selector: String,
+    },
+    WithinClear,
     Viewport {
         width: u32,
         height: u32,
+        /// Device scale factor; changing it, `mobile` or `touch` replaces
+        /// the browser context
+        #[serde(default)]
+        scale: Option<f64>,
+        #[serde(default)]
+        mobile: Option<bool>,
+        #[serde(default)]
+        touch: Option<bool>,
+        /// For reloading the page in the new context
+        #[serde(default)]
+        timeout: u64,
+    },
+    StateSave {
+        /// Absolute path of the storage state JSON to write
+        path: String,
+    },
+    StateLoad {
+        /// Absolute path of a storage state JSON
+        path: String,
+        /// For reloading the page in the new context
+        timeout: u64,
+    },
+    StateSnapshotSave {
+        name: String,
+    },
+    StateSnapshotRestore {
+        name: String,
+        /// For opening the snapshot's page in the new context
+        timeout: u64,
+    },
+    Emulate {
+        /// screen, print or no-override
+        #[serde(default)]
+        media: Option<String>,
+        /// dark, light, no-preference or no-override
+        color_scheme: Option<String>,
+        /// reduce, no-preference or no-override
+        reduced_motion: Option<String>,
+        /// active, none or no-override
+        #[serde(default)]
+        forced_colors: Option<String>,
     },
     InputFiles {
         selector: String,
         paths: Vec<String>,
         timeout: u64,
     },
+    FileChooser {
+        selector: String,
+        paths: Vec<String>,
+        timeout: u64,
+    },
+    Download {
+        selector: String,
+        path: String,
+        timeout: u64,
+    },
     Select {
         selector: String,
         values: Vec<String>,
         by_label: bool,
+        #[serde(default)]
+        by_index: bool,
         timeout: u64,
     },
+    Highlight {
+        selector: String,
+        duration: u64,
+    },
     Hover {
         selector: String,
         timeout: u64,
+        #[serde(default)]
+        force: bool,
+        #[serde(default)]
+        trial: bool,
     },
     Check {
         selector: String,
         timeout: u64,
+        #[serde(default)]
+        force: bool,
+        #[serde(default)]
+        trial: bool,
     },
     Uncheck {
         selector: String,
         timeout: u64,
+        #[serde(default)]
+        force: bool,
+        #[serde(default)]
+        trial: bool,
     },
     Dblclick {
         selector: String,
@@ -123,6 +390,10 @@ pub enum Command {
         modifiers: Vec<String>,
         #[serde(default)]
         button: Option<String>,
+        #[serde(default)]
+        force: bool,
+        #[serde(default)]
+        trial: bool,
     },
     Focus {
         selector: String,
@@ -136,6 +407,11 @@ pub enum Command {
         selector: String,
         timeout: u64,
     },
+    InnerText {
+        selector: String,
+        timeout: u64,
+        all: bool,
+    },
     InputValue {
         selector: String,
         timeout: u64,
@@ -144,6 +420,16 @@ pub enum Command {
         selector: String,
         timeout: u64,
     },
+    Scroll {
+        selector: Option<String>,
+        by: Option<(f64, f64)>,
+        to: Option<String>,
+        timeout: u64,
+    },
+    Bbox {
+        selector: String,
+        timeout: u64,
+    },
     ComputedStyle {
         selector: String,
         properties: Vec<String>,
@@ -160,19 +446,263 @@ pub enum Command {
         include_ws_messages: bool,
     },
     NetworkClear,
+    NetLog {
+        /// Regex the request URL must match
+        filter: Option<String>,
+        /// Only requests started at or after this time (ms since the epoch)
+        since: Option<i64>,
+    },
+    NetFailures {
+        filter: Option<String>,
+        /// Defaults to when the current page started loading
+        since: Option<i64>,
+    },
+    NetBody {
+        /// URL glob
+        pattern: String,
+    },
+    NetTiming {
+        /// URL glob
+        pattern: String,
+    },
+    NetSave {
+        /// URL glob
+        pattern: String,
+        /// Absolute directory to write the bodies to
+        dir: String,
+    },
+    NetSaveStop,
+    NetCapture,
+    NetClear,
+    HarStart {
+        omit_content: bool,
+    },
+    HarStop {
+        path: String,
+    },
+    Block {
+        /// URL globs
+        patterns: Vec<String>,
+        /// Playwright resource types (image, font, ...)
+        types: Vec<String>,
+        /// Bundled domain lists (trackers, ads) or filter list files
+        #[serde(default)]
+        presets: Vec<String>,
+    },
+    BlockList,
+    BlockClear,
+    Mock {
+        /// URL glob
+        pattern: String,
+        status: u16,
+        body: Option<String>,
+        body_file: Option<String>,
+        content_type: Option<String>,
+    },
+    MockList,
+    MockRemove {
+        pattern: String,
+    },
+    MockHar {
+        path: String,
+        /// Record traffic into `path` instead of replaying it
+        update: bool,
+        /// Send requests missing from the HAR to the network instead of
+        /// aborting them
+        not_found_fallback: bool,
+    },
+    MockClear,
+    Fetch {
+        method: String,
+        /// Absolute, or relative to the current page
+        url: String,
+        headers: Vec<(String, String)>,
+        body: Option<String>,
+        body_file: Option<String>,
+        /// Print status and headers along with the body
+        include: bool,
+        timeout: u64,
+    },
+    CacheDisable,
+    CacheEnable,
+    CacheClear,
+    ClearSiteData {
+        /// URL or origin (default: the current page's)
+        origin: Option<String>,
+        /// Which data to clear; all of it if none are set
+        cookies: bool,
+        storage: bool,
+        cache: bool,
+    },
+    SwList,
+    SwUnregister,
+    CacheStorageList,
+    CacheStorageDump {
+        name: String,
+    },
+    IdbList,
+    IdbDump {
+        db: String,
+        store: String,
+        limit: Option<usize>,
+    },
+    SwBypass {
+        enabled: bool,
+    },
     ClipboardCopy {
         selector: String,
         timeout: u64,
     },
     ClipboardPaste,
+    ClipboardGet,
+    ClipboardSet {
+        text: String,
+    },
     DialogAccept {
         prompt_text: Option<String>,
+        #[serde(default)]
+        persist: bool,
+    },
+    DialogDismiss {
+        #[serde(default)]
+        persist: bool,
+    },
+    DialogLast,
+    /// PID, URL, title and uptime for `sessions`
+    SessionInfo,
+    Status,
+    /// Checks that the daemon and its browser answer
+    Ping,
+    LoginRecord {
+        /// Stop once the page's URL matches this glob
+        until: Option<String>,
+        timeout: u64,
+    },
+    LoginReplay {
+        steps: Vec<LoginStep>,
+        timeout: u64,
     },
-    DialogDismiss,
     Stop,
 }
 
+/// A step of a recorded login, as `login record` saves it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum LoginStep {
+    Open {
+        url: String,
+    },
+    Fill {
+        selector: String,
+        value: String,
+    },
+    Click {
+        selector: String,
+    },
+    /// A key pressed in a field, e.g. Enter to submit
+    Press {
+        selector: String,
+        key: String,
+    },
+    Select {
+        selector: String,
+        value: String,
+    },
+}
+
+/// How `screenshot --compare` decides whether a capture matches its baseline.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ScreenshotCompare {
+    pub baseline: String,
+    /// Per-pixel color difference (0-1) below which pixels count as equal
+    pub threshold: f64,
+    pub max_diff_pixels: u64,
+    pub max_diff_ratio: Option<f64>,
+    pub diff_path: String,
+}
+
+impl ScreenshotCompare {
+    /// How many differing pixels still count as a match for an image with
+    /// `total` pixels.
+    pub fn diff_pixels_allowed(&self, total: u64) -> u64 {
+        let by_ratio = self
+            .max_diff_ratio
+            .map_or(0, |r| (r * total as f64).floor() as u64);
+        self.max_diff_pixels.max(by_ratio)
+    }
+}
+
 impl Command {
+    /// Whether the command sets cookies or storage itself, rather than
+    /// through whatever the page does.
+    pub fn sets_storage(&self) -> bool {
+        matches!(
+            self,
+            Command::Cookie { .. }
+                | Command::CookieClear
+                | Command::CookieImport { .. }
+                | Command::StateLoad { .. }
+                | Command::StateSnapshotRestore { .. }
+                | Command::ClearSiteData { .. }
+                | Command::LoginReplay { .. }
+        )
+    }
+
+    /// Whether the command only looks, so it can't have changed the
+    /// cookies or storage. Waits are left out: the page keeps running.
+    pub fn is_query(&self) -> bool {
+        matches!(
+            self,
+            Command::Url
+                | Command::Exists { .. }
+                | Command::IsVisible { .. }
+                | Command::IsEnabled { .. }
+                | Command::IsChecked { .. }
+                | Command::IsEditable { .. }
+                | Command::Text { .. }
+                | Command::Attr { .. }
+                | Command::Count { .. }
+                | Command::Screenshot { .. }
+                | Command::Tree { .. }
+                | Command::SnapshotSave { .. }
+                | Command::SnapshotDiff { .. }
+                | Command::Meta
+                | Command::StructuredData { .. }
+                | Command::Markdown { .. }
+                | Command::Links { .. }
+                | Command::Table { .. }
+                | Command::Find { .. }
+                | Command::SelectorFor { .. }
+                | Command::CookieList
+                | Command::AliasList
+                | Command::StateSave { .. }
+                | Command::StateSnapshotSave { .. }
+                | Command::InnerHtml { .. }
+                | Command::InnerText { .. }
+                | Command::InputValue { .. }
+                | Command::Bbox { .. }
+                | Command::ComputedStyle { .. }
+                | Command::Console
+                | Command::Network { .. }
+                | Command::NetLog { .. }
+                | Command::NetFailures { .. }
+                | Command::NetBody { .. }
+                | Command::NetTiming { .. }
+                | Command::BlockList
+                | Command::MockList
+                | Command::SwList
+                | Command::CacheStorageList
+                | Command::CacheStorageDump { .. }
+                | Command::IdbList
+                | Command::IdbDump { .. }
+                | Command::ClipboardGet
+                | Command::DialogLast
+                | Command::SessionInfo
+                | Command::Status
+                | Command::Ping
+        )
+    }
+
     pub fn requires_page(&self) -> bool {
         !matches!(
             self,
@@ -180,12 +710,52 @@ impl Command {
                 | Command::Stop
                 | Command::Header { .. }
                 | Command::HeaderClear
+                | Command::Lang { .. }
+                | Command::LangClear
+                | Command::Auth { .. }
+                | Command::AuthClear
+                | Command::Geo { .. }
+                | Command::GeoClear
                 | Command::Cookie { .. }
                 | Command::CookieList
                 | Command::CookieClear
+                | Command::CookieImport { .. }
+                | Command::Alias { .. }
+                | Command::AliasList
+                | Command::AliasClear
+                | Command::Within { .. }
+                | Command::WithinClear
                 | Command::Viewport { .. }
+                | Command::StateSave { .. }
+                | Command::StateLoad { .. }
+                | Command::StateSnapshotSave { .. }
+                | Command::StateSnapshotRestore { .. }
+                | Command::Emulate { .. }
+                | Command::NetSave { .. }
+                | Command::NetSaveStop
+                | Command::NetCapture
+                | Command::HarStart { .. }
+                | Command::HarStop { .. }
+                | Command::Block { .. }
+                | Command::BlockList
+                | Command::BlockClear
+                | Command::Mock { .. }
+                | Command::MockList
+                | Command::MockRemove { .. }
+                | Command::MockHar { .. }
+                | Command::MockClear
+                | Command::CacheDisable
+                | Command::CacheEnable
+                | Command::CacheClear
+                | Command::SessionInfo
+                | Command::Status
+                | Command::Ping
+                | Command::LoginReplay { .. }
+                | Command::ClearSiteData { .. }
+                | Command::SwBypass { .. }
                 | Command::DialogAccept { .. }
-                | Command::DialogDismiss
+                | Command::DialogDismiss { .. }
+                | Command::DialogLast
         )
     }
 }
@@ -197,6 +767,10 @@ pub struct Response {
     pub value: Option<serde_json::Value>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub error: Option<String>,
+    /// Something the user should know about besides the result, like the
+    /// browser having been restarted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub warning: Option<String>,
 }
 
 impl Response {
@@ -205,6 +779,7 @@ impl Response {
             ok: true,
             value: None,
             error: None,
+            warning: None,
         }
     }
 
@@ -213,6 +788,7 @@ impl Response {
             ok: true,
             value: Some(value),
             error: None,
+            warning: None,
         }
     }
 
@@ -221,6 +797,24 @@ impl Response {
             ok: false,
             value: None,
             error: Some(msg),
+            warning: None,
         }
     }
 }
+
+/// A list that may also be given as a single string.
+fn one_or_many<'de, D>(deserializer: D) -> Result<Vec<String>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum OneOrMany {
+        One(String),
+        Many(Vec<String>),
+    }
+    Ok(match OneOrMany::deserialize(deserializer)? {
+        OneOrMany::One(key) => vec![key],
+        OneOrMany::Many(keys) => keys,
+    })
+}
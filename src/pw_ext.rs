@@ -1,7 +1,13 @@
-use playwright_rs::server::channel_owner::ChannelOwner;
+use playwright_rs::server::channel::Channel;
+use playwright_rs::server::channel_owner::{
+    ChannelOwner, ChannelOwnerImpl, DisposeReason, ParentOrConnection,
+};
+use playwright_rs::server::connection::ConnectionLike;
 use playwright_rs::{Browser, BrowserContext, BrowserType, Page};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+use tokio::sync::mpsc;
 
 // -- BrowserType extensions --
 
@@ -83,17 +89,6 @@ pub async fn set_extra_http_headers(
         .await
 }
 
-// -- Page extensions --
-
-pub async fn disable_network_interception(page: &Page) -> playwright_rs::Result<()> {
-    page.channel()
-        .send_no_result(
-            "setNetworkInterceptionPatterns",
-            serde_json::json!({ "patterns": [] }),
-        )
-        .await
-}
-
 #[derive(Deserialize)]
 pub struct Cookie {
     pub name: String,
@@ -120,23 +115,129 @@ pub async fn get_cookies(ctx: &BrowserContext) -> playwright_rs::Result<Vec<Cook
     Ok(response.cookies)
 }
 
+/// The context's cookies and local storage as Playwright writes them for
+/// `storageState`, including any fields playwright-rs doesn't model.
+pub async fn storage_state(ctx: &BrowserContext) -> playwright_rs::Result<serde_json::Value> {
+    ctx.channel()
+        .send("storageState", serde_json::json!({}))
+        .await
+}
+
+/// Formats a storage state exactly as Playwright's `storageState({ path })`
+/// writes it, with `JSON.stringify(state, undefined, 2)`, so the files are
+/// interchangeable byte for byte. serde_json sorts object keys, so this goes
+/// through structs in Playwright's key order; fields they don't know about
+/// are kept at the end.
+pub fn storage_state_json(state: serde_json::Value) -> serde_json::Result<String> {
+    type Other = serde_json::Map<String, serde_json::Value>;
+
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Cookie {
+        name: String,
+        value: String,
+        domain: String,
+        path: String,
+        expires: serde_json::Number,
+        http_only: bool,
+        secure: bool,
+        same_site: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        partition_key: Option<String>,
+        #[serde(flatten)]
+        other: Other,
+    }
+    #[derive(Serialize, Deserialize)]
+    struct Item {
+        name: String,
+        value: String,
+    }
+    #[derive(Serialize, Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct Origin {
+        origin: String,
+        local_storage: Vec<Item>,
+        #[serde(flatten)]
+        other: Other,
+    }
+    #[derive(Serialize, Deserialize)]
+    struct StorageState {
+        cookies: Vec<Cookie>,
+        origins: Vec<Origin>,
+        #[serde(flatten)]
+        other: Other,
+    }
+
+    serde_json::to_string_pretty(&serde_json::from_value::<StorageState>(state)?)
+}
+
 pub async fn clear_cookies(ctx: &BrowserContext) -> playwright_rs::Result<()> {
     ctx.channel()
         .send_no_result("clearCookies", serde_json::json!({}))
         .await
 }
 
+/// Adds cookies already in the driver's format ({name, value, domain, path,
+/// expires, httpOnly, secure, sameSite}).
+pub async fn add_cookies(
+    ctx: &BrowserContext,
+    cookies: Vec<serde_json::Value>,
+) -> playwright_rs::Result<()> {
+    ctx.channel()
+        .send_no_result("addCookies", serde_json::json!({ "cookies": cookies }))
+        .await
+}
+
+/// Optional cookie attributes for `add_cookie`.
+#[derive(Default)]
+pub struct CookieAttributes {
+    pub path: Option<String>,
+    /// Unix time in seconds; a session cookie if unset
+    pub expires: Option<f64>,
+    pub http_only: bool,
+    pub secure: bool,
+    /// "Strict", "Lax" or "None"
+    pub same_site: Option<String>,
+}
+
+/// Adds a cookie for `domain` if given, or else for the host of `url`.
 pub async fn add_cookie(
     ctx: &BrowserContext,
     name: String,
     value: String,
     url: String,
+    domain: Option<String>,
+    attributes: &CookieAttributes,
 ) -> playwright_rs::Result<()> {
-    let cookie = serde_json::json!({
+    let mut cookie = serde_json::json!({
         "name": name,
         "value": value,
-        "url": url,
     });
+    // The driver takes either a URL, or a domain and a path.
+    match (domain, &attributes.path) {
+        (None, None) => cookie["url"] = serde_json::json!(url),
+        (domain, path) => {
+            let host = url.split_once("://").map_or("", |(_, rest)| {
+                rest.split(['/', '?', '#']).next().unwrap_or("")
+            });
+            let host = host.rsplit('@').next().unwrap_or("");
+            let host = host.split(':').next().unwrap_or("");
+            cookie["domain"] = serde_json::json!(domain.as_deref().unwrap_or(host));
+            cookie["path"] = serde_json::json!(path.as_deref().unwrap_or("/"));
+        }
+    }
+    if let Some(expires) = attributes.expires {
+        cookie["expires"] = serde_json::json!(expires);
+    }
+    if attributes.http_only {
+        cookie["httpOnly"] = serde_json::json!(true);
+    }
+    if attributes.secure {
+        cookie["secure"] = serde_json::json!(true);
+    }
+    if let Some(same_site) = &attributes.same_site {
+        cookie["sameSite"] = serde_json::json!(same_site);
+    }
     ctx.channel()
         .send_no_result("addCookies", serde_json::json!({ "cookies": [cookie] }))
         .await
@@ -158,7 +259,354 @@ pub async fn grant_permissions(
         .await
 }
 
+pub async fn clear_permissions(ctx: &BrowserContext) -> playwright_rs::Result<()> {
+    ctx.channel()
+        .send_no_result("clearPermissions", serde_json::json!({}))
+        .await
+}
+
+/// Sets the position pages in the context see, or makes it unavailable with
+/// `None`.
+pub async fn set_geolocation(
+    ctx: &BrowserContext,
+    geolocation: Option<&playwright_rs::Geolocation>,
+) -> playwright_rs::Result<()> {
+    // The driver rejects null for optional fields, so leave it out.
+    let params = match geolocation {
+        Some(geolocation) => serde_json::json!({ "geolocation": geolocation }),
+        None => serde_json::json!({}),
+    };
+    ctx.channel().send_no_result("setGeolocation", params).await
+}
+
+// Playwright's HAR recorder runs inside the driver and sees every request in
+// the context, which is the only way to observe traffic: playwright-rs drops
+// request/response events. harExport stops the recorder, so callers that want
+// continuous recording start a new one after each export.
+
+/// Starts recording the context's traffic and returns the recorder id for
+/// `har_export`. `content` is "embed" to keep response bodies in the HAR
+/// or "omit" to leave them out.
+pub async fn har_start(ctx: &BrowserContext, content: &str) -> playwright_rs::Result<String> {
+    #[derive(Deserialize)]
+    struct HarStartResponse {
+        #[serde(rename = "harId")]
+        har_id: String,
+    }
+    let response: HarStartResponse = ctx
+        .channel()
+        .send(
+            "harStart",
+            serde_json::json!({ "options": { "content": content, "zip": false } }),
+        )
+        .await?;
+    Ok(response.har_id)
+}
+
+/// Stops the recorder and returns the HAR it wrote, as JSON text.
+pub async fn har_export(ctx: &BrowserContext, har_id: &str) -> playwright_rs::Result<String> {
+    #[derive(Deserialize)]
+    struct GuidRef {
+        guid: String,
+    }
+    #[derive(Deserialize)]
+    struct HarExportResponse {
+        artifact: GuidRef,
+    }
+    let response: HarExportResponse = ctx
+        .channel()
+        .send("harExport", serde_json::json!({ "harId": har_id }))
+        .await?;
+    let artifact = ctx.connection().get_object(&response.artifact.guid).await?;
+    let path = artifact.initializer()["absolutePath"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let har = tokio::fs::read_to_string(&path)
+        .await
+        .map_err(|e| playwright_rs::Error::ProtocolError(format!("Cannot read HAR: {}", e)));
+    artifact
+        .channel()
+        .send_no_result("delete", serde_json::json!({}))
+        .await?;
+    har
+}
+
+/// Sends an HTTP request through the context's APIRequestContext, which
+/// shares the browser's cookie jar and extra HTTP headers. Redirects are
+/// followed. playwright-rs only uses it internally for `route.fetch`.
+pub async fn api_fetch(
+    ctx: &BrowserContext,
+    method: &str,
+    url: &str,
+    headers: &[(String, String)],
+    body: Option<&[u8]>,
+    timeout: f64,
+) -> playwright_rs::Result<playwright_rs::FetchResponse> {
+    use base64::prelude::{Engine, BASE64_STANDARD};
+
+    #[derive(Deserialize)]
+    struct Header {
+        name: String,
+        value: String,
+    }
+    #[derive(Deserialize)]
+    #[serde(rename_all = "camelCase")]
+    struct ApiResponse {
+        fetch_uid: String,
+        status: u16,
+        status_text: String,
+        headers: Vec<Header>,
+    }
+    #[derive(Deserialize)]
+    struct FetchResult {
+        response: ApiResponse,
+    }
+    #[derive(Deserialize)]
+    struct BodyResult {
+        #[serde(default)]
+        binary: Option<String>,
+    }
+
+    let guid = ctx.initializer()["requestContext"]["guid"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let request_context = ctx.connection().get_object(&guid).await?;
+    let mut params = serde_json::json!({
+        "url": url,
+        "method": method,
+        "headers": headers
+            .iter()
+            .map(|(name, value)| serde_json::json!({ "name": name, "value": value }))
+            .collect::<Vec<_>>(),
+        "timeout": timeout,
+    });
+    if let Some(body) = body {
+        params["postData"] = serde_json::json!(BASE64_STANDARD.encode(body));
+    }
+    let result: FetchResult = request_context.channel().send("fetch", params).await?;
+    let fetch_uid = serde_json::json!({ "fetchUid": result.response.fetch_uid });
+    let body: BodyResult = request_context
+        .channel()
+        .send("fetchResponseBody", fetch_uid.clone())
+        .await?;
+    request_context
+        .channel()
+        .send_no_result("disposeAPIResponse", fetch_uid)
+        .await
+        .ok();
+    Ok(playwright_rs::FetchResponse {
+        status: result.response.status,
+        status_text: result.response.status_text,
+        headers: result
+            .response
+            .headers
+            .into_iter()
+            .map(|h| (h.name, h.value))
+            .collect(),
+        body: body
+            .binary
+            .and_then(|b| BASE64_STANDARD.decode(b).ok())
+            .unwrap_or_default(),
+    })
+}
+
+/// Credentials for HTTP auth challenges, or `None` to stop answering them.
+/// Deprecated in Playwright's public API but still on the context channel.
+pub async fn set_http_credentials(
+    ctx: &BrowserContext,
+    credentials: Option<(&str, &str, Option<&str>)>,
+) -> playwright_rs::Result<()> {
+    // The driver rejects null for optional fields, so leave them out.
+    let mut params = serde_json::json!({});
+    if let Some((username, password, origin)) = credentials {
+        let mut creds = serde_json::json!({ "username": username, "password": password });
+        if let Some(origin) = origin {
+            creds["origin"] = serde_json::json!(origin);
+        }
+        params["httpCredentials"] = creds;
+    }
+    ctx.channel()
+        .send_no_result("setHTTPCredentials", params)
+        .await
+}
+
+// -- CDP sessions --
+// playwright-rs has no CDPSession type, so the driver's session object never
+// makes it into the connection's registry. Its channel still works by guid.
+
+/// A Chrome DevTools Protocol session attached to a page (Chromium only).
+/// Settings made through it last as long as the session.
+pub struct CdpSession {
+    channel: Channel,
+    connection: Arc<dyn ConnectionLike>,
+}
+
+pub async fn new_cdp_session(page: &Page) -> playwright_rs::Result<CdpSession> {
+    #[derive(Deserialize)]
+    struct GuidRef {
+        guid: String,
+    }
+    #[derive(Deserialize)]
+    struct NewSessionResponse {
+        session: GuidRef,
+    }
+    let ctx = page.context()?;
+    let response: NewSessionResponse = ctx
+        .channel()
+        .send(
+            "newCDPSession",
+            serde_json::json!({ "page": { "guid": page.guid() } }),
+        )
+        .await?;
+    Ok(CdpSession::new(response.session.guid, ctx.connection()))
+}
+
+/// A DevTools session attached to the browser rather than a page, for
+/// browser-wide domains like SystemInfo.
+pub async fn new_browser_cdp_session(browser: &Browser) -> playwright_rs::Result<CdpSession> {
+    #[derive(Deserialize)]
+    struct GuidRef {
+        guid: String,
+    }
+    #[derive(Deserialize)]
+    struct NewSessionResponse {
+        session: GuidRef,
+    }
+    let response: NewSessionResponse = browser
+        .channel()
+        .send("newBrowserCDPSession", serde_json::json!({}))
+        .await?;
+    Ok(CdpSession::new(response.session.guid, browser.connection()))
+}
+
+impl CdpSession {
+    fn new(guid: String, connection: Arc<dyn ConnectionLike>) -> Self {
+        CdpSession {
+            channel: Channel::new(guid.into(), connection.clone()),
+            connection,
+        }
+    }
+
+    pub async fn detach(self) -> playwright_rs::Result<()> {
+        self.channel
+            .send_no_result("detach", serde_json::json!({}))
+            .await
+    }
+
+    /// The session's DevTools events from now on, as (method, params) pairs.
+    /// They stop when the session is detached.
+    pub async fn events(&self) -> mpsc::UnboundedReceiver<(String, serde_json::Value)> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let guid: Arc<str> = self.channel.guid().into();
+        let base = ChannelOwnerImpl::new(
+            ParentOrConnection::Connection(self.connection.clone()),
+            "CDPSession".to_string(),
+            guid.clone(),
+            serde_json::Value::Null,
+        );
+        self.connection
+            .register_object(guid, Arc::new(CdpEvents { base, tx }))
+            .await;
+        rx
+    }
+
+    pub async fn send(
+        &self,
+        method: &str,
+        params: serde_json::Value,
+    ) -> playwright_rs::Result<serde_json::Value> {
+        let response: serde_json::Value = self
+            .channel
+            .send(
+                "send",
+                serde_json::json!({ "method": method, "params": params }),
+            )
+            .await?;
+        Ok(response["result"].clone())
+    }
+}
+
+// The driver sends session events to the session's guid, which the
+// connection would drop as unknown. This stands in for it in the registry
+// and passes the events on; the driver's __dispose__ on detach removes it.
+struct CdpEvents {
+    base: ChannelOwnerImpl,
+    tx: mpsc::UnboundedSender<(String, serde_json::Value)>,
+}
+
+impl ChannelOwner for CdpEvents {
+    fn guid(&self) -> &str {
+        self.base.guid()
+    }
+    fn type_name(&self) -> &str {
+        self.base.type_name()
+    }
+    fn parent(&self) -> Option<Arc<dyn ChannelOwner>> {
+        self.base.parent()
+    }
+    fn connection(&self) -> Arc<dyn ConnectionLike> {
+        self.base.connection()
+    }
+    fn initializer(&self) -> &serde_json::Value {
+        self.base.initializer()
+    }
+    fn channel(&self) -> &Channel {
+        self.base.channel()
+    }
+    fn dispose(&self, reason: DisposeReason) {
+        self.base.dispose(reason)
+    }
+    fn adopt(&self, child: Arc<dyn ChannelOwner>) {
+        self.base.adopt(child)
+    }
+    fn add_child(&self, guid: Arc<str>, child: Arc<dyn ChannelOwner>) {
+        self.base.add_child(guid, child)
+    }
+    fn remove_child(&self, guid: &str) {
+        self.base.remove_child(guid)
+    }
+    fn on_event(&self, method: &str, params: serde_json::Value) {
+        if method == "event" {
+            let name = params["method"].as_str().unwrap_or_default().to_string();
+            let _ = self.tx.send((name, params["params"].clone()));
+        }
+    }
+    fn was_collected(&self) -> bool {
+        self.base.was_collected()
+    }
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+}
+
 // -- Page extensions --
+
+pub async fn disable_network_interception(page: &Page) -> playwright_rs::Result<()> {
+    page.channel()
+        .send_no_result(
+            "setNetworkInterceptionPatterns",
+            serde_json::json!({ "patterns": [] }),
+        )
+        .await
+}
+
+// page.emulateMedia takes each option separately, and leaves out ones alone.
+
+/// Overrides CSS media features for the page, given as (option, value) pairs
+/// like `("colorScheme", "dark")`. "no-override" goes back to the browser's
+/// own value.
+pub async fn emulate_media(page: &Page, options: &[(&str, &str)]) -> playwright_rs::Result<()> {
+    let params: serde_json::Map<String, serde_json::Value> = options
+        .iter()
+        .map(|(name, value)| (name.to_string(), serde_json::json!(value)))
+        .collect();
+    page.channel()
+        .send_no_result("emulateMedia", serde_json::Value::Object(params))
+        .await
+}
+
 // page.evaluate_value exists but the stock signatures take &str where we need
 // String-based wrappers. These are thin helpers.
 
@@ -168,48 +616,130 @@ pub async fn page_evaluate_value(page: &Page, js: &str) -> playwright_rs::Result
 
 // -- Locator extensions --
 // Locator::evaluate runs JS with the matched element as argument (evalOnSelector).
-// Locator::evaluate_value runs JS in the page context via the locator's frame.
-// Stock playwright-rs doesn't expose these, so we use page.evaluate with querySelector.
+// Stock playwright-rs doesn't expose it, so we send evalOnSelector on the main
+// frame ourselves. Unlike document.querySelector this takes any Playwright
+// selector and pierces shadow roots, like click and text do.
+
+/// Runs `js` (a function of the element) on the first element matching
+/// `selector` and returns its result JSON-stringified.
+async fn eval_on_selector(page: &Page, selector: &str, js: &str) -> playwright_rs::Result<String> {
+    #[derive(Deserialize)]
+    struct EvalResponse {
+        value: serde_json::Value,
+    }
+    let frame = page.main_frame().await?;
+    let response: EvalResponse = frame
+        .channel()
+        .send(
+            "evalOnSelector",
+            serde_json::json!({
+                "selector": selector,
+                "strict": false,
+                "expression": format!("async el => JSON.stringify(await ({})(el))", js),
+                "isFunction": true,
+                "arg": { "value": { "v": "undefined" }, "handles": [] },
+            }),
+        )
+        .await
+        .map_err(|e| match e {
+            playwright_rs::Error::ProtocolError(msg)
+                if msg.contains("Failed to find element matching selector") =>
+            {
+                playwright_rs::Error::ProtocolError(format!(
+                    "No element found for selector: {}",
+                    selector
+                ))
+            }
+            other => other,
+        })?;
+    Ok(response
+        .value
+        .get("s")
+        .and_then(|s| s.as_str())
+        .unwrap_or_default()
+        .to_string())
+}
 
 pub async fn locator_focus(page: &Page, selector: &str) -> playwright_rs::Result<()> {
-    let escaped = selector.replace('\\', "\\\\").replace('\'', "\\'");
-    let js = format!(
-        "() => {{ const el = document.querySelector('{}'); if (!el) throw new Error('No element found'); el.focus(); }}",
-        escaped
-    );
-    page.evaluate_value(&js).await?;
+    eval_on_selector(page, selector, "el => el.focus()").await?;
     Ok(())
 }
 
 pub async fn locator_blur(page: &Page, selector: &str) -> playwright_rs::Result<()> {
-    let escaped = selector.replace('\\', "\\\\").replace('\'', "\\'");
-    let js = format!(
-        "() => {{ const el = document.querySelector('{}'); if (!el) throw new Error('No element found'); el.blur(); }}",
-        escaped
-    );
-    page.evaluate_value(&js).await?;
+    eval_on_selector(page, selector, "el => el.blur()").await?;
     Ok(())
 }
 
 pub async fn locator_scroll_into_view(page: &Page, selector: &str) -> playwright_rs::Result<()> {
-    let escaped = selector.replace('\\', "\\\\").replace('\'', "\\'");
-    let js = format!(
-        "() => {{ const el = document.querySelector('{}'); if (!el) throw new Error('No element found'); el.scrollIntoView({{behavior: 'instant', block: 'center'}}); }}",
-        escaped
-    );
-    page.evaluate_value(&js).await?;
+    eval_on_selector(
+        page,
+        selector,
+        "el => el.scrollIntoView({behavior: 'instant', block: 'center'})",
+    )
+    .await?;
     Ok(())
 }
 
+pub async fn locator_tap(page: &Page, selector: &str, timeout: u64) -> playwright_rs::Result<()> {
+    let frame = page.main_frame().await?;
+    frame
+        .channel()
+        .send_no_result(
+            "tap",
+            serde_json::json!({
+                "selector": selector,
+                "strict": true,
+                "timeout": timeout,
+            }),
+        )
+        .await
+        .map_err(|e| match e {
+            playwright_rs::Error::ProtocolError(msg) => {
+                playwright_rs::Error::ProtocolError(format!("{} [selector: {}]", msg, selector))
+            }
+            playwright_rs::Error::Timeout(msg) => {
+                playwright_rs::Error::Timeout(format!("{} [selector: {}]", msg, selector))
+            }
+            other => other,
+        })
+}
+
 pub async fn locator_eval_on_selector(
     page: &Page,
     selector: &str,
     js: &str,
 ) -> playwright_rs::Result<String> {
-    let escaped_selector = selector.replace('\\', "\\\\").replace('\'', "\\'");
-    let wrapper = format!(
-        "() => {{ const el = document.querySelector('{}'); if (!el) throw new Error('No element found for selector: {}'); const fn_ = {}; return JSON.stringify(fn_(el)); }}",
-        escaped_selector, escaped_selector, js
-    );
-    page.evaluate_value(&wrapper).await
+    eval_on_selector(page, selector, js).await
+}
+
+/// Runs `js` with every element matching `selector` (any Playwright selector,
+/// not just CSS). `js` must return a string.
+pub async fn locator_eval_on_selector_all(
+    page: &Page,
+    selector: &str,
+    js: &str,
+) -> playwright_rs::Result<String> {
+    #[derive(Deserialize)]
+    struct EvalResponse {
+        value: serde_json::Value,
+    }
+    let frame = page.main_frame().await?;
+    let response: EvalResponse = frame
+        .channel()
+        .send(
+            "evalOnSelectorAll",
+            serde_json::json!({
+                "selector": selector,
+                "expression": js,
+                "isFunction": true,
+                "arg": { "value": { "v": "undefined" }, "handles": [] },
+            }),
+        )
+        .await?;
+    Ok(response
+        .value
+        .get("s")
+        .and_then(|s| s.as_str())
+        .unwrap_or_default()
+        .to_string())
 }
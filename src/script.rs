@@ -0,0 +1,120 @@
+use regex::Regex;
+
+/// A line of a `plwr run` script that does something.
+pub struct Step {
+    /// Line number in the script, from 1
+    pub line: usize,
+    /// The line as written, for reporting
+    pub text: String,
+    pub kind: StepKind,
+}
+
+pub enum StepKind {
+    /// A plwr command, without `plwr`
+    Command(String),
+    /// `set NAME VALUE`, or `set NAME = COMMAND` to store the command's
+    /// output
+    Set {
+        name: String,
+        value: String,
+        capture: bool,
+    },
+    /// `if [not] exists SELECTOR`: carries on with the next step if the
+    /// element exists (or doesn't, with `not`), otherwise goes to step
+    /// `otherwise`
+    IfExists {
+        args: String,
+        negate: bool,
+        otherwise: usize,
+    },
+    /// Where the `if` branch ends in an `if ... else ... end`: goes on
+    /// after the `else` branch
+    Jump(usize),
+}
+
+/// Parses a script into steps. Words are only split and variables only
+/// expanded when a step runs, as variables can change along the way.
+pub fn parse(text: &str) -> Result<Vec<Step>, String> {
+    let set_re = Regex::new(r"^set\s+([A-Za-z_][A-Za-z0-9_]*)(?:\s+(=\s)?\s*(.*))?$").unwrap();
+    let if_re = Regex::new(r"^if\s+(not\s+)?exists\s+(.+)$").unwrap();
+    let keyword_re = Regex::new(r"^(else|end)\s*(#.*)?$").unwrap();
+
+    let mut steps: Vec<Step> = Vec::new();
+    // Open `if`s: the step index of the `if` and of its `else` jump
+    let mut open: Vec<(usize, usize, Option<usize>)> = Vec::new();
+    for (i, text) in text.lines().enumerate() {
+        let line = i + 1;
+        let trimmed = text.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        let kind = if let Some(keyword) = keyword_re.captures(trimmed) {
+            let Some((if_line, if_step, else_step)) = open.pop() else {
+                return Err(format!("Line {}: '{}' without 'if'", line, &keyword[1]));
+            };
+            match (&keyword[1], else_step) {
+                ("else", Some(_)) => {
+                    return Err(format!(
+                        "Line {}: second 'else' for the 'if' on line {}",
+                        line, if_line
+                    ));
+                }
+                ("else", None) => {
+                    let jump = steps.len();
+                    set_otherwise(&mut steps[if_step], jump + 1);
+                    open.push((if_line, if_step, Some(jump)));
+                    StepKind::Jump(0)
+                }
+                (_, Some(jump)) => {
+                    steps[jump].kind = StepKind::Jump(steps.len());
+                    continue;
+                }
+                (_, None) => {
+                    let end = steps.len();
+                    set_otherwise(&mut steps[if_step], end);
+                    continue;
+                }
+            }
+        } else if let Some(set) = set_re.captures(trimmed) {
+            StepKind::Set {
+                name: set[1].to_string(),
+                value: set.get(3).map_or("", |m| m.as_str()).to_string(),
+                capture: set.get(2).is_some(),
+            }
+        } else if let Some(cond) = if_re.captures(trimmed) {
+            open.push((line, steps.len(), None));
+            StepKind::IfExists {
+                args: cond[2].to_string(),
+                negate: cond.get(1).is_some(),
+                otherwise: 0,
+            }
+        } else if trimmed == "set" || trimmed.starts_with("set ") {
+            return Err(format!(
+                "Line {}: use 'set NAME VALUE' or 'set NAME = COMMAND'",
+                line
+            ));
+        } else if trimmed == "if" || trimmed.starts_with("if ") {
+            return Err(format!(
+                "Line {}: use 'if exists SELECTOR' or 'if not exists SELECTOR'",
+                line
+            ));
+        } else {
+            StepKind::Command(trimmed.to_string())
+        };
+        steps.push(Step {
+            line,
+            text: trimmed.to_string(),
+            kind,
+        });
+    }
+    if let Some((if_line, _, _)) = open.pop() {
+        return Err(format!("Line {}: 'if' without 'end'", if_line));
+    }
+    Ok(steps)
+}
+
+fn set_otherwise(step: &mut Step, target: usize) {
+    if let StepKind::IfExists { otherwise, .. } = &mut step.kind {
+        *otherwise = target;
+    }
+}
@@ -0,0 +1,79 @@
+use std::fs::File;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{DirBuilderExt, MetadataExt, OpenOptionsExt, PermissionsExt};
+use std::path::{Path, PathBuf};
+use tokio::net::UnixStream;
+
+/// Where the sessions' sockets live: `$XDG_RUNTIME_DIR/plwr`, or
+/// `plwr-<uid>` in the temp directory where there's no runtime directory,
+/// as on macOS.
+pub fn dir() -> PathBuf {
+    match dirs::runtime_dir() {
+        Some(dir) => dir.join("plwr"),
+        None => std::env::temp_dir().join(format!("plwr-{}", uid())),
+    }
+}
+
+/// The socket directory, created if needed and only accessible to us. A
+/// directory that belongs to someone else is refused rather than used, as
+/// they could swap the sockets in it for their own.
+pub fn private_dir() -> io::Result<PathBuf> {
+    let dir = dir();
+    std::fs::DirBuilder::new()
+        .recursive(true)
+        .mode(0o700)
+        .create(&dir)?;
+    let meta = std::fs::symlink_metadata(&dir)?;
+    if !meta.is_dir() || meta.uid() != uid() {
+        return Err(io::Error::new(
+            io::ErrorKind::PermissionDenied,
+            format!("{} belongs to another user", dir.display()),
+        ));
+    }
+    if meta.mode() & 0o077 != 0 {
+        std::fs::set_permissions(&dir, std::fs::Permissions::from_mode(0o700))?;
+    }
+    Ok(dir)
+}
+
+/// Makes a socket we're listening on usable only by us.
+pub fn restrict(path: &Path) -> io::Result<()> {
+    std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o600))
+}
+
+/// Where `start` keeps the options a session was started with, so a daemon
+/// that died can be started again the same way.
+pub fn options_path(socket: &Path) -> PathBuf {
+    socket.with_extension("options.json")
+}
+
+/// Removes a session's socket and its start options.
+pub fn remove(socket: &Path) {
+    std::fs::remove_file(socket).ok();
+    std::fs::remove_file(options_path(socket)).ok();
+}
+
+/// Waits for an exclusive lock on a session, held until the returned file
+/// is dropped, so that only one client at a time starts its daemon.
+pub fn lock(socket: &Path) -> io::Result<File> {
+    let file = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .mode(0o600)
+        .open(socket.with_extension("lock"))?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(file)
+}
+
+/// Whether the process on the other end of a connection runs as our user.
+pub fn is_own_user(stream: &UnixStream) -> bool {
+    stream.peer_cred().is_ok_and(|cred| cred.uid() == uid())
+}
+
+fn uid() -> u32 {
+    unsafe { libc::geteuid() }
+}
@@ -0,0 +1,207 @@
+use crate::pw_ext;
+use playwright_rs::Page;
+use serde::Deserialize;
+
+const MAX_SUGGESTIONS: usize = 5;
+
+const PAGE_NAMES_JS: &str = r#"() => {
+    const ids = new Set(), classes = new Set();
+    for (const el of document.querySelectorAll('*')) {
+        if (el.id) ids.add(el.id);
+        el.classList.forEach(c => classes.add(c));
+    }
+    return JSON.stringify({ ids: [...ids], classes: [...classes] });
+}"#;
+
+// Innermost elements containing each needle, as selectors from `selectorFor`.
+const TEXT_MATCHES_JS: &str = r#"(needles, selectorFor) => {
+    const skip = new Set(['SCRIPT', 'STYLE', 'NOSCRIPT', 'TEMPLATE']);
+    const norm = s => (s || '').replace(/\s+/g, ' ').trim().toLowerCase();
+    const found = [];
+    for (const needle of needles.map(norm)) {
+        const has = el => !skip.has(el.tagName) && norm(el.textContent).includes(needle);
+        for (const el of document.body.querySelectorAll('*')) {
+            if (has(el) && !Array.from(el.children).some(has)) found.push(selectorFor(el));
+            if (found.length >= 3) break;
+        }
+    }
+    return JSON.stringify(found);
+}"#;
+
+#[derive(Deserialize)]
+struct PageNames {
+    ids: Vec<String>,
+    classes: Vec<String>,
+}
+
+/// "Did you mean" candidates for a selector that matched nothing: the
+/// selector with a misspelled `#id` or `.class` corrected, and selectors
+/// for elements containing any of its `:has-text()` / `text=` strings.
+/// Only candidates that match something on the page are returned.
+pub async fn for_selector(page: &Page, selector: &str, selector_for_js: &str) -> Vec<String> {
+    if page.locator(selector).await.count().await.unwrap_or(0) > 0 {
+        return Vec::new();
+    }
+
+    let mut candidates = Vec::new();
+
+    let tokens = name_tokens(selector);
+    if !tokens.is_empty() {
+        if let Some(names) = pw_ext::page_evaluate_value(page, PAGE_NAMES_JS)
+            .await
+            .ok()
+            .and_then(|v| serde_json::from_str::<PageNames>(&v).ok())
+        {
+            for token in &tokens {
+                let pool = if token.prefix == '#' {
+                    &names.ids
+                } else {
+                    &names.classes
+                };
+                if let Some(best) = closest(&token.name, pool) {
+                    let mut fixed = selector.to_string();
+                    fixed.replace_range(token.start..token.end, best);
+                    candidates.push(fixed);
+                }
+            }
+        }
+    }
+
+    let texts = text_components(selector);
+    if !texts.is_empty() {
+        let js = format!(
+            "() => ({})({}, {})",
+            TEXT_MATCHES_JS,
+            serde_json::to_string(&texts).unwrap_or_default(),
+            selector_for_js
+        );
+        if let Some(found) = pw_ext::page_evaluate_value(page, &js)
+            .await
+            .ok()
+            .and_then(|v| serde_json::from_str::<Vec<String>>(&v).ok())
+        {
+            candidates.extend(found);
+        }
+    }
+
+    let mut suggestions: Vec<String> = Vec::new();
+    for candidate in candidates {
+        if suggestions.len() >= MAX_SUGGESTIONS || suggestions.contains(&candidate) {
+            continue;
+        }
+        if page.locator(&candidate).await.count().await.unwrap_or(0) > 0 {
+            suggestions.push(candidate);
+        }
+    }
+    suggestions
+}
+
+struct NameToken {
+    prefix: char,
+    name: String,
+    start: usize,
+    end: usize,
+}
+
+/// `#id` and `.class` names in a CSS selector, skipping quoted strings,
+/// attribute brackets and non-CSS engines like `text=` or `role=`.
+fn name_tokens(selector: &str) -> Vec<NameToken> {
+    let engine = selector.split_once('=').map(|(e, _)| e).unwrap_or("");
+    if !engine.is_empty()
+        && engine != "css"
+        && engine.chars().all(|c| c.is_ascii_alphabetic() || c == ':')
+    {
+        return Vec::new();
+    }
+
+    let is_name = |c: char| c.is_alphanumeric() || c == '-' || c == '_';
+    let chars: Vec<(usize, char)> = selector.char_indices().collect();
+    let mut tokens = Vec::new();
+    let mut quote = None;
+    let mut brackets = 0;
+    let mut i = 0;
+    while i < chars.len() {
+        let (pos, c) = chars[i];
+        match (quote, c) {
+            (Some(q), _) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '[' | '(') => brackets += 1,
+            (None, ']' | ')') => brackets -= 1,
+            (None, '#' | '.') if brackets == 0 => {
+                let mut j = i + 1;
+                while j < chars.len() && is_name(chars[j].1) {
+                    j += 1;
+                }
+                let end = chars.get(j).map(|&(p, _)| p).unwrap_or(selector.len());
+                if j > i + 1 {
+                    tokens.push(NameToken {
+                        prefix: c,
+                        name: selector[pos + 1..end].to_string(),
+                        start: pos + 1,
+                        end,
+                    });
+                }
+                i = j;
+                continue;
+            }
+            _ => {}
+        }
+        i += 1;
+    }
+    tokens
+}
+
+/// Strings from `:has-text("...")` and `text=...` parts of a selector.
+fn text_components(selector: &str) -> Vec<String> {
+    let mut texts = Vec::new();
+    for part in selector.split(">>").map(str::trim) {
+        if let Some(text) = part.strip_prefix("text=") {
+            texts.push(unquote(text).to_string());
+        }
+        let mut rest = part;
+        while let Some(i) = rest.find(":has-text(") {
+            rest = &rest[i + ":has-text(".len()..];
+            if let Some(end) = rest.find(')') {
+                texts.push(unquote(&rest[..end]).to_string());
+                rest = &rest[end..];
+            }
+        }
+    }
+    texts.retain(|t| !t.is_empty());
+    texts
+}
+
+fn unquote(s: &str) -> &str {
+    let s = s.trim();
+    let s = s
+        .strip_suffix(['i', 's'])
+        .filter(|t| t.ends_with(['"', '\'']))
+        .unwrap_or(s);
+    s.trim_matches(|c| c == '"' || c == '\'')
+}
+
+/// The name in `pool` closest to `name`, if it's within a few edits.
+fn closest<'a>(name: &str, pool: &'a [String]) -> Option<&'a str> {
+    let max = (name.chars().count() / 4).clamp(1, 3);
+    pool.iter()
+        .filter(|p| p.as_str() != name)
+        .map(|p| (edit_distance(name, p), p))
+        .filter(|&(d, _)| d <= max)
+        .min_by_key(|&(d, _)| d)
+        .map(|(_, p)| p.as_str())
+}
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut cur = vec![i + 1; b.len() + 1];
+        for (j, &cb) in b.iter().enumerate() {
+            let cost = usize::from(ca != cb);
+            cur[j + 1] = (prev[j] + cost).min(prev[j + 1] + 1).min(cur[j] + 1);
+        }
+        prev = cur;
+    }
+    prev[b.len()]
+}
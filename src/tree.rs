@@ -0,0 +1,172 @@
+use serde_json::{json, Value};
+
+/// Renders a `tree` node and its children as indented
+/// `tag#id.class [attr="value"] text` lines.
+pub fn outline(node: &Value) -> String {
+    let mut out = String::new();
+    outline_into(node, 0, &mut out);
+    out.trim_end().to_string()
+}
+
+fn outline_into(node: &Value, indent: usize, out: &mut String) {
+    out.push_str(&"  ".repeat(indent));
+    out.push_str(&label(node));
+    for (name, value) in node["attrs"].as_object().into_iter().flatten() {
+        out.push_str(&format!(" [{}={}]", name, value));
+    }
+    if let Some(text) = node["text"].as_str() {
+        out.push(' ');
+        out.push_str(text);
+    }
+    out.push('\n');
+    for child in children(node) {
+        outline_into(child, indent + 1, out);
+    }
+}
+
+/// Compares two `tree` snapshots (including element rects, when present,
+/// for `snapshot diff`). Children are paired up by tag and id,
+/// preferring identical subtrees, so an inserted element shows up as one
+/// addition rather than a cascade of changes to its later siblings.
+pub fn diff(before: &Value, after: &Value) -> Value {
+    let mut changes = Changes::default();
+    if before["tag"] == after["tag"] && before["id"] == after["id"] {
+        diff_node(before, after, &label(after), &mut changes);
+    } else {
+        changes.removed.push(entry(&label(before), before));
+        changes.added.push(entry(&label(after), after));
+    }
+    json!({
+        "added": changes.added,
+        "removed": changes.removed,
+        "changed": changes.changed,
+    })
+}
+
+#[derive(Default)]
+struct Changes {
+    added: Vec<Value>,
+    removed: Vec<Value>,
+    changed: Vec<Value>,
+}
+
+fn diff_node(before: &Value, after: &Value, selector: &str, changes: &mut Changes) {
+    let mut fields = vec!["text".to_string(), "class".to_string(), "rect".to_string()];
+    for attrs in [&before["attrs"], &after["attrs"]] {
+        for name in attrs.as_object().into_iter().flatten().map(|(k, _)| k) {
+            let field = format!("attrs.{}", name);
+            if !fields.contains(&field) {
+                fields.push(field);
+            }
+        }
+    }
+    for field in fields {
+        let get = |node: &Value| match field.strip_prefix("attrs.") {
+            Some(name) => node["attrs"][name].clone(),
+            None => node[field.as_str()].clone(),
+        };
+        let (old, new) = (get(before), get(after));
+        if old != new {
+            changes.changed.push(json!({
+                "selector": selector,
+                "field": field,
+                "before": old,
+                "after": new,
+            }));
+        }
+    }
+
+    let old: Vec<&Value> = children(before).collect();
+    let new: Vec<&Value> = children(after).collect();
+    let pairs = align(&old, &new);
+    let (mut i, mut j) = (0, 0);
+    for (pi, pj) in pairs.into_iter().chain([(old.len(), new.len())]) {
+        for k in i..pi {
+            changes
+                .removed
+                .push(entry(&child_selector(selector, &old, k), old[k]));
+        }
+        for k in j..pj {
+            changes
+                .added
+                .push(entry(&child_selector(selector, &new, k), new[k]));
+        }
+        if pi < old.len() {
+            let child = child_selector(selector, &new, pj);
+            diff_node(old[pi], new[pj], &child, changes);
+        }
+        (i, j) = (pi + 1, pj + 1);
+    }
+}
+
+/// Index pairs of matching children, in order. A pair needs the same tag
+/// and id; identical subtrees count double so they win over near-misses.
+fn align(old: &[&Value], new: &[&Value]) -> Vec<(usize, usize)> {
+    let weight = |a: &Value, b: &Value| {
+        if a == b {
+            2
+        } else if a["tag"] == b["tag"] && a["id"] == b["id"] {
+            1
+        } else {
+            0
+        }
+    };
+    let (n, m) = (old.len(), new.len());
+    let mut best = vec![vec![0usize; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            let w = weight(old[i], new[j]);
+            let take = if w > 0 { best[i + 1][j + 1] + w } else { 0 };
+            best[i][j] = take.max(best[i + 1][j]).max(best[i][j + 1]);
+        }
+    }
+    let mut pairs = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n && j < m {
+        let w = weight(old[i], new[j]);
+        if w > 0 && best[i][j] == best[i + 1][j + 1] + w {
+            pairs.push((i, j));
+            i += 1;
+            j += 1;
+        } else if best[i][j] == best[i + 1][j] {
+            i += 1;
+        } else {
+            j += 1;
+        }
+    }
+    pairs
+}
+
+fn entry(selector: &str, node: &Value) -> Value {
+    json!({ "selector": selector, "node": outline(node) })
+}
+
+/// CSS selector for `siblings[index]` under `parent`, with `:nth-of-type`
+/// when the parent has several children with that tag.
+fn child_selector(parent: &str, siblings: &[&Value], index: usize) -> String {
+    let node = siblings[index];
+    let same_tag = |s: &&&Value| s["tag"] == node["tag"];
+    let mut selector = format!("{} > {}", parent, label(node));
+    if node["id"].is_null() && siblings.iter().filter(same_tag).count() > 1 {
+        let nth = siblings[..index].iter().filter(same_tag).count() + 1;
+        selector.push_str(&format!(":nth-of-type({})", nth));
+    }
+    selector
+}
+
+fn label(node: &Value) -> String {
+    let mut label = node["tag"].as_str().unwrap_or("").to_string();
+    if let Some(id) = node["id"].as_str() {
+        label.push('#');
+        label.push_str(id);
+    }
+    for class in node["class"].as_array().into_iter().flatten() {
+        label.push('.');
+        label.push_str(class.as_str().unwrap_or(""));
+    }
+    label
+}
+
+fn children(node: &Value) -> impl Iterator<Item = &Value> {
+    node["children"].as_array().into_iter().flatten()
+}
@@ -0,0 +1,99 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+/// A named device for `viewport --preset`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct Preset {
+    pub width: u32,
+    pub height: u32,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub mobile: bool,
+    #[serde(default)]
+    pub touch: bool,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+const BUILTIN: &[(&str, Preset)] = &[
+    (
+        "mobile",
+        Preset {
+            width: 390,
+            height: 844,
+            scale: 3.0,
+            mobile: true,
+            touch: true,
+        },
+    ),
+    (
+        "tablet",
+        Preset {
+            width: 820,
+            height: 1180,
+            scale: 2.0,
+            mobile: true,
+            touch: true,
+        },
+    ),
+    (
+        "desktop",
+        Preset {
+            width: 1280,
+            height: 720,
+            scale: 1.0,
+            mobile: false,
+            touch: false,
+        },
+    ),
+    (
+        "4k",
+        Preset {
+            width: 3840,
+            height: 2160,
+            scale: 1.0,
+            mobile: false,
+            touch: false,
+        },
+    ),
+];
+
+/// Where user presets are read from: `$PLWR_VIEWPORTS`, or
+/// `viewports.json` in plwr's config directory.
+pub fn config_path() -> PathBuf {
+    if let Ok(path) = std::env::var("PLWR_VIEWPORTS") {
+        return PathBuf::from(path);
+    }
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("/tmp"))
+        .join("plwr")
+        .join("viewports.json")
+}
+
+/// Looks up a preset, user presets first so they can redefine the bundled
+/// ones. A missing config file just means there are no user presets.
+pub fn preset(name: &str) -> Result<Preset, String> {
+    let path = config_path();
+    let user: HashMap<String, Preset> = match std::fs::read_to_string(&path) {
+        Ok(text) => serde_json::from_str(&text)
+            .map_err(|e| format!("Invalid viewport presets in {}: {}", path.display(), e))?,
+        Err(_) => HashMap::new(),
+    };
+    if let Some(preset) = user.get(name) {
+        return Ok(preset.clone());
+    }
+    if let Some((_, preset)) = BUILTIN.iter().find(|(n, _)| *n == name) {
+        return Ok(preset.clone());
+    }
+    let mut names: Vec<&str> = BUILTIN.iter().map(|(n, _)| *n).collect();
+    names.extend(user.keys().map(|n| n.as_str()));
+    Err(format!(
+        "Unknown viewport preset '{}'. Use one of: {}",
+        name,
+        names.join(", ")
+    ))
+}
@@ -0,0 +1,22 @@
+// Minimal stand-in for axe-core so the audit tests run offline. Reports
+// every <img> without alt text as an image-alt (wcag2a) violation.
+window.axe = {
+  run: async (context, options) => {
+    const tags = options.runOnly ? options.runOnly.values : null;
+    const images = [...document.querySelectorAll('img:not([alt])')];
+    if (!images.length || (tags && !tags.includes('wcag2a'))) return { violations: [] };
+    return {
+      violations: [{
+        id: 'image-alt',
+        impact: 'critical',
+        help: 'Images must have alternate text',
+        helpUrl: 'https://dequeuniversity.com/rules/axe/4.10/image-alt',
+        nodes: images.map(img => ({
+          target: ['#' + img.id],
+          html: img.outerHTML,
+          failureSummary: 'Element does not have an alt attribute',
+        })),
+      }],
+    };
+  },
+};
@@ -0,0 +1,7 @@
+self.addEventListener('install', () => self.skipWaiting());
+self.addEventListener('activate', event => event.waitUntil(self.clients.claim()));
+self.addEventListener('fetch', event => {
+  if (new URL(event.request.url).pathname === '/sw-test') {
+    event.respondWith(new Response('from service worker'));
+  }
+});